@@ -13,18 +13,16 @@ namespace ConsoleHarness
         { Stopwatch sw = new Stopwatch();
             sw.Start();
             Console.WriteLine("Hello from C#!");
+            RustCalls.EnsureCompatible();
             RustCalls.hello_world();
             int width = 5120, height = 5120;
-            var ptr = RustCalls.ReturnStringUTF8();
-            var str = Marshal.PtrToStringUni(ptr);
+            var str = RustCalls.ReturnStringUTF16();
             Console.WriteLine(str);
             Console.WriteLine(sw.Elapsed);
-            var imgptr = RustCalls.ReturnImageRG24BPP((uint)width, (uint)height);
+            var imgarray = RustCalls.FillImageRG24BPP((uint)width, (uint)height);
+            Console.WriteLine("Filled the image buffer");
             Console.WriteLine(sw.Elapsed);
             var bmp = new Bitmap(width, height,PixelFormat.Format24bppRgb);
-            var imgarray = new byte [width * height * 3];
-            Marshal.Copy(imgptr, imgarray, 0, (int) (width * height * 3));
-            Console.WriteLine("Copied the memory over");
             Console.WriteLine(sw.Elapsed);
             byte r, g, b;
             for (var j = 0; j < height; j++)
@@ -7,31 +7,427 @@ using System.Threading.Tasks;
 
 namespace RustflameFFI_CS
 {
+    public enum RustflameStatus
+    {
+        Ok = 0,
+        NullPointer = 1,
+        InvalidArgument = 2,
+        BufferTooSmall = 3,
+        NotFinished = 4,
+        RenderFailed = 5,
+        Panic = 6,
+        Cancelled = 7
+    }
+
+    /// <summary>
+    /// Called on the render thread. Keep the delegate referenced until the job is freed.
+    /// </summary>
+    [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+    public delegate void RustflameProgressCallback(IntPtr userData, double progress);
+
+    /// <summary>
+    /// Called on the render thread; <paramref name="pixels"/> is only valid during the call.
+    /// </summary>
+    [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+    public delegate void RustflamePreviewCallback(IntPtr userData, IntPtr pixels, UIntPtr len, UInt32 width,
+        UInt32 height);
+
+    /// <summary>
+    /// Mirrors rustflame's AffineTransform: x' = x*xx + y*yx + cx, y' = x*xy + y*yy + cy.
+    /// </summary>
+    [StructLayout(LayoutKind.Sequential)]
+    public struct AffineTransform
+    {
+        public double xx;
+        public double xy;
+        public double yx;
+        public double yy;
+        public double cx;
+        public double cy;
+    }
+
+    /// <summary>
+    /// A point in flame coordinates, with y up.
+    /// </summary>
+    [StructLayout(LayoutKind.Sequential)]
+    public struct FlamePoint
+    {
+        public double x;
+        public double y;
+    }
+
+    /// <summary>
+    /// Where an affine transform sends the origin and the two unit vectors: the triangle editors draw.
+    /// </summary>
+    [StructLayout(LayoutKind.Sequential)]
+    public struct Triangle
+    {
+        public FlamePoint o;
+        public FlamePoint x;
+        public FlamePoint y;
+    }
+
+    public enum RustflameVertex : uint
+    {
+        O = 0,
+        X = 1,
+        Y = 2,
+    }
+
+    public class RustflameException : Exception
+    {
+        public RustflameException(RustflameStatus status, string message) : base($"{status}: {message}")
+        {
+            Status = status;
+        }
+
+        public RustflameStatus Status { get; }
+    }
+
     public static class RustCalls
     {
-        [DllImport("rustflame_ffi.dll")]
+        // No extension, so the runtime picks rustflame_ffi.dll or librustflame_ffi.so for the platform.
+        private const string Lib = "rustflame_ffi";
+
+        /// <summary>
+        /// The ABI version these bindings were written against. Must match RUSTFLAME_ABI_VERSION in
+        /// rustflame-ffi/include/rustflame.h.
+        /// </summary>
+        public const uint AbiVersion = 6;
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern uint rustflame_abi_version();
 
-        public static extern void hello_world();
+        /// <summary>
+        /// Throws if the loaded library doesn't match these bindings. Call it once before anything else.
+        /// </summary>
+        public static void EnsureCompatible()
+        {
+            var version = rustflame_abi_version();
+            if (version != AbiVersion)
+                throw new InvalidOperationException(
+                    $"rustflame_ffi has ABI version {version}, these bindings expect {AbiVersion}");
+        }
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern IntPtr rustflame_last_error_message();
 
-        [DllImport("rustflame_ffi.dll",CallingConvention = CallingConvention.Cdecl)]
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus hello_world();
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
         public static unsafe extern void* return_string_utf8();
 
-        [DllImport("rustflame_ffi.dll", CallingConvention = CallingConvention.Cdecl)]
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static unsafe extern void* return_string_utf16();
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static unsafe extern RustflameStatus free_string_utf8(void* s);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static unsafe extern RustflameStatus free_string_utf16(void* s);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
         public static unsafe extern void* return_image_rg_24bpp(UInt32 width, UInt32 height);
 
-        public static IntPtr ReturnStringUTF8()
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static unsafe extern RustflameStatus free_image_rg_24bpp(void* image, UInt32 width, UInt32 height);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus fill_image_rg_24bpp(UInt32 width, UInt32 height, byte[] buffer,
+            UIntPtr capacity, out UIntPtr len);
+
+        public const uint PixelFormatRgb24 = 0;
+        public const uint PixelFormatRgba32 = 1;
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_flame_new(byte[] nameUtf8, out IntPtr flame);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_flame_free(IntPtr flame);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_flame_add_affine_transform(IntPtr flame, double xx,
+            double xy, double yx, double yy, double cx, double cy, float r, float g, float b);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_flame_set_background(IntPtr flame, float r, float g,
+            float b);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_flame_transform_count(IntPtr flame, out UIntPtr count);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_flame_remove_transform(IntPtr flame, UIntPtr index);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_flame_get_affine(IntPtr flame, UIntPtr index,
+            out AffineTransform affine);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_flame_set_affine(IntPtr flame, UIntPtr index,
+            AffineTransform affine);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_flame_set_color(IntPtr flame, UIntPtr index, float r,
+            float g, float b);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_flame_set_weight(IntPtr flame, UIntPtr index,
+            double weight);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_flame_set_variation_weight(IntPtr flame, UIntPtr index,
+            byte[] variationUtf8, double weight);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_flame_get_variation_weight(IntPtr flame, UIntPtr index,
+            byte[] variationUtf8, out double weight);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_flame_set_variation_param(IntPtr flame, UIntPtr index,
+            byte[] variationUtf8, byte[] paramUtf8, double value);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_flame_get_variation_param(IntPtr flame, UIntPtr index,
+            byte[] variationUtf8, byte[] paramUtf8, out double value);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_affine_to_triangle(AffineTransform affine,
+            out Triangle triangle);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_affine_from_triangle(Triangle triangle,
+            out AffineTransform affine);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_affine_rotate(ref AffineTransform affine, double angle,
+            FlamePoint pivot);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_affine_scale(ref AffineTransform affine, double sx,
+            double sy, FlamePoint pivot);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_affine_shear(ref AffineTransform affine, double kx,
+            double ky, FlamePoint pivot);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_affine_flip_horizontal(ref AffineTransform affine,
+            FlamePoint pivot);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_affine_flip_vertical(ref AffineTransform affine,
+            FlamePoint pivot);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_affine_translate(ref AffineTransform affine,
+            FlamePoint offset);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_affine_move_vertex(ref AffineTransform affine,
+            RustflameVertex vertex, FlamePoint to);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_history_new(IntPtr flame, out IntPtr history);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_history_free(IntPtr history);
+
+        /// <summary>
+        /// The flame is owned by the history: read it and render it, but edit it only through the history.
+        /// </summary>
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_history_flame(IntPtr history, out IntPtr flame);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_history_set_coalesce_ms(IntPtr history, UInt64 ms);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_history_seal(IntPtr history);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_history_undo(IntPtr history,
+            [MarshalAs(UnmanagedType.U1)] out bool done);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_history_redo(IntPtr history,
+            [MarshalAs(UnmanagedType.U1)] out bool done);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_history_state(IntPtr history,
+            [MarshalAs(UnmanagedType.U1)] out bool canUndo, [MarshalAs(UnmanagedType.U1)] out bool canRedo);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_history_add_affine_transform(IntPtr history,
+            AffineTransform affine, float r, float g, float b);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_history_remove_transform(IntPtr history, UIntPtr index);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_history_set_background(IntPtr history, float r, float g,
+            float b);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_history_set_affine(IntPtr history, UIntPtr index,
+            AffineTransform affine);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_history_set_color(IntPtr history, UIntPtr index, float r,
+            float g, float b);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_history_set_weight(IntPtr history, UIntPtr index,
+            double weight);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_history_set_variation_weight(IntPtr history,
+            UIntPtr index, byte[] variationUtf8, double weight);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_history_set_variation_param(IntPtr history,
+            UIntPtr index, byte[] variationUtf8, byte[] paramUtf8, double value);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_variation_count(out UIntPtr count);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_variation_name(UIntPtr index, byte[] buffer,
+            UIntPtr capacity, out UIntPtr len);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_variation_param_count(byte[] variationUtf8,
+            out UIntPtr count);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_variation_param_name(byte[] variationUtf8, UIntPtr index,
+            byte[] buffer, UIntPtr capacity, out UIntPtr len);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_renderer_new(IntPtr flame, UInt32 width, UInt32 height,
+            double gamma, double vibrancy, out IntPtr renderer);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_renderer_free(IntPtr renderer);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_renderer_set_iterations(IntPtr renderer,
+            UInt64 iterations);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_renderer_set_time_limit_ms(IntPtr renderer, UInt64 ms);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_renderer_set_seed(IntPtr renderer, UInt32 s0, UInt32 s1,
+            UInt32 s2, UInt32 s3);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_render_start(IntPtr renderer, UInt32 pixelFormat,
+            out IntPtr job);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_render_start_with_callbacks(IntPtr renderer,
+            UInt32 pixelFormat, RustflameProgressCallback progress, RustflamePreviewCallback preview,
+            UInt32 previewFrames, IntPtr userData, out IntPtr job);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_render_cancel(IntPtr job);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_render_poll(IntPtr job, out double progress);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_render_wait(IntPtr job);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_render_pixels_len(IntPtr job, out UIntPtr len);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_render_copy_pixels(IntPtr job, byte[] buffer,
+            UIntPtr capacity, out UIntPtr len);
+
+        /// <summary>
+        /// Renders a small preview synchronously. Pass 0 for budgetMs to use the default budget.
+        /// </summary>
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_thumbnail_render(IntPtr flame, uint width, uint height,
+            UInt64 budgetMs, uint pixelFormat, byte[] buffer, UIntPtr capacity, out UIntPtr len);
+
+        [DllImport(Lib, CallingConvention = CallingConvention.Cdecl)]
+        public static extern RustflameStatus rustflame_render_free(IntPtr job);
+
+        /// <summary>
+        /// Throws a <see cref="RustflameException"/> carrying the last error message if a call failed.
+        /// </summary>
+        public static void Check(RustflameStatus status)
         {
-            IntPtr ret;
-            unsafe { ret = new IntPtr(return_string_utf8());}
-            return ret;
+            if (status == RustflameStatus.Ok)
+                return;
+            var message = Marshal.PtrToStringAnsi(rustflame_last_error_message()) ?? "no details";
+            throw new RustflameException(status, message);
         }
 
-        public static IntPtr ReturnImageRG24BPP(uint width, uint height)
+        public static IntPtr FlameNew(string name)
         {
-            IntPtr ret;
-            unsafe { ret = new IntPtr(return_image_rg_24bpp(width,height));}
+            IntPtr flame;
+            Check(rustflame_flame_new(Utf8(name), out flame));
+            return flame;
+        }
 
-            return ret;
+        /// <summary>
+        /// Encodes a string argument as NUL-terminated UTF-8.
+        /// </summary>
+        public static byte[] Utf8(string s)
+        {
+            return Encoding.UTF8.GetBytes(s + "\0");
+        }
+
+        /// <summary>
+        /// Lists every registered variation along with its parameter names.
+        /// </summary>
+        public static Dictionary<string, List<string>> Variations()
+        {
+            var variations = new Dictionary<string, List<string>>();
+            UIntPtr count;
+            Check(rustflame_variation_count(out count));
+            var buffer = new byte[256];
+            for (ulong i = 0; i < count.ToUInt64(); i++)
+            {
+                UIntPtr len;
+                Check(rustflame_variation_name(new UIntPtr(i), buffer, new UIntPtr((ulong) buffer.Length), out len));
+                var name = Encoding.UTF8.GetString(buffer, 0, (int) len.ToUInt64());
+                UIntPtr paramCount;
+                Check(rustflame_variation_param_count(Utf8(name), out paramCount));
+                var parameters = new List<string>();
+                for (ulong j = 0; j < paramCount.ToUInt64(); j++)
+                {
+                    Check(rustflame_variation_param_name(Utf8(name), new UIntPtr(j), buffer,
+                        new UIntPtr((ulong) buffer.Length), out len));
+                    parameters.Add(Encoding.UTF8.GetString(buffer, 0, (int) len.ToUInt64()));
+                }
+                variations[name] = parameters;
+            }
+            return variations;
+        }
+
+        public static string ReturnStringUTF16()
+        {
+            unsafe
+            {
+                var ptr = return_string_utf16();
+                var ret = Marshal.PtrToStringUni(new IntPtr(ptr));
+                Check(free_string_utf16(ptr));
+                return ret;
+            }
+        }
+
+        public static byte[] FillImageRG24BPP(uint width, uint height)
+        {
+            var buffer = new byte[width * height * 3];
+            UIntPtr len;
+            Check(fill_image_rg_24bpp(width, height, buffer, new UIntPtr((ulong) buffer.Length), out len));
+            return buffer;
         }
 }
-}
+}
\ No newline at end of file
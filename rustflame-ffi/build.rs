@@ -0,0 +1,28 @@
+extern crate cbindgen;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+//Generates the C header from the exported functions into OUT_DIR on every build, where the tests
+//check the checked-in include/rustflame.h against it. Building with RUSTFLAME_UPDATE_HEADER set
+//copies it over the checked-in one, which hosts that don't build the crate use.
+fn main() {
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let generated = PathBuf::from(env::var("OUT_DIR").unwrap()).join("rustflame.h");
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml"))
+        .expect("cbindgen.toml is invalid");
+    cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("unable to generate the C header")
+        .write_to_file(&generated);
+    if env::var_os("RUSTFLAME_UPDATE_HEADER").is_some() {
+        fs::copy(&generated, crate_dir.join("include").join("rustflame.h"))
+            .expect("unable to update include/rustflame.h");
+    }
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-env-changed=RUSTFLAME_UPDATE_HEADER");
+}
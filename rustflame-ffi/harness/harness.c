@@ -0,0 +1,152 @@
+/*
+ * Loads the rustflame-ffi shared library with dlopen and drives a small render through it, the
+ * same way a foreign host would. Run it through run.sh, which builds the library first.
+ */
+#include <dlfcn.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "../include/rustflame.h"
+
+#define WIDTH 64
+#define HEIGHT 48
+
+/* Function pointer types for the symbols looked up below, matching the declarations in
+ * rustflame.h. */
+typedef uint32_t (*abi_version_fn)(void);
+typedef RustflameStatus (*flame_new_fn)(const char *name, Flame **out);
+typedef RustflameStatus (*flame_free_fn)(Flame *flame);
+typedef RustflameStatus (*add_affine_fn)(Flame *flame, double xx, double xy, double yx, double yy,
+                                         double cx, double cy, float r, float g, float b);
+typedef RustflameStatus (*renderer_new_fn)(const Flame *flame, uint32_t width, uint32_t height,
+                                           double gamma, double vibrancy, Renderer **out);
+typedef RustflameStatus (*renderer_free_fn)(Renderer *renderer);
+typedef RustflameStatus (*set_iterations_fn)(Renderer *renderer, uint64_t iterations);
+typedef RustflameStatus (*render_start_fn)(const Renderer *renderer, uint32_t pixel_format,
+                                           RenderJob **out);
+typedef RustflameStatus (*render_start_callbacks_fn)(const Renderer *renderer,
+                                                     uint32_t pixel_format,
+                                                     RustflameProgressCallback progress,
+                                                     RustflamePreviewCallback preview,
+                                                     uint32_t preview_frames, void *user_data,
+                                                     RenderJob **out);
+typedef RustflameStatus (*render_wait_fn)(RenderJob *job);
+typedef RustflameStatus (*render_free_fn)(RenderJob *job);
+typedef RustflameStatus (*pixels_len_fn)(const RenderJob *job, size_t *len);
+typedef RustflameStatus (*copy_pixels_fn)(const RenderJob *job, uint8_t *buffer, size_t capacity,
+                                          size_t *len);
+typedef char *(*string_fn)(void);
+typedef RustflameStatus (*string_free_fn)(char *s);
+typedef const char *(*last_error_fn)(void);
+
+static void *lib;
+
+/* Called on the render thread. */
+static void on_progress(void *user_data, double progress) {
+    *(double *)user_data = progress;
+}
+
+static void *symbol(const char *name) {
+    void *sym = dlsym(lib, name);
+    if (sym == NULL) {
+        fprintf(stderr, "missing symbol %s: %s\n", name, dlerror());
+        exit(1);
+    }
+    return sym;
+}
+
+#define CHECK(cond)                                                                                \
+    do {                                                                                           \
+        if (!(cond)) {                                                                             \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);               \
+            exit(1);                                                                               \
+        }                                                                                          \
+    } while (0)
+
+int main(int argc, char **argv) {
+    if (argc != 2) {
+        fprintf(stderr, "usage: %s path/to/librustflame_ffi.so\n", argv[0]);
+        return 2;
+    }
+    lib = dlopen(argv[1], RTLD_NOW | RTLD_LOCAL);
+    if (lib == NULL) {
+        fprintf(stderr, "dlopen failed: %s\n", dlerror());
+        return 1;
+    }
+
+    abi_version_fn abi_version = (abi_version_fn)symbol("rustflame_abi_version");
+    if (abi_version() != RUSTFLAME_ABI_VERSION) {
+        fprintf(stderr, "library ABI version %u, harness built for %u\n", (unsigned)abi_version(),
+                (unsigned)RUSTFLAME_ABI_VERSION);
+        return 1;
+    }
+
+    last_error_fn last_error = (last_error_fn)symbol("rustflame_last_error_message");
+    string_fn return_string_utf8 = (string_fn)symbol("return_string_utf8");
+    string_free_fn free_string_utf8 = (string_free_fn)symbol("free_string_utf8");
+    flame_new_fn flame_new = (flame_new_fn)symbol("rustflame_flame_new");
+    flame_free_fn flame_free = (flame_free_fn)symbol("rustflame_flame_free");
+    add_affine_fn add_affine = (add_affine_fn)symbol("rustflame_flame_add_affine_transform");
+    renderer_new_fn renderer_new = (renderer_new_fn)symbol("rustflame_renderer_new");
+    renderer_free_fn renderer_free = (renderer_free_fn)symbol("rustflame_renderer_free");
+    set_iterations_fn set_iterations =
+        (set_iterations_fn)symbol("rustflame_renderer_set_iterations");
+    render_start_fn render_start = (render_start_fn)symbol("rustflame_render_start");
+    render_start_callbacks_fn render_start_callbacks =
+        (render_start_callbacks_fn)symbol("rustflame_render_start_with_callbacks");
+    render_wait_fn render_wait = (render_wait_fn)symbol("rustflame_render_wait");
+    pixels_len_fn pixels_len = (pixels_len_fn)symbol("rustflame_render_pixels_len");
+    copy_pixels_fn copy_pixels = (copy_pixels_fn)symbol("rustflame_render_copy_pixels");
+    render_free_fn render_free = (render_free_fn)symbol("rustflame_render_free");
+
+    char *greeting = return_string_utf8();
+    printf("%s\n", greeting);
+    free_string_utf8(greeting);
+
+    Flame *flame = NULL;
+    CHECK(flame_new("Harness flame", &flame) == RUSTFLAME_STATUS_OK);
+    CHECK(add_affine(flame, 0.5, 0.0, 0.0, 0.5, 0.0, 0.0, 1.0f, 0.0f, 0.0f) == RUSTFLAME_STATUS_OK);
+    CHECK(add_affine(flame, 0.5, 0.0, 0.0, 0.5, 0.5, 0.0, 0.0f, 1.0f, 0.0f) == RUSTFLAME_STATUS_OK);
+    CHECK(add_affine(flame, 0.5, 0.0, 0.0, 0.5, 0.0, 0.5, 0.0f, 0.0f, 1.0f) == RUSTFLAME_STATUS_OK);
+    CHECK(add_affine(flame, 0.5, 0.0, 0.0, 0.5, 0.0, 0.5, 2.0f, 0.0f, 1.0f) != RUSTFLAME_STATUS_OK);
+    printf("Expected error: %s\n", last_error());
+
+    Renderer *renderer = NULL;
+    CHECK(renderer_new(flame, WIDTH, HEIGHT, 2.2, 0.75, &renderer) == RUSTFLAME_STATUS_OK);
+    CHECK(set_iterations(renderer, 100000) == RUSTFLAME_STATUS_OK);
+
+    RenderJob *job = NULL;
+    CHECK(render_start(renderer, 7, &job) == RUSTFLAME_STATUS_INVALID_ARGUMENT);
+    double progress = 0.0;
+    CHECK(render_start_callbacks(renderer, PIXEL_FORMAT_RGB24, on_progress, NULL, 0, &progress,
+                                 &job) == RUSTFLAME_STATUS_OK);
+    CHECK(render_wait(job) == RUSTFLAME_STATUS_OK);
+    CHECK(progress == 1.0);
+    size_t len = 0;
+    CHECK(pixels_len(job, &len) == RUSTFLAME_STATUS_OK);
+    CHECK(len == WIDTH * HEIGHT * 3);
+    uint8_t *pixels = malloc(len);
+    size_t copied = 0;
+    CHECK(copy_pixels(job, pixels, 1, &copied) == RUSTFLAME_STATUS_BUFFER_TOO_SMALL);
+    CHECK(copied == len);
+    CHECK(copy_pixels(job, pixels, len, &copied) == RUSTFLAME_STATUS_OK);
+
+    size_t lit = 0;
+    for (size_t i = 0; i < len; i += 3) {
+        if (pixels[i] || pixels[i + 1] || pixels[i + 2]) {
+            lit++;
+        }
+    }
+    CHECK(lit > 0);
+    printf("Rendered %dx%d, %zu pixels lit\n", WIDTH, HEIGHT, lit);
+
+    free(pixels);
+    render_free(job);
+    renderer_free(renderer);
+    flame_free(flame);
+    dlclose(lib);
+    return 0;
+}
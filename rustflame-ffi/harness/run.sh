@@ -0,0 +1,13 @@
+#!/bin/sh
+# Builds rustflame-ffi and runs the C harness against the resulting shared library.
+set -e
+cd "$(dirname "$0")"
+cargo build -p rustflame-ffi
+target_dir="$(cd ../.. && pwd)/target/debug"
+case "$(uname -s)" in
+    Darwin) lib="$target_dir/librustflame_ffi.dylib" ;;
+    *) lib="$target_dir/librustflame_ffi.so" ;;
+esac
+out="$target_dir/rustflame_ffi_harness"
+${CC:-cc} -std=c99 -Wall -Wextra -o "$out" harness.c -ldl
+"$out" "$lib"
@@ -0,0 +1,823 @@
+/* Generated by cbindgen from rustflame-ffi. Do not edit by hand. */
+
+#ifndef RUSTFLAME_H
+#define RUSTFLAME_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Bumped whenever an exported function, struct or enum changes in a way existing callers would
+ * notice. `include/rustflame.h` carries the same number.
+ */
+#define RUSTFLAME_ABI_VERSION 6
+
+/**
+ * Pixel layouts a finished render can be copied out as.
+ */
+#define PIXEL_FORMAT_RGB24 0
+
+#define PIXEL_FORMAT_RGBA32 1
+
+/**
+ * Status codes returned by every `rustflame_*` call. Anything other than `Ok` leaves a description
+ * in `rustflame_last_error_message`.
+ */
+typedef enum RustflameStatus {
+  RUSTFLAME_STATUS_OK = 0,
+  RUSTFLAME_STATUS_NULL_POINTER = 1,
+  RUSTFLAME_STATUS_INVALID_ARGUMENT = 2,
+  /**
+   * The caller's buffer is too small; the required length has been written to the out-param.
+   */
+  RUSTFLAME_STATUS_BUFFER_TOO_SMALL = 3,
+  RUSTFLAME_STATUS_NOT_FINISHED = 4,
+  RUSTFLAME_STATUS_RENDER_FAILED = 5,
+  /**
+   * Rust panicked. The call was abandoned, and the handles it was given may be left part-way
+   * through an edit, but they are still safe to free.
+   */
+  RUSTFLAME_STATUS_PANIC = 6,
+  /**
+   * The render was cancelled before it finished.
+   */
+  RUSTFLAME_STATUS_CANCELLED = 7,
+} RustflameStatus;
+
+/**
+ * Which corner `rustflame_affine_move_vertex` moves.
+ */
+enum RustflameVertex
+#ifdef __cplusplus
+  : uint32_t
+#endif // __cplusplus
+ {
+  RUSTFLAME_VERTEX_O = 0,
+  RUSTFLAME_VERTEX_X = 1,
+  RUSTFLAME_VERTEX_Y = 2,
+};
+#ifndef __cplusplus
+typedef uint32_t RustflameVertex;
+#endif // __cplusplus
+
+/**
+ * Owns a flame and records every edit made through it, so edits can be undone and redone.
+ *
+ * Edits to the same value that arrive within the coalescing window of each other, such as the
+ * stream of edits from dragging a slider, are folded into one undo step. `seal` ends a run early,
+ * for example when the slider is released.
+ */
+typedef struct EditHistory EditHistory;
+
+typedef struct Flame Flame;
+
+/**
+ * A render running on its own thread, and its pixels once it has finished.
+ */
+typedef struct RenderJob RenderJob;
+
+typedef struct Renderer Renderer;
+
+typedef struct AffineTransform {
+  double xx;
+  double xy;
+  double yx;
+  double yy;
+  double cx;
+  double cy;
+} AffineTransform;
+
+typedef struct Point {
+  double x;
+  double y;
+} Point;
+
+/**
+ * The three points of an affine transform's triangle, in flame coordinates with y up. Editors
+ * that draw y down should flip the points on the way in and out.
+ */
+typedef struct Triangle {
+  struct Point o;
+  struct Point x;
+  struct Point y;
+} Triangle;
+
+/**
+ * Called from the render thread with the fraction of the render done, from 0.0 to 1.0.
+ */
+typedef void (*RustflameProgressCallback)(void *user_data, double progress);
+
+/**
+ * Called from the render thread with a preview of the image so far, in the job's pixel format.
+ * `pixels` is only valid for the duration of the call.
+ */
+typedef void (*RustflamePreviewCallback)(void *user_data,
+                                         const uint8_t *pixels,
+                                         size_t len,
+                                         uint32_t width,
+                                         uint32_t height);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Hosts should compare this against the version they were built for before calling anything
+ * else, rather than finding a signature mismatch by crashing.
+ */
+uint32_t rustflame_abi_version(void);
+
+enum RustflameStatus hello_world(void);
+
+/**
+ * Returns a NUL-terminated UTF-8 string. Release it with `free_string_utf8`.
+ */
+char *return_string_utf8(void);
+
+/**
+ * # Safety
+ *
+ * `s` must be null or a string from `return_string_utf8` that hasn't been freed yet.
+ */
+enum RustflameStatus free_string_utf8(char *s);
+
+/**
+ * Returns a NUL-terminated UTF-16 string, in native byte order. Release it with
+ * `free_string_utf16`.
+ */
+uint16_t *return_string_utf16(void);
+
+/**
+ * # Safety
+ *
+ * `s` must be null or a string from `return_string_utf16` that hasn't been freed yet.
+ */
+enum RustflameStatus free_string_utf16(uint16_t *s);
+
+/**
+ * Copies the demo string, UTF-8 without a terminator, into a caller-allocated buffer. `len`
+ * receives the string's length whether or not it fit.
+ *
+ * # Safety
+ *
+ * `buffer` must be writable for `capacity` bytes and `len` writable.
+ */
+enum RustflameStatus copy_string_utf8(uint8_t *buffer, size_t capacity, size_t *len);
+
+/**
+ * Returns a red/green gradient, or null if the size overflows. Release it with
+ * `free_image_rg_24bpp`, passing the same width and height.
+ */
+uint8_t *return_image_rg_24bpp(uint32_t width, uint32_t height);
+
+/**
+ * # Safety
+ *
+ * `image` must be null or an image from `return_image_rg_24bpp` of the same size that hasn't
+ * been freed yet.
+ */
+enum RustflameStatus free_image_rg_24bpp(uint8_t *image, uint32_t width, uint32_t height);
+
+/**
+ * Writes the red/green gradient into a caller-allocated buffer of at least
+ * `width * height * 3` bytes, rows top to bottom. `len` receives the required length.
+ *
+ * # Safety
+ *
+ * `buffer` must be writable for `capacity` bytes and `len` writable.
+ */
+enum RustflameStatus fill_image_rg_24bpp(uint32_t width,
+                                         uint32_t height,
+                                         uint8_t *buffer,
+                                         size_t capacity,
+                                         size_t *len);
+
+/**
+ * Writes the points the transform sends the origin and the unit vectors to. Points are in flame
+ * coordinates, with y up.
+ *
+ * # Safety
+ *
+ * `triangle` must be writable.
+ */
+enum RustflameStatus rustflame_affine_to_triangle(struct AffineTransform affine,
+                                                  struct Triangle *triangle);
+
+/**
+ * Writes the transform whose triangle is `triangle`.
+ *
+ * # Safety
+ *
+ * `affine` must be writable.
+ */
+enum RustflameStatus rustflame_affine_from_triangle(struct Triangle triangle,
+                                                    struct AffineTransform *affine);
+
+/**
+ * Rotates the triangle by `angle` radians, counterclockwise, around `pivot`.
+ *
+ * # Safety
+ *
+ * `affine` must be valid for reads and writes.
+ */
+enum RustflameStatus rustflame_affine_rotate(struct AffineTransform *affine,
+                                             double angle,
+                                             struct Point pivot);
+
+/**
+ * # Safety
+ *
+ * `affine` must be valid for reads and writes.
+ */
+enum RustflameStatus rustflame_affine_scale(struct AffineTransform *affine,
+                                            double sx,
+                                            double sy,
+                                            struct Point pivot);
+
+/**
+ * # Safety
+ *
+ * `affine` must be valid for reads and writes.
+ */
+enum RustflameStatus rustflame_affine_shear(struct AffineTransform *affine,
+                                            double kx,
+                                            double ky,
+                                            struct Point pivot);
+
+/**
+ * Mirrors the triangle across the vertical line through `pivot`.
+ *
+ * # Safety
+ *
+ * `affine` must be valid for reads and writes.
+ */
+enum RustflameStatus rustflame_affine_flip_horizontal(struct AffineTransform *affine,
+                                                      struct Point pivot);
+
+/**
+ * Mirrors the triangle across the horizontal line through `pivot`.
+ *
+ * # Safety
+ *
+ * `affine` must be valid for reads and writes.
+ */
+enum RustflameStatus rustflame_affine_flip_vertical(struct AffineTransform *affine,
+                                                    struct Point pivot);
+
+/**
+ * # Safety
+ *
+ * `affine` must be valid for reads and writes.
+ */
+enum RustflameStatus rustflame_affine_translate(struct AffineTransform *affine,
+                                                struct Point offset);
+
+/**
+ * Moves one corner of the triangle to `to`, for dragging a handle. `vertex` is a
+ * `RUSTFLAME_VERTEX_*` value.
+ *
+ * # Safety
+ *
+ * `affine` must be valid for reads and writes.
+ */
+enum RustflameStatus rustflame_affine_move_vertex(struct AffineTransform *affine,
+                                                  uint32_t vertex,
+                                                  struct Point to);
+
+/**
+ * Describes the most recent failed call made on this thread, as a NUL-terminated UTF-8 string, or
+ * returns null if no call has failed yet. The pointer stays valid until the next failing call on
+ * this thread.
+ */
+const char *rustflame_last_error_message(void);
+
+/**
+ * Creates an empty flame. `name` is a NUL-terminated UTF-8 string. Free the flame with
+ * `rustflame_flame_free`.
+ *
+ * # Safety
+ *
+ * `name` must be a NUL-terminated string and `out` writable.
+ */
+enum RustflameStatus rustflame_flame_new(const char *name, struct Flame **out);
+
+/**
+ * # Safety
+ *
+ * `flame` must be a live flame, not used again after this call.
+ */
+enum RustflameStatus rustflame_flame_free(struct Flame *flame);
+
+/**
+ * Adds a purely affine transform with the given color.
+ *
+ * # Safety
+ *
+ * `flame` must be a live flame.
+ */
+enum RustflameStatus rustflame_flame_add_affine_transform(struct Flame *flame,
+                                                          double xx,
+                                                          double xy,
+                                                          double yx,
+                                                          double yy,
+                                                          double cx,
+                                                          double cy,
+                                                          float r,
+                                                          float g,
+                                                          float b);
+
+/**
+ * # Safety
+ *
+ * `flame` must be a live flame.
+ */
+enum RustflameStatus rustflame_flame_set_background(struct Flame *flame, float r, float g, float b);
+
+/**
+ * Writes the number of transforms in the flame to `count`.
+ *
+ * # Safety
+ *
+ * `flame` must be a live flame and `count` writable.
+ */
+enum RustflameStatus rustflame_flame_transform_count(const struct Flame *flame, size_t *count);
+
+/**
+ * Removes a transform. Transforms after it move down one index.
+ *
+ * # Safety
+ *
+ * `flame` must be a live flame.
+ */
+enum RustflameStatus rustflame_flame_remove_transform(struct Flame *flame, size_t index);
+
+/**
+ * Writes a transform's affine coefficients to `affine`.
+ *
+ * # Safety
+ *
+ * `flame` must be a live flame and `affine` writable.
+ */
+enum RustflameStatus rustflame_flame_get_affine(const struct Flame *flame,
+                                                size_t index,
+                                                struct AffineTransform *affine);
+
+/**
+ * # Safety
+ *
+ * `flame` must be a live flame.
+ */
+enum RustflameStatus rustflame_flame_set_affine(struct Flame *flame,
+                                                size_t index,
+                                                struct AffineTransform affine);
+
+/**
+ * # Safety
+ *
+ * `flame` must be a live flame.
+ */
+enum RustflameStatus rustflame_flame_set_color(struct Flame *flame,
+                                               size_t index,
+                                               float r,
+                                               float g,
+                                               float b);
+
+/**
+ * Sets how often a transform is picked, relative to the others.
+ *
+ * # Safety
+ *
+ * `flame` must be a live flame.
+ */
+enum RustflameStatus rustflame_flame_set_weight(struct Flame *flame, size_t index, double weight);
+
+/**
+ * Sets the weight of a variation on a transform, adding the variation if the transform doesn't
+ * use it yet. A weight of zero removes it. A purely affine transform starts out as `linear` at
+ * weight 1.
+ *
+ * # Safety
+ *
+ * `flame` must be a live flame and `variation` a NUL-terminated string.
+ */
+enum RustflameStatus rustflame_flame_set_variation_weight(struct Flame *flame,
+                                                          size_t index,
+                                                          const char *variation,
+                                                          double weight);
+
+/**
+ * Writes the weight of a variation on a transform to `weight`, which is zero if the transform
+ * doesn't use it.
+ *
+ * # Safety
+ *
+ * `flame` must be a live flame, `variation` a NUL-terminated string and `weight` writable.
+ */
+enum RustflameStatus rustflame_flame_get_variation_weight(const struct Flame *flame,
+                                                          size_t index,
+                                                          const char *variation,
+                                                          double *weight);
+
+/**
+ * Sets a parameter of a variation the transform uses.
+ *
+ * # Safety
+ *
+ * `flame` must be a live flame, and `variation` and `param` NUL-terminated strings.
+ */
+enum RustflameStatus rustflame_flame_set_variation_param(struct Flame *flame,
+                                                         size_t index,
+                                                         const char *variation,
+                                                         const char *param,
+                                                         double value);
+
+/**
+ * Writes a parameter of a variation the transform uses to `value`.
+ *
+ * # Safety
+ *
+ * `flame` must be a live flame, `variation` and `param` NUL-terminated strings and `value`
+ * writable.
+ */
+enum RustflameStatus rustflame_flame_get_variation_param(const struct Flame *flame,
+                                                         size_t index,
+                                                         const char *variation,
+                                                         const char *param,
+                                                         double *value);
+
+/**
+ * Starts an edit history on a copy of `flame`. Edits made through the history can be undone and
+ * redone. Free it with `rustflame_history_free`.
+ *
+ * # Safety
+ *
+ * `flame` must be a live flame and `out` writable.
+ */
+enum RustflameStatus rustflame_history_new(const struct Flame *flame, struct EditHistory **out);
+
+/**
+ * # Safety
+ *
+ * `history` must be a live history, not used again after this call.
+ */
+enum RustflameStatus rustflame_history_free(struct EditHistory *history);
+
+/**
+ * Writes the history's current flame to `out`. The flame belongs to the history: it can be read
+ * with the `rustflame_flame_*` getters and passed to `rustflame_renderer_new`, but must only be
+ * edited through the history, and not freed. It stays valid until the history is freed.
+ *
+ * # Safety
+ *
+ * `history` must be a live history and `out` writable.
+ */
+enum RustflameStatus rustflame_history_flame(const struct EditHistory *history,
+                                             const struct Flame **out);
+
+/**
+ * Edits to the same value less than `ms` apart are undone as one step. The default is 500.
+ *
+ * # Safety
+ *
+ * `history` must be a live history.
+ */
+enum RustflameStatus rustflame_history_set_coalesce_ms(struct EditHistory *history, uint64_t ms);
+
+/**
+ * Ends a run of coalesced edits, for example when a slider is released.
+ *
+ * # Safety
+ *
+ * `history` must be a live history.
+ */
+enum RustflameStatus rustflame_history_seal(struct EditHistory *history);
+
+/**
+ * Undoes the last step, writing whether there was one to `done`.
+ *
+ * # Safety
+ *
+ * `history` must be a live history and `done` null or writable.
+ */
+enum RustflameStatus rustflame_history_undo(struct EditHistory *history, bool *done);
+
+/**
+ * Redoes the last undone step, writing whether there was one to `done`.
+ *
+ * # Safety
+ *
+ * `history` must be a live history and `done` null or writable.
+ */
+enum RustflameStatus rustflame_history_redo(struct EditHistory *history, bool *done);
+
+/**
+ * Writes whether there is anything to undo and redo, for enabling menu items.
+ *
+ * # Safety
+ *
+ * `history` must be a live history, and `can_undo` and `can_redo` writable.
+ */
+enum RustflameStatus rustflame_history_state(const struct EditHistory *history,
+                                             bool *can_undo,
+                                             bool *can_redo);
+
+/**
+ * As `rustflame_flame_add_affine_transform`, recording the edit.
+ *
+ * # Safety
+ *
+ * `history` must be a live history.
+ */
+enum RustflameStatus rustflame_history_add_affine_transform(struct EditHistory *history,
+                                                            struct AffineTransform affine,
+                                                            float r,
+                                                            float g,
+                                                            float b);
+
+/**
+ * # Safety
+ *
+ * `history` must be a live history.
+ */
+enum RustflameStatus rustflame_history_remove_transform(struct EditHistory *history, size_t index);
+
+/**
+ * # Safety
+ *
+ * `history` must be a live history.
+ */
+enum RustflameStatus rustflame_history_set_background(struct EditHistory *history,
+                                                      float r,
+                                                      float g,
+                                                      float b);
+
+/**
+ * # Safety
+ *
+ * `history` must be a live history.
+ */
+enum RustflameStatus rustflame_history_set_affine(struct EditHistory *history,
+                                                  size_t index,
+                                                  struct AffineTransform affine);
+
+/**
+ * # Safety
+ *
+ * `history` must be a live history.
+ */
+enum RustflameStatus rustflame_history_set_color(struct EditHistory *history,
+                                                 size_t index,
+                                                 float r,
+                                                 float g,
+                                                 float b);
+
+/**
+ * # Safety
+ *
+ * `history` must be a live history.
+ */
+enum RustflameStatus rustflame_history_set_weight(struct EditHistory *history,
+                                                  size_t index,
+                                                  double weight);
+
+/**
+ * # Safety
+ *
+ * `history` must be a live history and `variation` a NUL-terminated string.
+ */
+enum RustflameStatus rustflame_history_set_variation_weight(struct EditHistory *history,
+                                                            size_t index,
+                                                            const char *variation,
+                                                            double weight);
+
+/**
+ * # Safety
+ *
+ * `history` must be a live history, and `variation` and `param` NUL-terminated strings.
+ */
+enum RustflameStatus rustflame_history_set_variation_param(struct EditHistory *history,
+                                                           size_t index,
+                                                           const char *variation,
+                                                           const char *param,
+                                                           double value);
+
+/**
+ * Creates a renderer for a copy of `flame`, so the flame can keep being edited.
+ * Free the renderer with `rustflame_renderer_free`.
+ *
+ * # Safety
+ *
+ * `flame` must be a live flame and `out` writable.
+ */
+enum RustflameStatus rustflame_renderer_new(const struct Flame *flame,
+                                            uint32_t width,
+                                            uint32_t height,
+                                            double gamma,
+                                            double vibrancy,
+                                            struct Renderer **out);
+
+/**
+ * # Safety
+ *
+ * `renderer` must be a live renderer, not used again after this call.
+ */
+enum RustflameStatus rustflame_renderer_free(struct Renderer *renderer);
+
+/**
+ * Stops renders after a fixed number of iterations.
+ *
+ * # Safety
+ *
+ * `renderer` must be a live renderer.
+ */
+enum RustflameStatus rustflame_renderer_set_iterations(struct Renderer *renderer,
+                                                       uint64_t iterations);
+
+/**
+ * Stops renders after a fixed amount of time.
+ *
+ * # Safety
+ *
+ * `renderer` must be a live renderer.
+ */
+enum RustflameStatus rustflame_renderer_set_time_limit_ms(struct Renderer *renderer, uint64_t ms);
+
+/**
+ * # Safety
+ *
+ * `renderer` must be a live renderer.
+ */
+enum RustflameStatus rustflame_renderer_set_seed(struct Renderer *renderer,
+                                                 uint32_t s0,
+                                                 uint32_t s1,
+                                                 uint32_t s2,
+                                                 uint32_t s3);
+
+/**
+ * Starts rendering a copy of `renderer` on a background thread, in one of the `PIXEL_FORMAT_*`
+ * layouts. Free the job with `rustflame_render_free`.
+ *
+ * # Safety
+ *
+ * `renderer` must be a live renderer and `out` writable.
+ */
+enum RustflameStatus rustflame_render_start(const struct Renderer *renderer,
+                                            uint32_t pixel_format,
+                                            struct RenderJob **out);
+
+/**
+ * Starts a render like `rustflame_render_start`, calling `progress` as it goes and `preview` with
+ * `preview_frames` images of the render so far. Either callback may be null. Both are called on
+ * the render thread with `user_data`, so a UI host must marshal back to its own thread.
+ *
+ * # Safety
+ *
+ * `renderer` must be a live renderer and `out` writable.
+ */
+enum RustflameStatus rustflame_render_start_with_callbacks(const struct Renderer *renderer,
+                                                           uint32_t pixel_format,
+                                                           RustflameProgressCallback progress,
+                                                           RustflamePreviewCallback preview,
+                                                           uint32_t preview_frames,
+                                                           void *user_data,
+                                                           struct RenderJob **out);
+
+/**
+ * Asks a render to stop. It stops soon after, without blocking the caller, and then waiting on
+ * it reports `Cancelled`. Cancelling a finished render does nothing.
+ *
+ * # Safety
+ *
+ * `job` must be a live render job.
+ */
+enum RustflameStatus rustflame_render_cancel(const struct RenderJob *job);
+
+/**
+ * Checks on a render without blocking, writing the fraction done to `progress` if it isn't null.
+ * Returns `NotFinished` while the render is running, and otherwise what `rustflame_render_wait`
+ * would.
+ *
+ * # Safety
+ *
+ * `job` must be a live render job and `progress` null or writable.
+ */
+enum RustflameStatus rustflame_render_poll(struct RenderJob *job, double *progress);
+
+/**
+ * Blocks until the render has finished. A render that panicked reports `RenderFailed`, and one
+ * that was cancelled reports `Cancelled`.
+ *
+ * # Safety
+ *
+ * `job` must be a live render job.
+ */
+enum RustflameStatus rustflame_render_wait(struct RenderJob *job);
+
+/**
+ * Writes the size in bytes of the finished image to `len`.
+ *
+ * # Safety
+ *
+ * `job` must be a live render job and `len` writable.
+ */
+enum RustflameStatus rustflame_render_pixels_len(const struct RenderJob *job, size_t *len);
+
+/**
+ * Copies the finished image, rows top to bottom, into a caller-allocated buffer. `len` receives
+ * the image's size in bytes once the render has finished.
+ *
+ * # Safety
+ *
+ * `job` must be a live render job, `buffer` writable for `capacity` bytes and `len` writable.
+ */
+enum RustflameStatus rustflame_render_copy_pixels(const struct RenderJob *job,
+                                                  uint8_t *buffer,
+                                                  size_t capacity,
+                                                  size_t *len);
+
+/**
+ * Frees a render job. A render that is still running is cancelled and left to wind down in the
+ * background.
+ *
+ * # Safety
+ *
+ * `job` must be a live render job, not used again after this call.
+ */
+enum RustflameStatus rustflame_render_free(struct RenderJob *job);
+
+/**
+ * Renders a quick, rough thumbnail of `flame` on the calling thread, into a caller-allocated
+ * buffer in one of the `PIXEL_FORMAT_*` layouts, rows top to bottom. Iteration stops after
+ * `budget_ms`, or after a few thousand iterations per pixel if that comes first; pass 0 for the
+ * default budget. `len` receives the required length.
+ *
+ * Calls only read the flame, so hosts filling a grid of thumbnails can make them from as many
+ * threads as they like, as long as nothing edits the flames meanwhile.
+ *
+ * # Safety
+ *
+ * `flame` must be a live flame, `buffer` writable for `capacity` bytes and `len` writable.
+ */
+enum RustflameStatus rustflame_thumbnail_render(const struct Flame *flame,
+                                                uint32_t width,
+                                                uint32_t height,
+                                                uint64_t budget_ms,
+                                                uint32_t pixel_format,
+                                                uint8_t *buffer,
+                                                size_t capacity,
+                                                size_t *len);
+
+/**
+ * Writes the number of registered variations to `count`.
+ *
+ * # Safety
+ *
+ * `count` must be writable.
+ */
+enum RustflameStatus rustflame_variation_count(size_t *count);
+
+/**
+ * Copies the name of registered variation `index`, UTF-8 without a terminator, into a
+ * caller-allocated buffer. `len` receives the name's length whether or not it fit.
+ *
+ * # Safety
+ *
+ * `buffer` must be writable for `capacity` bytes and `len` writable.
+ */
+enum RustflameStatus rustflame_variation_name(size_t index,
+                                              uint8_t *buffer,
+                                              size_t capacity,
+                                              size_t *len);
+
+/**
+ * Writes the number of parameters the named variation takes to `count`.
+ *
+ * # Safety
+ *
+ * `variation` must be a NUL-terminated string and `count` writable.
+ */
+enum RustflameStatus rustflame_variation_param_count(const char *variation, size_t *count);
+
+/**
+ * Copies the name of parameter `index` of the named variation into a caller-allocated buffer, as
+ * `rustflame_variation_name` does.
+ *
+ * # Safety
+ *
+ * `variation` must be a NUL-terminated string, `buffer` writable for `capacity` bytes and `len`
+ * writable.
+ */
+enum RustflameStatus rustflame_variation_param_name(const char *variation,
+                                                    size_t index,
+                                                    uint8_t *buffer,
+                                                    size_t capacity,
+                                                    size_t *len);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* RUSTFLAME_H */
@@ -0,0 +1,185 @@
+use error::*;
+use rustflame::flame_2d::AffineTransform;
+use rustflame::flame_2d::Point;
+use rustflame::flame_2d::Triangle;
+use rustflame::flame_2d::Vertex;
+
+//Triangle-handle math for editors. These work on caller-owned values rather than handles: read a
+//transform with `rustflame_flame_get_affine`, change it here, and write it back with
+//`rustflame_flame_set_affine` or `rustflame_history_set_affine`.
+
+/// Writes the points the transform sends the origin and the unit vectors to. Points are in flame
+/// coordinates, with y up.
+///
+/// # Safety
+///
+/// `triangle` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_affine_to_triangle(
+    affine: AffineTransform,
+    triangle: *mut Triangle,
+) -> RustflameStatus {
+    guard(|| unsafe { write_out(triangle, affine.triangle(), "triangle") })
+}
+
+/// Writes the transform whose triangle is `triangle`.
+///
+/// # Safety
+///
+/// `affine` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_affine_from_triangle(
+    triangle: Triangle,
+    affine: *mut AffineTransform,
+) -> RustflameStatus {
+    guard(|| {
+        for (point, what) in &[(triangle.o, "o"), (triangle.x, "x"), (triangle.y, "y")] {
+            point_arg(*point, what)?;
+        }
+        unsafe { write_out(affine, AffineTransform::from_triangle(&triangle), "affine") }
+    })
+}
+
+/// Rotates the triangle by `angle` radians, counterclockwise, around `pivot`.
+///
+/// # Safety
+///
+/// `affine` must be valid for reads and writes.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_affine_rotate(
+    affine: *mut AffineTransform,
+    angle: f64,
+    pivot: Point,
+) -> RustflameStatus {
+    guard(|| {
+        finite_arg(angle, "angle")?;
+        point_arg(pivot, "pivot")?;
+        unsafe { handle_mut(affine, "affine") }?.rotate(angle, pivot);
+        Ok(())
+    })
+}
+
+/// # Safety
+///
+/// `affine` must be valid for reads and writes.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_affine_scale(
+    affine: *mut AffineTransform,
+    sx: f64,
+    sy: f64,
+    pivot: Point,
+) -> RustflameStatus {
+    guard(|| {
+        finite_arg(sx, "sx")?;
+        finite_arg(sy, "sy")?;
+        point_arg(pivot, "pivot")?;
+        unsafe { handle_mut(affine, "affine") }?.scale(sx, sy, pivot);
+        Ok(())
+    })
+}
+
+/// # Safety
+///
+/// `affine` must be valid for reads and writes.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_affine_shear(
+    affine: *mut AffineTransform,
+    kx: f64,
+    ky: f64,
+    pivot: Point,
+) -> RustflameStatus {
+    guard(|| {
+        finite_arg(kx, "kx")?;
+        finite_arg(ky, "ky")?;
+        point_arg(pivot, "pivot")?;
+        unsafe { handle_mut(affine, "affine") }?.shear(kx, ky, pivot);
+        Ok(())
+    })
+}
+
+/// Mirrors the triangle across the vertical line through `pivot`.
+///
+/// # Safety
+///
+/// `affine` must be valid for reads and writes.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_affine_flip_horizontal(
+    affine: *mut AffineTransform,
+    pivot: Point,
+) -> RustflameStatus {
+    guard(|| {
+        point_arg(pivot, "pivot")?;
+        unsafe { handle_mut(affine, "affine") }?.flip_horizontal(pivot);
+        Ok(())
+    })
+}
+
+/// Mirrors the triangle across the horizontal line through `pivot`.
+///
+/// # Safety
+///
+/// `affine` must be valid for reads and writes.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_affine_flip_vertical(
+    affine: *mut AffineTransform,
+    pivot: Point,
+) -> RustflameStatus {
+    guard(|| {
+        point_arg(pivot, "pivot")?;
+        unsafe { handle_mut(affine, "affine") }?.flip_vertical(pivot);
+        Ok(())
+    })
+}
+
+/// # Safety
+///
+/// `affine` must be valid for reads and writes.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_affine_translate(
+    affine: *mut AffineTransform,
+    offset: Point,
+) -> RustflameStatus {
+    guard(|| {
+        point_arg(offset, "offset")?;
+        unsafe { handle_mut(affine, "affine") }?.translate(offset);
+        Ok(())
+    })
+}
+
+/// Which corner `rustflame_affine_move_vertex` moves.
+#[repr(u32)]
+pub enum RustflameVertex {
+    O = 0,
+    X = 1,
+    Y = 2,
+}
+
+/// Moves one corner of the triangle to `to`, for dragging a handle. `vertex` is a
+/// `RUSTFLAME_VERTEX_*` value.
+///
+/// # Safety
+///
+/// `affine` must be valid for reads and writes.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_affine_move_vertex(
+    affine: *mut AffineTransform,
+    vertex: u32,
+    to: Point,
+) -> RustflameStatus {
+    guard(|| {
+        let vertex = match vertex {
+            v if v == RustflameVertex::O as u32 => Vertex::O,
+            v if v == RustflameVertex::X as u32 => Vertex::X,
+            v if v == RustflameVertex::Y as u32 => Vertex::Y,
+            _ => return Err(FfiError::invalid(format!("{} is not a vertex", vertex))),
+        };
+        point_arg(to, "to")?;
+        unsafe { handle_mut(affine, "affine") }?.move_vertex(vertex, to);
+        Ok(())
+    })
+}
+
+fn point_arg(point: Point, what: &str) -> FfiResult<()> {
+    finite_arg(point.x, what)?;
+    finite_arg(point.y, what)
+}
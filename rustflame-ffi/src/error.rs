@@ -0,0 +1,241 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::ptr;
+use std::slice;
+
+/// Status codes returned by every `rustflame_*` call. Anything other than `Ok` leaves a description
+/// in `rustflame_last_error_message`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RustflameStatus {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidArgument = 2,
+    /// The caller's buffer is too small; the required length has been written to the out-param.
+    BufferTooSmall = 3,
+    NotFinished = 4,
+    RenderFailed = 5,
+    /// Rust panicked. The call was abandoned, and the handles it was given may be left part-way
+    /// through an edit, but they are still safe to free.
+    Panic = 6,
+    /// The render was cancelled before it finished.
+    Cancelled = 7,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Describes the most recent failed call made on this thread, as a NUL-terminated UTF-8 string, or
+/// returns null if no call has failed yet. The pointer stays valid until the next failing call on
+/// this thread.
+#[no_mangle]
+pub extern "C" fn rustflame_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| match *last.borrow() {
+        Some(ref message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// A failed call: the status to hand back and a message for `rustflame_last_error_message`.
+#[derive(Debug)]
+pub struct FfiError {
+    pub status: RustflameStatus,
+    pub message: String,
+}
+
+impl FfiError {
+    pub fn new<S: Into<String>>(status: RustflameStatus, message: S) -> FfiError {
+        FfiError {
+            status,
+            message: message.into(),
+        }
+    }
+    pub fn invalid<S: Into<String>>(message: S) -> FfiError {
+        FfiError::new(RustflameStatus::InvalidArgument, message)
+    }
+}
+
+fn null_pointer(what: &str) -> FfiError {
+    FfiError::new(RustflameStatus::NullPointer, format!("{} is null", what))
+}
+
+pub type FfiResult<T> = Result<T, FfiError>;
+
+/// Runs the body of an exported function, turning errors and panics into a status code and
+/// recording their message. Panics must never unwind into the host.
+pub fn guard<F: FnOnce() -> FfiResult<()>>(body: F) -> RustflameStatus {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(())) => RustflameStatus::Ok,
+        Ok(Err(e)) => {
+            set_last_error(e.message);
+            e.status
+        }
+        Err(payload) => {
+            set_last_error(format!("rustflame panicked: {}", panic_message(&*payload)));
+            RustflameStatus::Panic
+        }
+    }
+}
+
+/// Like `guard`, for the functions that hand back a bare pointer. Failures return null.
+pub fn guard_ptr<T, F: FnOnce() -> FfiResult<*mut T>>(body: F) -> *mut T {
+    let mut ret = ptr::null_mut();
+    guard(|| {
+        ret = body()?;
+        Ok(())
+    });
+    ret
+}
+
+pub fn set_last_error(message: String) {
+    //Interior NULs would truncate the message on the C side anyway.
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+pub fn panic_message(payload: &(Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        String::from(*s)
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        String::from("unknown panic payload")
+    }
+}
+
+//The helpers below take pointers straight from the host. Null is checked for; anything else has
+//to be taken on trust, which is what their `unsafe` is for.
+
+/// Borrows the object behind a handle, failing on null.
+///
+/// # Safety
+///
+/// `handle` must be null or point to a live `T`, not being mutated for the borrow's lifetime.
+pub unsafe fn handle_ref<'a, T>(handle: *const T, what: &str) -> FfiResult<&'a T> {
+    handle.as_ref().ok_or_else(|| null_pointer(what))
+}
+
+/// Borrows the object behind a handle mutably, failing on null.
+///
+/// # Safety
+///
+/// `handle` must be null or point to a live `T`, used by nothing else for the borrow's lifetime.
+pub unsafe fn handle_mut<'a, T>(handle: *mut T, what: &str) -> FfiResult<&'a mut T> {
+    handle.as_mut().ok_or_else(|| null_pointer(what))
+}
+
+/// Writes a result through an out-param, failing on null.
+///
+/// # Safety
+///
+/// `out` must be null or valid for writing a `T`.
+pub unsafe fn write_out<T>(out: *mut T, value: T, what: &str) -> FfiResult<()> {
+    if out.is_null() {
+        return Err(null_pointer(what));
+    }
+    ptr::write(out, value);
+    Ok(())
+}
+
+/// Hands a boxed object to the caller as an opaque handle.
+///
+/// # Safety
+///
+/// `out` must be null or valid for writing a pointer.
+pub unsafe fn export_handle<T>(out: *mut *mut T, value: T, what: &str) -> FfiResult<()> {
+    if out.is_null() {
+        return Err(null_pointer(what));
+    }
+    *out = Box::into_raw(Box::new(value));
+    Ok(())
+}
+
+/// Takes back and drops a handle created by `export_handle`. Null is ignored.
+///
+/// # Safety
+///
+/// `handle` must be null or a handle from `export_handle` for a `T` that hasn't been freed, and
+/// mustn't be used again.
+pub unsafe fn free_handle<T>(handle: *mut T) -> RustflameStatus {
+    guard(|| {
+        if !handle.is_null() {
+            drop(Box::from_raw(handle));
+        }
+        Ok(())
+    })
+}
+
+/// Copies `data` into a caller-allocated buffer, reporting the length needed through `len`.
+///
+/// # Safety
+///
+/// As for `out_buffer`.
+pub unsafe fn copy_out(
+    data: &[u8],
+    buffer: *mut u8,
+    capacity: usize,
+    len: *mut usize,
+) -> FfiResult<()> {
+    out_buffer(buffer, capacity, data.len(), len)?.copy_from_slice(data);
+    Ok(())
+}
+
+/// Checks a caller-allocated buffer can hold `required` bytes, reporting that length through
+/// `len`, and returns the part of it to fill.
+///
+/// # Safety
+///
+/// `buffer` must be null or valid for writing `capacity` bytes, used by nothing else for the
+/// slice's lifetime, and `len` must be null or valid for writing a `usize`.
+pub unsafe fn out_buffer<'a>(
+    buffer: *mut u8,
+    capacity: usize,
+    required: usize,
+    len: *mut usize,
+) -> FfiResult<&'a mut [u8]> {
+    if !len.is_null() {
+        *len = required;
+    }
+    if buffer.is_null() {
+        return Err(null_pointer("buffer"));
+    }
+    if capacity < required {
+        return Err(FfiError::new(
+            RustflameStatus::BufferTooSmall,
+            format!("buffer holds {} bytes, {} are needed", capacity, required),
+        ));
+    }
+    Ok(slice::from_raw_parts_mut(buffer, required))
+}
+
+/// Reads a NUL-terminated UTF-8 string argument.
+///
+/// # Safety
+///
+/// `s` must be null or point to a NUL-terminated string that outlives the borrow.
+pub unsafe fn str_arg<'a>(s: *const c_char, what: &str) -> FfiResult<&'a str> {
+    if s.is_null() {
+        return Err(null_pointer(what));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| FfiError::invalid(format!("{} is not valid UTF-8", what)))
+}
+
+/// Rejects NaN and infinite number arguments.
+pub fn finite_arg(value: f64, what: &str) -> FfiResult<()> {
+    if value.is_finite() {
+        Ok(())
+    } else {
+        Err(FfiError::invalid(format!(
+            "{} {} is not finite",
+            what, value
+        )))
+    }
+}
@@ -0,0 +1,461 @@
+use error::*;
+use rustflame::color::ColorFRGB;
+use rustflame::flame_2d::AffineTransform;
+use rustflame::flame_2d::Flame;
+use rustflame::flame_2d::Transform;
+use rustflame::history::Edit;
+use rustflame::history::EditError;
+use rustflame::history::EditHistory;
+use rustflame::variations::VariationError;
+use std::convert::TryFrom;
+use std::os::raw::c_char;
+
+/// Creates an empty flame. `name` is a NUL-terminated UTF-8 string. Free the flame with
+/// `rustflame_flame_free`.
+///
+/// # Safety
+///
+/// `name` must be a NUL-terminated string and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_flame_new(
+    name: *const c_char,
+    out: *mut *mut Flame,
+) -> RustflameStatus {
+    guard(|| {
+        let name = unsafe { str_arg(name, "name") }?;
+        unsafe { export_handle(out, Flame::new(String::from(name)), "out") }
+    })
+}
+
+/// # Safety
+///
+/// `flame` must be a live flame, not used again after this call.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_flame_free(flame: *mut Flame) -> RustflameStatus {
+    unsafe { free_handle(flame) }
+}
+
+/// Adds a purely affine transform with the given color.
+///
+/// # Safety
+///
+/// `flame` must be a live flame.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_flame_add_affine_transform(
+    flame: *mut Flame,
+    xx: f64,
+    xy: f64,
+    yx: f64,
+    yy: f64,
+    cx: f64,
+    cy: f64,
+    r: f32,
+    g: f32,
+    b: f32,
+) -> RustflameStatus {
+    let affine = AffineTransform {
+        xx,
+        xy,
+        yx,
+        yy,
+        cx,
+        cy,
+    };
+    unsafe { add_affine_transform(flame, affine, r, g, b) }
+}
+
+/// # Safety
+///
+/// `flame` must be a live flame.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_flame_set_background(
+    flame: *mut Flame,
+    r: f32,
+    g: f32,
+    b: f32,
+) -> RustflameStatus {
+    unsafe { set_background(flame, r, g, b) }
+}
+
+/// Writes the number of transforms in the flame to `count`.
+///
+/// # Safety
+///
+/// `flame` must be a live flame and `count` writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_flame_transform_count(
+    flame: *const Flame,
+    count: *mut usize,
+) -> RustflameStatus {
+    guard(|| {
+        let flame = unsafe { handle_ref(flame, "flame") }?;
+        unsafe { write_out(count, flame.transforms.len(), "count") }
+    })
+}
+
+/// Removes a transform. Transforms after it move down one index.
+///
+/// # Safety
+///
+/// `flame` must be a live flame.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_flame_remove_transform(
+    flame: *mut Flame,
+    index: usize,
+) -> RustflameStatus {
+    unsafe { remove_transform(flame, index) }
+}
+
+/// Writes a transform's affine coefficients to `affine`.
+///
+/// # Safety
+///
+/// `flame` must be a live flame and `affine` writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_flame_get_affine(
+    flame: *const Flame,
+    index: usize,
+    affine: *mut AffineTransform,
+) -> RustflameStatus {
+    guard(|| {
+        let coefficients = unsafe { transform_ref(flame, index) }?
+            .affine()
+            .ok_or_else(|| edit_error(EditError::NoAffine(index)))?;
+        unsafe { write_out(affine, *coefficients, "affine") }
+    })
+}
+
+/// # Safety
+///
+/// `flame` must be a live flame.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_flame_set_affine(
+    flame: *mut Flame,
+    index: usize,
+    affine: AffineTransform,
+) -> RustflameStatus {
+    unsafe { set_affine(flame, index, affine) }
+}
+
+/// # Safety
+///
+/// `flame` must be a live flame.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_flame_set_color(
+    flame: *mut Flame,
+    index: usize,
+    r: f32,
+    g: f32,
+    b: f32,
+) -> RustflameStatus {
+    unsafe { set_color(flame, index, r, g, b) }
+}
+
+/// Sets how often a transform is picked, relative to the others.
+///
+/// # Safety
+///
+/// `flame` must be a live flame.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_flame_set_weight(
+    flame: *mut Flame,
+    index: usize,
+    weight: f64,
+) -> RustflameStatus {
+    unsafe { set_weight(flame, index, weight) }
+}
+
+/// Sets the weight of a variation on a transform, adding the variation if the transform doesn't
+/// use it yet. A weight of zero removes it. A purely affine transform starts out as `linear` at
+/// weight 1.
+///
+/// # Safety
+///
+/// `flame` must be a live flame and `variation` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_flame_set_variation_weight(
+    flame: *mut Flame,
+    index: usize,
+    variation: *const c_char,
+    weight: f64,
+) -> RustflameStatus {
+    unsafe { set_variation_weight(flame, index, variation, weight) }
+}
+
+/// Writes the weight of a variation on a transform to `weight`, which is zero if the transform
+/// doesn't use it.
+///
+/// # Safety
+///
+/// `flame` must be a live flame, `variation` a NUL-terminated string and `weight` writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_flame_get_variation_weight(
+    flame: *const Flame,
+    index: usize,
+    variation: *const c_char,
+    weight: *mut f64,
+) -> RustflameStatus {
+    guard(|| {
+        let variation = unsafe { str_arg(variation, "variation") }?;
+        let value = unsafe { transform_ref(flame, index) }?.variation_weight(variation);
+        unsafe { write_out(weight, value, "weight") }
+    })
+}
+
+/// Sets a parameter of a variation the transform uses.
+///
+/// # Safety
+///
+/// `flame` must be a live flame, and `variation` and `param` NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_flame_set_variation_param(
+    flame: *mut Flame,
+    index: usize,
+    variation: *const c_char,
+    param: *const c_char,
+    value: f64,
+) -> RustflameStatus {
+    unsafe { set_variation_param(flame, index, variation, param, value) }
+}
+
+/// Writes a parameter of a variation the transform uses to `value`.
+///
+/// # Safety
+///
+/// `flame` must be a live flame, `variation` and `param` NUL-terminated strings and `value`
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_flame_get_variation_param(
+    flame: *const Flame,
+    index: usize,
+    variation: *const c_char,
+    param: *const c_char,
+    value: *mut f64,
+) -> RustflameStatus {
+    guard(|| {
+        let variation = unsafe { str_arg(variation, "variation") }?;
+        let param = unsafe { str_arg(param, "param") }?;
+        let transform = unsafe { transform_ref(flame, index) }?;
+        let variations = transform.variations();
+        let function = variations
+            .iter()
+            .find(|(_, f)| f.name() == variation)
+            .ok_or_else(|| variation_error(VariationError::NotPresent(String::from(variation))))?
+            .1;
+        let found = function.param(param).ok_or_else(|| {
+            variation_error(VariationError::UnknownParam {
+                variation: String::from(variation),
+                param: String::from(param),
+            })
+        })?;
+        unsafe { write_out(value, found, "value") }
+    })
+}
+
+/// Something the editing calls can change: a bare flame, or an edit history that records each
+/// change so it can be undone.
+pub trait EditTarget {
+    /// How the handle is named in error messages.
+    const HANDLE: &'static str;
+    fn flame(&self) -> &Flame;
+    fn edit(&mut self, edit: Edit) -> Result<(), EditError>;
+}
+
+impl EditTarget for Flame {
+    const HANDLE: &'static str = "flame";
+    fn flame(&self) -> &Flame {
+        self
+    }
+    fn edit(&mut self, edit: Edit) -> Result<(), EditError> {
+        edit.apply(self).map(|_| ())
+    }
+}
+
+impl EditTarget for EditHistory {
+    const HANDLE: &'static str = "history";
+    fn flame(&self) -> &Flame {
+        EditHistory::flame(self)
+    }
+    fn edit(&mut self, edit: Edit) -> Result<(), EditError> {
+        self.apply(edit)
+    }
+}
+
+//The bodies of the editing calls, shared by the flame and history versions.
+
+/// # Safety
+///
+/// `target` must be a live flame or history.
+pub unsafe fn add_affine_transform<T: EditTarget>(
+    target: *mut T,
+    affine: AffineTransform,
+    r: f32,
+    g: f32,
+    b: f32,
+) -> RustflameStatus {
+    guard(|| {
+        let target = unsafe { handle_mut(target, T::HANDLE) }?;
+        let edit = Edit::InsertTransform {
+            index: target.flame().transforms.len(),
+            transform: Transform::AffineOnly(affine),
+            color: color_arg(r, g, b)?,
+            weight: 1.0,
+        };
+        target.edit(edit).map_err(edit_error)
+    })
+}
+
+/// # Safety
+///
+/// `target` must be a live flame or history.
+pub unsafe fn set_background<T: EditTarget>(
+    target: *mut T,
+    r: f32,
+    g: f32,
+    b: f32,
+) -> RustflameStatus {
+    guard(|| {
+        let target = unsafe { handle_mut(target, T::HANDLE) }?;
+        let edit = Edit::SetBackground(color_arg(r, g, b)?);
+        target.edit(edit).map_err(edit_error)
+    })
+}
+
+/// # Safety
+///
+/// `target` must be a live flame or history.
+pub unsafe fn remove_transform<T: EditTarget>(target: *mut T, index: usize) -> RustflameStatus {
+    guard(|| unsafe { apply_edit(target, Edit::RemoveTransform { index }) })
+}
+
+/// # Safety
+///
+/// `target` must be a live flame or history.
+pub unsafe fn set_affine<T: EditTarget>(
+    target: *mut T,
+    index: usize,
+    affine: AffineTransform,
+) -> RustflameStatus {
+    guard(|| unsafe { apply_edit(target, Edit::SetAffine { index, affine }) })
+}
+
+/// # Safety
+///
+/// `target` must be a live flame or history.
+pub unsafe fn set_color<T: EditTarget>(
+    target: *mut T,
+    index: usize,
+    r: f32,
+    g: f32,
+    b: f32,
+) -> RustflameStatus {
+    guard(|| {
+        let color = color_arg(r, g, b)?;
+        unsafe { apply_edit(target, Edit::SetColor { index, color }) }
+    })
+}
+
+/// # Safety
+///
+/// `target` must be a live flame or history.
+pub unsafe fn set_weight<T: EditTarget>(
+    target: *mut T,
+    index: usize,
+    weight: f64,
+) -> RustflameStatus {
+    guard(|| {
+        if !weight.is_finite() || weight < 0.0 {
+            return Err(FfiError::invalid(format!(
+                "weight {} must be zero or more",
+                weight
+            )));
+        }
+        unsafe { apply_edit(target, Edit::SetWeight { index, weight }) }
+    })
+}
+
+/// # Safety
+///
+/// `target` must be a live flame or history, and the strings NUL-terminated.
+pub unsafe fn set_variation_weight<T: EditTarget>(
+    target: *mut T,
+    index: usize,
+    variation: *const c_char,
+    weight: f64,
+) -> RustflameStatus {
+    guard(|| {
+        let variation = String::from(unsafe { str_arg(variation, "variation") }?);
+        finite_arg(weight, "weight")?;
+        unsafe {
+            apply_edit(
+                target,
+                Edit::SetVariationWeight {
+                    index,
+                    variation,
+                    weight,
+                },
+            )
+        }
+    })
+}
+
+/// # Safety
+///
+/// `target` must be a live flame or history, and the strings NUL-terminated.
+pub unsafe fn set_variation_param<T: EditTarget>(
+    target: *mut T,
+    index: usize,
+    variation: *const c_char,
+    param: *const c_char,
+    value: f64,
+) -> RustflameStatus {
+    guard(|| {
+        let variation = String::from(unsafe { str_arg(variation, "variation") }?);
+        let param = String::from(unsafe { str_arg(param, "param") }?);
+        finite_arg(value, "value")?;
+        unsafe {
+            apply_edit(
+                target,
+                Edit::SetVariationParam {
+                    index,
+                    variation,
+                    param,
+                    value,
+                },
+            )
+        }
+    })
+}
+
+unsafe fn apply_edit<T: EditTarget>(target: *mut T, edit: Edit) -> FfiResult<()> {
+    unsafe { handle_mut(target, T::HANDLE) }?
+        .edit(edit)
+        .map_err(edit_error)
+}
+
+unsafe fn transform_ref<'a>(flame: *const Flame, index: usize) -> FfiResult<&'a Transform> {
+    let flame = unsafe { handle_ref(flame, "flame") }?;
+    flame.transforms.get(index).ok_or_else(|| {
+        edit_error(EditError::NoTransform {
+            index,
+            count: flame.transforms.len(),
+        })
+    })
+}
+
+fn edit_error(e: EditError) -> FfiError {
+    FfiError::invalid(e.to_string())
+}
+
+fn variation_error(e: VariationError) -> FfiError {
+    FfiError::invalid(e.to_string())
+}
+
+pub fn color_arg(r: f32, g: f32, b: f32) -> FfiResult<ColorFRGB> {
+    ColorFRGB::try_from((r, g, b)).map_err(|e| {
+        FfiError::invalid(format!(
+            "color ({}, {}, {}) has a channel outside 0.0-1.0: {}",
+            r, g, b, e
+        ))
+    })
+}
@@ -0,0 +1,241 @@
+use error::*;
+use flame;
+use rustflame::flame_2d::AffineTransform;
+use rustflame::flame_2d::Flame;
+use rustflame::history::EditHistory;
+use std::os::raw::c_char;
+use std::time::Duration;
+
+/// Starts an edit history on a copy of `flame`. Edits made through the history can be undone and
+/// redone. Free it with `rustflame_history_free`.
+///
+/// # Safety
+///
+/// `flame` must be a live flame and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_history_new(
+    flame: *const Flame,
+    out: *mut *mut EditHistory,
+) -> RustflameStatus {
+    guard(|| {
+        let flame = unsafe { handle_ref(flame, "flame") }?;
+        unsafe { export_handle(out, EditHistory::new(flame.clone()), "out") }
+    })
+}
+
+/// # Safety
+///
+/// `history` must be a live history, not used again after this call.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_history_free(history: *mut EditHistory) -> RustflameStatus {
+    unsafe { free_handle(history) }
+}
+
+/// Writes the history's current flame to `out`. The flame belongs to the history: it can be read
+/// with the `rustflame_flame_*` getters and passed to `rustflame_renderer_new`, but must only be
+/// edited through the history, and not freed. It stays valid until the history is freed.
+///
+/// # Safety
+///
+/// `history` must be a live history and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_history_flame(
+    history: *const EditHistory,
+    out: *mut *const Flame,
+) -> RustflameStatus {
+    guard(|| {
+        let history = unsafe { handle_ref(history, "history") }?;
+        unsafe { write_out(out, history.flame() as *const Flame, "out") }
+    })
+}
+
+/// Edits to the same value less than `ms` apart are undone as one step. The default is 500.
+///
+/// # Safety
+///
+/// `history` must be a live history.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_history_set_coalesce_ms(
+    history: *mut EditHistory,
+    ms: u64,
+) -> RustflameStatus {
+    guard(|| {
+        let history = unsafe { handle_mut(history, "history") }?;
+        history.set_coalesce_window(Duration::from_millis(ms));
+        Ok(())
+    })
+}
+
+/// Ends a run of coalesced edits, for example when a slider is released.
+///
+/// # Safety
+///
+/// `history` must be a live history.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_history_seal(history: *mut EditHistory) -> RustflameStatus {
+    guard(|| {
+        unsafe { handle_mut(history, "history") }?.seal();
+        Ok(())
+    })
+}
+
+/// Undoes the last step, writing whether there was one to `done`.
+///
+/// # Safety
+///
+/// `history` must be a live history and `done` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_history_undo(
+    history: *mut EditHistory,
+    done: *mut bool,
+) -> RustflameStatus {
+    guard(|| {
+        let undone = unsafe { handle_mut(history, "history") }?.undo();
+        if !done.is_null() {
+            unsafe { *done = undone };
+        }
+        Ok(())
+    })
+}
+
+/// Redoes the last undone step, writing whether there was one to `done`.
+///
+/// # Safety
+///
+/// `history` must be a live history and `done` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_history_redo(
+    history: *mut EditHistory,
+    done: *mut bool,
+) -> RustflameStatus {
+    guard(|| {
+        let redone = unsafe { handle_mut(history, "history") }?.redo();
+        if !done.is_null() {
+            unsafe { *done = redone };
+        }
+        Ok(())
+    })
+}
+
+/// Writes whether there is anything to undo and redo, for enabling menu items.
+///
+/// # Safety
+///
+/// `history` must be a live history, and `can_undo` and `can_redo` writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_history_state(
+    history: *const EditHistory,
+    can_undo: *mut bool,
+    can_redo: *mut bool,
+) -> RustflameStatus {
+    guard(|| {
+        let history = unsafe { handle_ref(history, "history") }?;
+        unsafe { write_out(can_undo, history.can_undo(), "can_undo") }?;
+        unsafe { write_out(can_redo, history.can_redo(), "can_redo") }
+    })
+}
+
+/// As `rustflame_flame_add_affine_transform`, recording the edit.
+///
+/// # Safety
+///
+/// `history` must be a live history.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_history_add_affine_transform(
+    history: *mut EditHistory,
+    affine: AffineTransform,
+    r: f32,
+    g: f32,
+    b: f32,
+) -> RustflameStatus {
+    unsafe { flame::add_affine_transform(history, affine, r, g, b) }
+}
+
+/// # Safety
+///
+/// `history` must be a live history.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_history_remove_transform(
+    history: *mut EditHistory,
+    index: usize,
+) -> RustflameStatus {
+    unsafe { flame::remove_transform(history, index) }
+}
+
+/// # Safety
+///
+/// `history` must be a live history.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_history_set_background(
+    history: *mut EditHistory,
+    r: f32,
+    g: f32,
+    b: f32,
+) -> RustflameStatus {
+    unsafe { flame::set_background(history, r, g, b) }
+}
+
+/// # Safety
+///
+/// `history` must be a live history.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_history_set_affine(
+    history: *mut EditHistory,
+    index: usize,
+    affine: AffineTransform,
+) -> RustflameStatus {
+    unsafe { flame::set_affine(history, index, affine) }
+}
+
+/// # Safety
+///
+/// `history` must be a live history.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_history_set_color(
+    history: *mut EditHistory,
+    index: usize,
+    r: f32,
+    g: f32,
+    b: f32,
+) -> RustflameStatus {
+    unsafe { flame::set_color(history, index, r, g, b) }
+}
+
+/// # Safety
+///
+/// `history` must be a live history.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_history_set_weight(
+    history: *mut EditHistory,
+    index: usize,
+    weight: f64,
+) -> RustflameStatus {
+    unsafe { flame::set_weight(history, index, weight) }
+}
+
+/// # Safety
+///
+/// `history` must be a live history and `variation` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_history_set_variation_weight(
+    history: *mut EditHistory,
+    index: usize,
+    variation: *const c_char,
+    weight: f64,
+) -> RustflameStatus {
+    unsafe { flame::set_variation_weight(history, index, variation, weight) }
+}
+
+/// # Safety
+///
+/// `history` must be a live history, and `variation` and `param` NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_history_set_variation_param(
+    history: *mut EditHistory,
+    index: usize,
+    variation: *const c_char,
+    param: *const c_char,
+    value: f64,
+) -> RustflameStatus {
+    unsafe { flame::set_variation_param(history, index, variation, param, value) }
+}
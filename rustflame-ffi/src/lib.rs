@@ -1,43 +1,191 @@
-use std::ffi::CString;
-use std::ffi::OsStr;
-use std::ffi::OsString;
+//! C bindings for rustflame. Functions taking pointers are `unsafe`: null is always checked for and
+//! reported as `RustflameStatus::NullPointer`, but any other pointer has to be valid as the
+//! function's `# Safety` section describes.
+
+extern crate lodepng;
+extern crate rgb;
+extern crate rustflame;
+
+use error::*;
+use std::ffi::CStr;
 use std::os::raw::c_char;
-use std::os::windows::ffi::OsStrExt;
-use std::mem;
+use std::ptr;
 
 #[cfg(test)]
 mod tests;
 
+pub mod affine;
+pub mod error;
+pub mod flame;
+pub mod history;
+pub mod render;
+pub mod variations;
+
+/// Bumped whenever an exported function, struct or enum changes in a way existing callers would
+/// notice. `include/rustflame.h` carries the same number.
+pub const RUSTFLAME_ABI_VERSION: u32 = 6;
+
+/// Hosts should compare this against the version they were built for before calling anything
+/// else, rather than finding a signature mismatch by crashing.
+#[no_mangle]
+pub extern "C" fn rustflame_abi_version() -> u32 {
+    RUSTFLAME_ABI_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn hello_world() -> RustflameStatus {
+    guard(|| {
+        println!("Hello from rust!");
+        Ok(())
+    })
+}
+
+const DEMO_STRING: &str = "This is a string from rust!";
+
+/// Returns a NUL-terminated UTF-8 string. Release it with `free_string_utf8`.
+#[no_mangle]
+pub extern "C" fn return_string_utf8() -> *mut c_char {
+    guard_ptr(|| {
+        let bytes: Box<[u8]> = format!("{}\0", DEMO_STRING).into_bytes().into_boxed_slice();
+        Ok(Box::into_raw(bytes) as *mut c_char)
+    })
+}
+
+/// # Safety
+///
+/// `s` must be null or a string from `return_string_utf8` that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn free_string_utf8(s: *mut c_char) -> RustflameStatus {
+    guard(|| {
+        if !s.is_null() {
+            unsafe {
+                let len = CStr::from_ptr(s).to_bytes_with_nul().len();
+                drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+                    s as *mut u8,
+                    len,
+                )));
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Returns a NUL-terminated UTF-16 string, in native byte order. Release it with
+/// `free_string_utf16`.
+#[no_mangle]
+pub extern "C" fn return_string_utf16() -> *mut u16 {
+    guard_ptr(|| {
+        let units: Box<[u16]> = DEMO_STRING
+            .encode_utf16()
+            .chain(Some(0))
+            .collect::<Vec<u16>>()
+            .into_boxed_slice();
+        Ok(Box::into_raw(units) as *mut u16)
+    })
+}
+
+/// # Safety
+///
+/// `s` must be null or a string from `return_string_utf16` that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn free_string_utf16(s: *mut u16) -> RustflameStatus {
+    guard(|| {
+        if !s.is_null() {
+            unsafe {
+                let mut len = 0;
+                while *s.add(len) != 0 {
+                    len += 1;
+                }
+                drop(Box::from_raw(ptr::slice_from_raw_parts_mut(s, len + 1)));
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Copies the demo string, UTF-8 without a terminator, into a caller-allocated buffer. `len`
+/// receives the string's length whether or not it fit.
+///
+/// # Safety
+///
+/// `buffer` must be writable for `capacity` bytes and `len` writable.
 #[no_mangle]
-pub extern "cdecl" fn hello_world() {
-    println!("Hello from rust!");
+pub unsafe extern "C" fn copy_string_utf8(
+    buffer: *mut u8,
+    capacity: usize,
+    len: *mut usize,
+) -> RustflameStatus {
+    guard(|| unsafe { copy_out(DEMO_STRING.as_bytes(), buffer, capacity, len) })
 }
 
+/// Returns a red/green gradient, or null if the size overflows. Release it with
+/// `free_image_rg_24bpp`, passing the same width and height.
 #[no_mangle]
-pub extern "cdecl" fn return_string_utf8() -> *const u16 {
-    let mut vec: Vec<u16> = OsStr::new("This is a string from rust!\0")
-        .encode_wide()
-        .collect();
-    let r = vec.as_ptr();
-    mem::forget(vec);
-    r
+pub extern "C" fn return_image_rg_24bpp(width: u32, height: u32) -> *mut u8 {
+    guard_ptr(|| {
+        println!(
+            "starting the image generation. width: {} height: {}",
+            width, height
+        );
+        let mut v = vec![0u8; image_rg_24bpp_len(width, height)?];
+        fill_rg_24bpp(width, &mut v);
+        Ok(Box::into_raw(v.into_boxed_slice()) as *mut u8)
+    })
 }
 
+/// # Safety
+///
+/// `image` must be null or an image from `return_image_rg_24bpp` of the same size that hasn't
+/// been freed yet.
 #[no_mangle]
-pub extern "cdecl" fn return_image_rg_24bpp(width: u32, height: u32) -> *const u8 {
-    println!("starting the image generation. width: {} height: {}",width, height);
-    let mut v = Vec::<u8>::with_capacity((width * height * 3) as usize);
-    for i in 0..width {
-        for j in 0..height {
-            let r = (i%255) as u8;
-            let g = (j%255) as u8;
-            v.push(r);
-            v.push(g);
-            v.push(0);
+pub unsafe extern "C" fn free_image_rg_24bpp(
+    image: *mut u8,
+    width: u32,
+    height: u32,
+) -> RustflameStatus {
+    guard(|| {
+        let len = image_rg_24bpp_len(width, height)?;
+        if !image.is_null() {
+            unsafe { drop(Box::from_raw(ptr::slice_from_raw_parts_mut(image, len))) };
         }
+        Ok(())
+    })
+}
+
+/// Writes the red/green gradient into a caller-allocated buffer of at least
+/// `width * height * 3` bytes, rows top to bottom. `len` receives the required length.
+///
+/// # Safety
+///
+/// `buffer` must be writable for `capacity` bytes and `len` writable.
+#[no_mangle]
+pub unsafe extern "C" fn fill_image_rg_24bpp(
+    width: u32,
+    height: u32,
+    buffer: *mut u8,
+    capacity: usize,
+    len: *mut usize,
+) -> RustflameStatus {
+    guard(|| {
+        let required = image_rg_24bpp_len(width, height)?;
+        fill_rg_24bpp(width, unsafe {
+            out_buffer(buffer, capacity, required, len)
+        }?);
+        Ok(())
+    })
+}
+
+fn image_rg_24bpp_len(width: u32, height: u32) -> FfiResult<usize> {
+    (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(3))
+        .ok_or_else(|| FfiError::invalid(format!("a {}x{} image is too large", width, height)))
+}
+
+fn fill_rg_24bpp(width: u32, buffer: &mut [u8]) {
+    for (idx, pixel) in buffer.chunks_mut(3).enumerate() {
+        pixel[0] = ((idx % width as usize) % 255) as u8;
+        pixel[1] = ((idx / width as usize) % 255) as u8;
+        pixel[2] = 0;
     }
-    println!("{} {} {}, {} {} {}, {} {} {}",v[0],v[1],v[2],v[3],v[4],v[5],v[6],v[7],v[8]);
-    let ret = v.as_ptr();
-    mem::forget(v);
-    ret
 }
@@ -0,0 +1,503 @@
+use error::*;
+use lodepng::Bitmap;
+use rgb::RGB;
+use rgb::RGBA;
+use rustflame::flame_2d::Flame;
+use rustflame::flame_2d::RenderMonitor;
+use rustflame::flame_2d::Renderer;
+use rustflame::flame_2d::Termination;
+use rustflame::flame_2d::THUMBNAIL_SAMPLES_PER_PIXEL;
+use rustflame::output::PngPixel;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Pixel layouts a finished render can be copied out as.
+pub const PIXEL_FORMAT_RGB24: u32 = 0;
+pub const PIXEL_FORMAT_RGBA32: u32 = 1;
+
+/// Called from the render thread with the fraction of the render done, from 0.0 to 1.0.
+pub type RustflameProgressCallback = Option<extern "C" fn(user_data: *mut c_void, progress: f64)>;
+
+/// Called from the render thread with a preview of the image so far, in the job's pixel format.
+/// `pixels` is only valid for the duration of the call.
+pub type RustflamePreviewCallback = Option<
+    extern "C" fn(user_data: *mut c_void, pixels: *const u8, len: usize, width: u32, height: u32),
+>;
+
+/// A render running on its own thread, and its pixels once it has finished.
+pub struct RenderJob {
+    thread: Option<JoinHandle<Option<Vec<u8>>>>,
+    state: Arc<JobState>,
+    outcome: Option<Outcome>,
+}
+
+/// Shared between a job handle and its render thread.
+#[derive(Default)]
+struct JobState {
+    /// The fraction done, as the bits of an f64.
+    progress: AtomicU64,
+    cancelled: AtomicBool,
+    finished: AtomicBool,
+}
+
+enum Outcome {
+    Finished(Vec<u8>),
+    Cancelled,
+    Failed(String),
+}
+
+/// Marks the job finished when the render thread ends, even by panicking.
+struct FinishOnDrop(Arc<JobState>);
+
+impl Drop for FinishOnDrop {
+    fn drop(&mut self) {
+        self.0.finished.store(true, Ordering::SeqCst);
+    }
+}
+
+/// The host's `user_data`. Handing it to the render thread is the host's promise that the
+/// callbacks can be called from there.
+struct UserData(*mut c_void);
+
+unsafe impl Send for UserData {}
+
+/// Forwards a render's progress to the job state and the host's callbacks.
+struct CallbackMonitor {
+    state: Arc<JobState>,
+    progress: RustflameProgressCallback,
+    preview: RustflamePreviewCallback,
+    preview_frames: u32,
+    user_data: UserData,
+}
+
+impl<P: PngPixel> RenderMonitor<P> for CallbackMonitor {
+    fn progress(&mut self, done: f64) -> bool {
+        self.state.progress.store(done.to_bits(), Ordering::SeqCst);
+        if let Some(progress) = self.progress {
+            progress(self.user_data.0, done);
+        }
+        !self.state.cancelled.load(Ordering::SeqCst)
+    }
+    fn preview_frames(&self) -> u32 {
+        match self.preview {
+            Some(_) => self.preview_frames,
+            None => 0,
+        }
+    }
+    fn preview(&mut self, frame: &Bitmap<P>) {
+        if let Some(preview) = self.preview {
+            let pixels = pixel_bytes(frame);
+            preview(
+                self.user_data.0,
+                pixels.as_ptr(),
+                pixels.len(),
+                frame.width as u32,
+                frame.height as u32,
+            );
+        }
+    }
+}
+
+/// Creates a renderer for a copy of `flame`, so the flame can keep being edited.
+/// Free the renderer with `rustflame_renderer_free`.
+///
+/// # Safety
+///
+/// `flame` must be a live flame and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_renderer_new(
+    flame: *const Flame,
+    width: u32,
+    height: u32,
+    gamma: f64,
+    vibrancy: f64,
+    out: *mut *mut Renderer,
+) -> RustflameStatus {
+    guard(|| {
+        let flame = unsafe { handle_ref(flame, "flame") }?;
+        if width == 0 || height == 0 {
+            return Err(FfiError::invalid(format!(
+                "image size {}x{} is empty",
+                width, height
+            )));
+        }
+        if gamma.is_nan() || gamma <= 0.0 {
+            return Err(FfiError::invalid(format!(
+                "gamma {} must be positive",
+                gamma
+            )));
+        }
+        let renderer = Renderer::new(width, height, gamma, vibrancy, flame.clone());
+        unsafe { export_handle(out, renderer, "out") }
+    })
+}
+
+/// # Safety
+///
+/// `renderer` must be a live renderer, not used again after this call.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_renderer_free(renderer: *mut Renderer) -> RustflameStatus {
+    unsafe { free_handle(renderer) }
+}
+
+/// Stops renders after a fixed number of iterations.
+///
+/// # Safety
+///
+/// `renderer` must be a live renderer.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_renderer_set_iterations(
+    renderer: *mut Renderer,
+    iterations: u64,
+) -> RustflameStatus {
+    guard(|| {
+        let renderer = unsafe { handle_mut(renderer, "renderer") }?;
+        if iterations == 0 {
+            return Err(FfiError::invalid("iterations must be at least 1"));
+        }
+        renderer.set_termination(Termination::Iterations(iterations as usize));
+        Ok(())
+    })
+}
+
+/// Stops renders after a fixed amount of time.
+///
+/// # Safety
+///
+/// `renderer` must be a live renderer.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_renderer_set_time_limit_ms(
+    renderer: *mut Renderer,
+    ms: u64,
+) -> RustflameStatus {
+    guard(|| {
+        let renderer = unsafe { handle_mut(renderer, "renderer") }?;
+        renderer.set_termination(Termination::Time(Duration::from_millis(ms)));
+        Ok(())
+    })
+}
+
+/// # Safety
+///
+/// `renderer` must be a live renderer.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_renderer_set_seed(
+    renderer: *mut Renderer,
+    s0: u32,
+    s1: u32,
+    s2: u32,
+    s3: u32,
+) -> RustflameStatus {
+    guard(|| {
+        let renderer = unsafe { handle_mut(renderer, "renderer") }?;
+        renderer.set_seed([s0, s1, s2, s3]);
+        Ok(())
+    })
+}
+
+/// Starts rendering a copy of `renderer` on a background thread, in one of the `PIXEL_FORMAT_*`
+/// layouts. Free the job with `rustflame_render_free`.
+///
+/// # Safety
+///
+/// `renderer` must be a live renderer and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_render_start(
+    renderer: *const Renderer,
+    pixel_format: u32,
+    out: *mut *mut RenderJob,
+) -> RustflameStatus {
+    rustflame_render_start_with_callbacks(
+        renderer,
+        pixel_format,
+        None,
+        None,
+        0,
+        ptr::null_mut(),
+        out,
+    )
+}
+
+/// Starts a render like `rustflame_render_start`, calling `progress` as it goes and `preview` with
+/// `preview_frames` images of the render so far. Either callback may be null. Both are called on
+/// the render thread with `user_data`, so a UI host must marshal back to its own thread.
+///
+/// # Safety
+///
+/// `renderer` must be a live renderer and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_render_start_with_callbacks(
+    renderer: *const Renderer,
+    pixel_format: u32,
+    progress: RustflameProgressCallback,
+    preview: RustflamePreviewCallback,
+    preview_frames: u32,
+    user_data: *mut c_void,
+    out: *mut *mut RenderJob,
+) -> RustflameStatus {
+    guard(|| {
+        let renderer = unsafe { handle_ref(renderer, "renderer") }?.clone();
+        if renderer.flame().transforms.is_empty() {
+            return Err(FfiError::invalid("the flame has no transforms"));
+        }
+        let state = Arc::new(JobState::default());
+        let monitor = CallbackMonitor {
+            state: state.clone(),
+            progress,
+            preview,
+            preview_frames,
+            user_data: UserData(user_data),
+        };
+        let thread = match pixel_format {
+            PIXEL_FORMAT_RGB24 => spawn_render::<RGB<u8>>(renderer, monitor),
+            PIXEL_FORMAT_RGBA32 => spawn_render::<RGBA<u8>>(renderer, monitor),
+            _ => {
+                return Err(FfiError::invalid(format!(
+                    "unknown pixel format {}",
+                    pixel_format
+                )))
+            }
+        };
+        let job = RenderJob {
+            thread: Some(thread),
+            state,
+            outcome: None,
+        };
+        unsafe { export_handle(out, job, "out") }
+    })
+}
+
+/// Asks a render to stop. It stops soon after, without blocking the caller, and then waiting on
+/// it reports `Cancelled`. Cancelling a finished render does nothing.
+///
+/// # Safety
+///
+/// `job` must be a live render job.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_render_cancel(job: *const RenderJob) -> RustflameStatus {
+    guard(|| {
+        let job = unsafe { handle_ref(job, "job") }?;
+        job.state.cancelled.store(true, Ordering::SeqCst);
+        Ok(())
+    })
+}
+
+/// Checks on a render without blocking, writing the fraction done to `progress` if it isn't null.
+/// Returns `NotFinished` while the render is running, and otherwise what `rustflame_render_wait`
+/// would.
+///
+/// # Safety
+///
+/// `job` must be a live render job and `progress` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_render_poll(
+    job: *mut RenderJob,
+    progress: *mut f64,
+) -> RustflameStatus {
+    guard(|| {
+        let job = unsafe { handle_mut(job, "job") }?;
+        if !progress.is_null() {
+            let done = f64::from_bits(job.state.progress.load(Ordering::SeqCst));
+            unsafe { *progress = done };
+        }
+        if job.thread.is_some() && !job.state.finished.load(Ordering::SeqCst) {
+            return Err(FfiError::new(
+                RustflameStatus::NotFinished,
+                "the render is still running",
+            ));
+        }
+        finish(job)
+    })
+}
+
+/// Blocks until the render has finished. A render that panicked reports `RenderFailed`, and one
+/// that was cancelled reports `Cancelled`.
+///
+/// # Safety
+///
+/// `job` must be a live render job.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_render_wait(job: *mut RenderJob) -> RustflameStatus {
+    guard(|| finish(unsafe { handle_mut(job, "job") }?))
+}
+
+/// Writes the size in bytes of the finished image to `len`.
+///
+/// # Safety
+///
+/// `job` must be a live render job and `len` writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_render_pixels_len(
+    job: *const RenderJob,
+    len: *mut usize,
+) -> RustflameStatus {
+    guard(|| {
+        let pixels = finished_pixels(unsafe { handle_ref(job, "job") }?)?;
+        unsafe { write_out(len, pixels.len(), "len") }
+    })
+}
+
+/// Copies the finished image, rows top to bottom, into a caller-allocated buffer. `len` receives
+/// the image's size in bytes once the render has finished.
+///
+/// # Safety
+///
+/// `job` must be a live render job, `buffer` writable for `capacity` bytes and `len` writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_render_copy_pixels(
+    job: *const RenderJob,
+    buffer: *mut u8,
+    capacity: usize,
+    len: *mut usize,
+) -> RustflameStatus {
+    guard(|| {
+        let pixels = finished_pixels(unsafe { handle_ref(job, "job") }?)?;
+        unsafe { copy_out(pixels, buffer, capacity, len) }
+    })
+}
+
+/// Frees a render job. A render that is still running is cancelled and left to wind down in the
+/// background.
+///
+/// # Safety
+///
+/// `job` must be a live render job, not used again after this call.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_render_free(job: *mut RenderJob) -> RustflameStatus {
+    if let Some(job) = unsafe { job.as_ref() } {
+        job.state.cancelled.store(true, Ordering::SeqCst);
+    }
+    unsafe { free_handle(job) }
+}
+
+/// Renders a quick, rough thumbnail of `flame` on the calling thread, into a caller-allocated
+/// buffer in one of the `PIXEL_FORMAT_*` layouts, rows top to bottom. Iteration stops after
+/// `budget_ms`, or after a few thousand iterations per pixel if that comes first; pass 0 for the
+/// default budget. `len` receives the required length.
+///
+/// Calls only read the flame, so hosts filling a grid of thumbnails can make them from as many
+/// threads as they like, as long as nothing edits the flames meanwhile.
+///
+/// # Safety
+///
+/// `flame` must be a live flame, `buffer` writable for `capacity` bytes and `len` writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_thumbnail_render(
+    flame: *const Flame,
+    width: u32,
+    height: u32,
+    budget_ms: u64,
+    pixel_format: u32,
+    buffer: *mut u8,
+    capacity: usize,
+    len: *mut usize,
+) -> RustflameStatus {
+    guard(|| {
+        let flame = unsafe { handle_ref(flame, "flame") }?;
+        if width == 0 || height == 0 {
+            return Err(FfiError::invalid(format!(
+                "image size {}x{} is empty",
+                width, height
+            )));
+        }
+        if flame.transforms.is_empty() {
+            return Err(FfiError::invalid("the flame has no transforms"));
+        }
+        let mut renderer = Renderer::thumbnail(width, height, flame.clone());
+        if budget_ms > 0 {
+            let pixels = width as usize * height as usize;
+            renderer.set_termination(Termination::FirstOf(
+                Duration::from_millis(budget_ms),
+                pixels.saturating_mul(THUMBNAIL_SAMPLES_PER_PIXEL),
+            ));
+        }
+        let bytes_per_pixel = match pixel_format {
+            PIXEL_FORMAT_RGB24 => 3,
+            PIXEL_FORMAT_RGBA32 => 4,
+            _ => {
+                return Err(FfiError::invalid(format!(
+                    "unknown pixel format {}",
+                    pixel_format
+                )))
+            }
+        };
+        //Check the buffer before rendering, so a host asking for the length doesn't pay for a
+        //render.
+        let required = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|pixels| pixels.checked_mul(bytes_per_pixel))
+            .ok_or_else(|| {
+                FfiError::invalid(format!("a {}x{} image is too large", width, height))
+            })?;
+        let out = unsafe { out_buffer(buffer, capacity, required, len) }?;
+        let pixels = match pixel_format {
+            PIXEL_FORMAT_RGB24 => pixel_bytes(&renderer.render_unthreaded_as::<RGB<u8>>()),
+            _ => pixel_bytes(&renderer.render_unthreaded_as::<RGBA<u8>>()),
+        };
+        out.copy_from_slice(&pixels);
+        Ok(())
+    })
+}
+
+fn spawn_render<P: PngPixel + 'static>(
+    mut renderer: Renderer,
+    mut monitor: CallbackMonitor,
+) -> JoinHandle<Option<Vec<u8>>> {
+    thread::spawn(move || {
+        let _finish = FinishOnDrop(monitor.state.clone());
+        renderer
+            .render_monitored::<P, _>(&mut monitor)
+            .map(|bm| pixel_bytes(&bm))
+    })
+}
+
+fn pixel_bytes<P: PngPixel>(bitmap: &Bitmap<P>) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(bitmap.buffer.len() * 4);
+    for p in &bitmap.buffer {
+        p.push_samples(&mut bytes);
+    }
+    bytes
+}
+
+/// Joins the render thread if that hasn't happened yet, and reports how the render ended.
+fn finish(job: &mut RenderJob) -> FfiResult<()> {
+    if let Some(thread) = job.thread.take() {
+        job.outcome = Some(match thread.join() {
+            Ok(Some(pixels)) => Outcome::Finished(pixels),
+            Ok(None) => Outcome::Cancelled,
+            Err(payload) => Outcome::Failed(panic_message(&*payload)),
+        });
+    }
+    match job.outcome {
+        Some(Outcome::Finished(_)) => Ok(()),
+        Some(Outcome::Cancelled) => Err(FfiError::new(
+            RustflameStatus::Cancelled,
+            "the render was cancelled",
+        )),
+        Some(Outcome::Failed(ref message)) => Err(FfiError::new(
+            RustflameStatus::RenderFailed,
+            format!("render failed: {}", message),
+        )),
+        None => Err(FfiError::new(
+            RustflameStatus::RenderFailed,
+            "render failed earlier",
+        )),
+    }
+}
+
+fn finished_pixels(job: &RenderJob) -> FfiResult<&Vec<u8>> {
+    match job.outcome {
+        Some(Outcome::Finished(ref pixels)) => Ok(pixels),
+        _ => Err(FfiError::new(
+            RustflameStatus::NotFinished,
+            "the render hasn't finished, or hasn't been waited on",
+        )),
+    }
+}
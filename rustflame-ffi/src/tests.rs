@@ -1,4 +1,584 @@
+use super::*;
+use affine::*;
+use flame::*;
+use history::*;
+use render::*;
+use rustflame::flame_2d::AffineTransform;
+use rustflame::flame_2d::Flame;
+use rustflame::flame_2d::Point;
+use rustflame::flame_2d::Renderer;
+use std::ffi::CString;
+use std::os::raw::c_void;
+use std::ptr;
+use std::slice;
+use variations::*;
+
 #[test]
 fn it_works() {
     assert_eq!(2 + 2, 4);
 }
+
+fn last_error() -> String {
+    let message = rustflame_last_error_message();
+    assert!(!message.is_null());
+    unsafe { CStr::from_ptr(message) }
+        .to_str()
+        .unwrap()
+        .to_owned()
+}
+
+fn new_renderer(width: u32, height: u32) -> *mut Renderer {
+    unsafe {
+        let flame = new_flame("Renderer flame");
+        rustflame_flame_add_affine_transform(flame, 0.5, 0.0, 0.0, 0.5, 0.0, 0.0, 1.0, 0.0, 0.0);
+        rustflame_flame_add_affine_transform(flame, 0.5, 0.0, 0.0, 0.5, 0.5, 0.0, 0.0, 1.0, 0.0);
+        let mut renderer = ptr::null_mut();
+        assert_eq!(
+            rustflame_renderer_new(flame, width, height, 2.2, 0.75, &mut renderer),
+            RustflameStatus::Ok
+        );
+        rustflame_flame_free(flame);
+        renderer
+    }
+}
+
+fn new_flame(name: &str) -> *mut Flame {
+    unsafe {
+        let name = CString::new(name).unwrap();
+        let mut flame = ptr::null_mut();
+        assert_eq!(
+            rustflame_flame_new(name.as_ptr(), &mut flame),
+            RustflameStatus::Ok
+        );
+        assert!(!flame.is_null());
+        flame
+    }
+}
+
+#[test]
+fn render_through_handles() {
+    unsafe {
+        let flame = new_flame("FFI flame");
+        assert_eq!(
+            rustflame_flame_add_affine_transform(
+                flame, 0.5, 0.0, 0.0, 0.5, 0.0, 0.0, 1.0, 0.0, 0.0
+            ),
+            RustflameStatus::Ok
+        );
+        assert_eq!(
+            rustflame_flame_add_affine_transform(
+                flame, 0.5, 0.0, 0.0, 0.5, 0.5, 0.0, 0.0, 1.0, 0.0
+            ),
+            RustflameStatus::Ok
+        );
+
+        let mut renderer: *mut Renderer = ptr::null_mut();
+        assert_eq!(
+            rustflame_renderer_new(flame, 32, 16, 2.2, 0.75, &mut renderer),
+            RustflameStatus::Ok
+        );
+        assert_eq!(rustflame_flame_free(flame), RustflameStatus::Ok);
+        assert_eq!(
+            rustflame_renderer_set_iterations(renderer, 10_000),
+            RustflameStatus::Ok
+        );
+        assert_eq!(
+            rustflame_renderer_set_seed(renderer, 1, 2, 3, 4),
+            RustflameStatus::Ok
+        );
+
+        let mut job = ptr::null_mut();
+        assert_eq!(
+            rustflame_render_start(renderer, 7, &mut job),
+            RustflameStatus::InvalidArgument
+        );
+        assert_eq!(
+            rustflame_render_start(renderer, PIXEL_FORMAT_RGBA32, &mut job),
+            RustflameStatus::Ok
+        );
+        assert_eq!(rustflame_renderer_free(renderer), RustflameStatus::Ok);
+        let mut len = 0;
+        assert_eq!(
+            rustflame_render_pixels_len(job, &mut len),
+            RustflameStatus::NotFinished
+        );
+        assert_eq!(rustflame_render_wait(job), RustflameStatus::Ok);
+        assert_eq!(
+            rustflame_render_pixels_len(job, &mut len),
+            RustflameStatus::Ok
+        );
+        assert_eq!(len, 32 * 16 * 4);
+
+        let mut pixels = vec![0u8; 32 * 16 * 4];
+        assert_eq!(
+            rustflame_render_copy_pixels(job, pixels.as_mut_ptr(), 10, &mut len),
+            RustflameStatus::BufferTooSmall
+        );
+        assert_eq!(len, pixels.len());
+        assert_eq!(
+            rustflame_render_copy_pixels(job, pixels.as_mut_ptr(), pixels.len(), &mut len),
+            RustflameStatus::Ok
+        );
+        assert!(pixels.chunks(4).any(|p| p[3] > 0));
+        assert_eq!(rustflame_render_free(job), RustflameStatus::Ok);
+    }
+}
+
+#[test]
+fn errors_are_reported() {
+    unsafe {
+        let flame = new_flame("Errors");
+        assert_eq!(
+            rustflame_flame_add_affine_transform(
+                flame, 0.5, 0.0, 0.0, 0.5, 0.0, 0.5, 2.0, 0.0, 0.0
+            ),
+            RustflameStatus::InvalidArgument
+        );
+        assert!(last_error().contains("outside 0.0-1.0"));
+        assert_eq!(
+            rustflame_flame_set_background(ptr::null_mut(), 0.0, 0.0, 0.0),
+            RustflameStatus::NullPointer
+        );
+        assert_eq!(last_error(), "flame is null");
+
+        let mut renderer = ptr::null_mut();
+        assert_eq!(
+            rustflame_renderer_new(flame, 0, 16, 2.2, 0.75, &mut renderer),
+            RustflameStatus::InvalidArgument
+        );
+        assert!(renderer.is_null());
+        assert_eq!(
+            rustflame_renderer_new(flame, 16, 16, 2.2, 0.75, &mut renderer),
+            RustflameStatus::Ok
+        );
+        let mut job = ptr::null_mut();
+        assert_eq!(
+            rustflame_render_start(renderer, PIXEL_FORMAT_RGB24, &mut job),
+            RustflameStatus::InvalidArgument
+        );
+        assert_eq!(last_error(), "the flame has no transforms");
+        rustflame_renderer_free(renderer);
+        rustflame_flame_free(flame);
+    }
+}
+
+#[test]
+fn header_matches_abi_version() {
+    let header = include_str!("../include/rustflame.h");
+    let generated = include_str!(concat!(env!("OUT_DIR"), "/rustflame.h"));
+    assert!(
+        header == generated,
+        "include/rustflame.h is stale; rebuild with RUSTFLAME_UPDATE_HEADER=1 to update it"
+    );
+    assert_eq!(rustflame_abi_version(), RUSTFLAME_ABI_VERSION);
+    assert!(header.contains(&format!(
+        "#define RUSTFLAME_ABI_VERSION {}",
+        RUSTFLAME_ABI_VERSION
+    )));
+}
+
+#[test]
+fn panics_do_not_unwind_out() {
+    let status = guard(|| panic!("on purpose"));
+    assert_eq!(status, RustflameStatus::Panic);
+    assert_eq!(last_error(), "rustflame panicked: on purpose");
+}
+
+#[test]
+fn demo_buffers_round_trip() {
+    unsafe {
+        let s = return_string_utf8();
+        assert_eq!(
+            CStr::from_ptr(s).to_str().unwrap(),
+            "This is a string from rust!"
+        );
+        assert_eq!(free_string_utf8(s), RustflameStatus::Ok);
+
+        let s = return_string_utf16();
+        assert_eq!(*s, 'T' as u16);
+        assert_eq!(free_string_utf16(s), RustflameStatus::Ok);
+
+        let mut buffer = [0u8; 8];
+        let mut len = 0;
+        assert_eq!(
+            copy_string_utf8(buffer.as_mut_ptr(), buffer.len(), &mut len),
+            RustflameStatus::BufferTooSmall
+        );
+        assert_eq!(len, 27);
+
+        let image = return_image_rg_24bpp(4, 3);
+        let mut filled = [0u8; 4 * 3 * 3];
+        assert_eq!(
+            fill_image_rg_24bpp(4, 3, filled.as_mut_ptr(), filled.len(), &mut len),
+            RustflameStatus::Ok
+        );
+        assert_eq!(slice::from_raw_parts(image, 36), &filled[..]);
+        //Pixel (x: 1, y: 2) is red 1, green 2.
+        assert_eq!(&filled[(2 * 4 + 1) * 3..(2 * 4 + 2) * 3], &[1, 2, 0]);
+        assert_eq!(free_image_rg_24bpp(image, 4, 3), RustflameStatus::Ok);
+    }
+}
+
+#[derive(Default)]
+struct CallbackLog {
+    progress: Vec<f64>,
+    preview_lens: Vec<usize>,
+}
+
+extern "C" fn log_progress(user_data: *mut c_void, progress: f64) {
+    let log = unsafe { &mut *(user_data as *mut CallbackLog) };
+    log.progress.push(progress);
+}
+
+extern "C" fn log_preview(
+    user_data: *mut c_void,
+    pixels: *const u8,
+    len: usize,
+    width: u32,
+    height: u32,
+) {
+    let log = unsafe { &mut *(user_data as *mut CallbackLog) };
+    assert!(!pixels.is_null());
+    assert_eq!(len, (width * height * 3) as usize);
+    log.preview_lens.push(len);
+}
+
+#[test]
+fn async_render_callbacks() {
+    unsafe {
+        let renderer = new_renderer(24, 16);
+        rustflame_renderer_set_iterations(renderer, 200_000);
+        let mut log = CallbackLog::default();
+        let mut job = ptr::null_mut();
+        assert_eq!(
+            rustflame_render_start_with_callbacks(
+                renderer,
+                PIXEL_FORMAT_RGB24,
+                Some(log_progress),
+                Some(log_preview),
+                2,
+                &mut log as *mut CallbackLog as *mut c_void,
+                &mut job,
+            ),
+            RustflameStatus::Ok
+        );
+        rustflame_renderer_free(renderer);
+        assert_eq!(rustflame_render_wait(job), RustflameStatus::Ok);
+        let mut progress = 0.0;
+        assert_eq!(
+            rustflame_render_poll(job, &mut progress),
+            RustflameStatus::Ok
+        );
+        assert_eq!(progress, 1.0);
+        rustflame_render_free(job);
+
+        assert_eq!(log.preview_lens, vec![24 * 16 * 3; 2]);
+        assert_eq!(*log.progress.last().unwrap(), 1.0);
+    }
+}
+
+#[test]
+fn async_render_cancel() {
+    unsafe {
+        let renderer = new_renderer(24, 16);
+        rustflame_renderer_set_time_limit_ms(renderer, 60_000);
+        let mut job = ptr::null_mut();
+        assert_eq!(
+            rustflame_render_start(renderer, PIXEL_FORMAT_RGBA32, &mut job),
+            RustflameStatus::Ok
+        );
+        rustflame_renderer_free(renderer);
+        assert_eq!(
+            rustflame_render_poll(job, ptr::null_mut()),
+            RustflameStatus::NotFinished
+        );
+        assert_eq!(rustflame_render_cancel(job), RustflameStatus::Ok);
+        assert_eq!(rustflame_render_wait(job), RustflameStatus::Cancelled);
+        assert_eq!(
+            rustflame_render_poll(job, ptr::null_mut()),
+            RustflameStatus::Cancelled
+        );
+        let mut len = 0;
+        assert_eq!(
+            rustflame_render_pixels_len(job, &mut len),
+            RustflameStatus::NotFinished
+        );
+        rustflame_render_free(job);
+    }
+}
+
+fn c(s: &str) -> CString {
+    CString::new(s).unwrap()
+}
+
+#[test]
+fn edit_transforms() {
+    unsafe {
+        let flame = new_flame("Editing");
+        rustflame_flame_add_affine_transform(flame, 0.5, 0.0, 0.0, 0.5, 0.0, 0.0, 1.0, 0.0, 0.0);
+        rustflame_flame_add_affine_transform(flame, 0.5, 0.0, 0.0, 0.5, 0.5, 0.0, 0.0, 1.0, 0.0);
+        let mut count = 0;
+        rustflame_flame_transform_count(flame, &mut count);
+        assert_eq!(count, 2);
+
+        let affine = AffineTransform {
+            xx: 0.1,
+            xy: 0.2,
+            yx: 0.3,
+            yy: 0.4,
+            cx: 0.5,
+            cy: 0.6,
+        };
+        assert_eq!(
+            rustflame_flame_set_affine(flame, 1, affine),
+            RustflameStatus::Ok
+        );
+        let mut read = AffineTransform {
+            xx: 0.0,
+            xy: 0.0,
+            yx: 0.0,
+            yy: 0.0,
+            cx: 0.0,
+            cy: 0.0,
+        };
+        rustflame_flame_get_affine(flame, 1, &mut read);
+        assert_eq!(read, affine);
+        assert_eq!(
+            rustflame_flame_set_affine(flame, 2, affine),
+            RustflameStatus::InvalidArgument
+        );
+        assert_eq!(last_error(), "transform 2 is out of range, the flame has 2");
+
+        assert_eq!(
+            rustflame_flame_set_color(flame, 0, 0.0, 0.0, 1.0),
+            RustflameStatus::Ok
+        );
+        assert_eq!(
+            rustflame_flame_set_weight(flame, 0, -1.0),
+            RustflameStatus::InvalidArgument
+        );
+        assert_eq!(
+            rustflame_flame_set_weight(flame, 0, 2.0),
+            RustflameStatus::Ok
+        );
+
+        let curl = c("curl");
+        let c2 = c("curl_c2");
+        let mut value = 0.0;
+        rustflame_flame_get_variation_weight(flame, 0, c("linear").as_ptr(), &mut value);
+        assert_eq!(value, 1.0);
+        assert_eq!(
+            rustflame_flame_set_variation_weight(flame, 0, curl.as_ptr(), 0.5),
+            RustflameStatus::Ok
+        );
+        assert_eq!(
+            rustflame_flame_set_variation_param(flame, 0, curl.as_ptr(), c2.as_ptr(), 0.3),
+            RustflameStatus::Ok
+        );
+        rustflame_flame_get_variation_param(flame, 0, curl.as_ptr(), c2.as_ptr(), &mut value);
+        assert_eq!(value, 0.3);
+        rustflame_flame_get_variation_weight(flame, 0, curl.as_ptr(), &mut value);
+        assert_eq!(value, 0.5);
+        assert_eq!(
+            rustflame_flame_set_variation_weight(flame, 0, c("nope").as_ptr(), 0.5),
+            RustflameStatus::InvalidArgument
+        );
+        assert_eq!(last_error(), "no variation is named nope");
+
+        assert_eq!(
+            rustflame_flame_remove_transform(flame, 1),
+            RustflameStatus::Ok
+        );
+        rustflame_flame_transform_count(flame, &mut count);
+        assert_eq!(count, 1);
+        {
+            let flame = &*flame;
+            assert_eq!(flame.weights, vec![2.0]);
+            assert_eq!(flame.colors[0].b, 1.0);
+        }
+        rustflame_flame_free(flame);
+    }
+}
+
+#[test]
+fn list_variations() {
+    unsafe {
+        let mut count = 0;
+        rustflame_variation_count(&mut count);
+        let mut names = Vec::new();
+        for i in 0..count {
+            let mut buffer = [0u8; 32];
+            let mut len = 0;
+            assert_eq!(
+                rustflame_variation_name(i, buffer.as_mut_ptr(), buffer.len(), &mut len),
+                RustflameStatus::Ok
+            );
+            names.push(String::from_utf8(buffer[..len].to_vec()).unwrap());
+        }
+        assert!(names.iter().any(|n| n == "pdj"));
+
+        let pdj = c("pdj");
+        rustflame_variation_param_count(pdj.as_ptr(), &mut count);
+        assert_eq!(count, 4);
+        let mut buffer = [0u8; 32];
+        let mut len = 0;
+        rustflame_variation_param_name(
+            pdj.as_ptr(),
+            3,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut len,
+        );
+        assert_eq!(&buffer[..len], b"pdj_d");
+        assert_eq!(
+            rustflame_variation_param_count(c("nope").as_ptr(), &mut count),
+            RustflameStatus::InvalidArgument
+        );
+    }
+}
+
+#[test]
+fn undo_through_history() {
+    unsafe {
+        let flame = new_flame("History");
+        let mut history = ptr::null_mut();
+        assert_eq!(
+            rustflame_history_new(flame, &mut history),
+            RustflameStatus::Ok
+        );
+        rustflame_flame_free(flame);
+        let affine = AffineTransform {
+            xx: 0.5,
+            xy: 0.0,
+            yx: 0.0,
+            yy: 0.5,
+            cx: 0.0,
+            cy: 0.0,
+        };
+        assert_eq!(
+            rustflame_history_add_affine_transform(history, affine, 1.0, 0.0, 0.0),
+            RustflameStatus::Ok
+        );
+        rustflame_history_seal(history);
+        for i in 0..5 {
+            let dragged = AffineTransform {
+                cx: i as f64 * 0.1,
+                ..affine
+            };
+            rustflame_history_set_affine(history, 0, dragged);
+        }
+        assert_eq!(
+            rustflame_history_set_affine(history, 3, affine),
+            RustflameStatus::InvalidArgument
+        );
+
+        let mut current = ptr::null();
+        rustflame_history_flame(history, &mut current);
+        let mut read = affine;
+        rustflame_flame_get_affine(current, 0, &mut read);
+        assert_eq!(read.cx, 0.4);
+
+        let mut done = false;
+        rustflame_history_undo(history, &mut done);
+        assert!(done);
+        rustflame_flame_get_affine(current, 0, &mut read);
+        assert_eq!(read, affine);
+        rustflame_history_undo(history, &mut done);
+        let mut count = 1;
+        rustflame_flame_transform_count(current, &mut count);
+        assert_eq!(count, 0);
+        rustflame_history_undo(history, &mut done);
+        assert!(!done);
+
+        let (mut can_undo, mut can_redo) = (true, false);
+        rustflame_history_state(history, &mut can_undo, &mut can_redo);
+        assert!(!can_undo && can_redo);
+        rustflame_history_redo(history, &mut done);
+        rustflame_history_redo(history, &mut done);
+        rustflame_flame_get_affine(current, 0, &mut read);
+        assert_eq!(read.cx, 0.4);
+        rustflame_history_free(history);
+    }
+}
+
+#[test]
+fn drag_triangle_handles() {
+    unsafe {
+        let mut affine = AffineTransform {
+            xx: 1.0,
+            xy: 0.0,
+            yx: 0.0,
+            yy: 1.0,
+            cx: 0.0,
+            cy: 0.0,
+        };
+        let corner = Point { x: 0.5, y: 0.5 };
+        assert_eq!(
+            rustflame_affine_move_vertex(&mut affine, RustflameVertex::X as u32, corner),
+            RustflameStatus::Ok
+        );
+        let mut triangle = affine.triangle();
+        assert_eq!(
+            rustflame_affine_to_triangle(affine, &mut triangle),
+            RustflameStatus::Ok
+        );
+        assert_eq!(triangle.x, corner);
+        assert_eq!(triangle.y, Point { x: 0.0, y: 1.0 });
+
+        rustflame_affine_scale(&mut affine, 2.0, 2.0, triangle.o);
+        assert_eq!(affine.xx, 1.0);
+        assert_eq!(
+            rustflame_affine_move_vertex(&mut affine, 3, corner),
+            RustflameStatus::InvalidArgument
+        );
+        assert_eq!(
+            rustflame_affine_rotate(&mut affine, f64::NAN, corner),
+            RustflameStatus::InvalidArgument
+        );
+
+        let mut back = affine;
+        rustflame_affine_from_triangle(affine.triangle(), &mut back);
+        assert_eq!(back, affine);
+    }
+}
+
+#[test]
+fn thumbnails_into_caller_buffers() {
+    unsafe {
+        let flame = new_flame("Thumbnail");
+        rustflame_flame_add_affine_transform(flame, 0.5, 0.0, 0.0, 0.5, 0.5, 0.0, 1.0, 0.0, 0.0);
+        rustflame_flame_add_affine_transform(flame, 0.5, 0.0, 0.0, 0.5, -0.5, 0.5, 0.0, 1.0, 0.0);
+        let mut len = 0;
+        let mut pixels = vec![0u8; 10];
+        assert_eq!(
+            rustflame_thumbnail_render(
+                flame,
+                24,
+                16,
+                0,
+                PIXEL_FORMAT_RGBA32,
+                pixels.as_mut_ptr(),
+                pixels.len(),
+                &mut len
+            ),
+            RustflameStatus::BufferTooSmall
+        );
+        assert_eq!(len, 24 * 16 * 4);
+        pixels.resize(len, 0);
+        assert_eq!(
+            rustflame_thumbnail_render(
+                flame,
+                24,
+                16,
+                10,
+                PIXEL_FORMAT_RGBA32,
+                pixels.as_mut_ptr(),
+                pixels.len(),
+                &mut len
+            ),
+            RustflameStatus::Ok
+        );
+        assert!(pixels.chunks(4).any(|p| p[3] > 0));
+        rustflame_flame_free(flame);
+    }
+}
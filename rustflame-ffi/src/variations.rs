@@ -0,0 +1,89 @@
+use error::*;
+use rustflame::variations;
+use std::os::raw::c_char;
+
+/// Writes the number of registered variations to `count`.
+///
+/// # Safety
+///
+/// `count` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_variation_count(count: *mut usize) -> RustflameStatus {
+    guard(|| unsafe { write_out(count, variations::names().len(), "count") })
+}
+
+/// Copies the name of registered variation `index`, UTF-8 without a terminator, into a
+/// caller-allocated buffer. `len` receives the name's length whether or not it fit.
+///
+/// # Safety
+///
+/// `buffer` must be writable for `capacity` bytes and `len` writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_variation_name(
+    index: usize,
+    buffer: *mut u8,
+    capacity: usize,
+    len: *mut usize,
+) -> RustflameStatus {
+    guard(|| {
+        let names = variations::names();
+        let name = names.get(index).ok_or_else(|| {
+            FfiError::invalid(format!(
+                "variation {} is out of range, there are {}",
+                index,
+                names.len()
+            ))
+        })?;
+        unsafe { copy_out(name.as_bytes(), buffer, capacity, len) }
+    })
+}
+
+/// Writes the number of parameters the named variation takes to `count`.
+///
+/// # Safety
+///
+/// `variation` must be a NUL-terminated string and `count` writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_variation_param_count(
+    variation: *const c_char,
+    count: *mut usize,
+) -> RustflameStatus {
+    guard(|| {
+        let params = param_names(variation)?;
+        unsafe { write_out(count, params.len(), "count") }
+    })
+}
+
+/// Copies the name of parameter `index` of the named variation into a caller-allocated buffer, as
+/// `rustflame_variation_name` does.
+///
+/// # Safety
+///
+/// `variation` must be a NUL-terminated string, `buffer` writable for `capacity` bytes and `len`
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn rustflame_variation_param_name(
+    variation: *const c_char,
+    index: usize,
+    buffer: *mut u8,
+    capacity: usize,
+    len: *mut usize,
+) -> RustflameStatus {
+    guard(|| {
+        let params = param_names(variation)?;
+        let param = params.get(index).ok_or_else(|| {
+            FfiError::invalid(format!(
+                "parameter {} is out of range, the variation has {}",
+                index,
+                params.len()
+            ))
+        })?;
+        unsafe { copy_out(param.as_bytes(), buffer, capacity, len) }
+    })
+}
+
+fn param_names(variation: *const c_char) -> FfiResult<Vec<&'static str>> {
+    let variation = unsafe { str_arg(variation, "variation") }?;
+    variations::param_names(variation)
+        .ok_or_else(|| FfiError::invalid(format!("no variation is named {}", variation)))
+}
@@ -0,0 +1,277 @@
+//! Rendering every flame in a set of genome files, for collections too big to render one by one.
+
+use genome;
+use genome::Genome;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use Config;
+use OutputFormat;
+
+/// What happened to one flame, or to a file that couldn't be read.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Rendered(Duration),
+    /// The output already existed.
+    Skipped,
+    Failed(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Rendered(time) => write!(f, "rendered in {:.1}s", time.as_secs_f64()),
+            Outcome::Skipped => write!(f, "skipped, it already exists"),
+            Outcome::Failed(e) => write!(f, "failed: {}", e),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub input: String,
+    /// Empty for files that couldn't be read.
+    pub flame: String,
+    pub output: String,
+    pub outcome: Outcome,
+}
+
+/// Everything a batch did, in input order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Summary {
+    pub entries: Vec<Entry>,
+    pub elapsed: Duration,
+}
+
+impl Summary {
+    pub fn rendered(&self) -> usize {
+        self.count(|o| matches!(o, Outcome::Rendered(_)))
+    }
+    pub fn skipped(&self) -> usize {
+        self.count(|o| *o == Outcome::Skipped)
+    }
+    pub fn failed(&self) -> usize {
+        self.count(|o| matches!(o, Outcome::Failed(_)))
+    }
+    fn count<F: Fn(&Outcome) -> bool>(&self, f: F) -> usize {
+        self.entries.iter().filter(|e| f(&e.outcome)).count()
+    }
+
+    /// Writes one tab-separated line per entry, under a header: input, flame, output, status,
+    /// seconds and error.
+    pub fn write_report<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, "input\tflame\toutput\tstatus\tseconds\terror")?;
+        for entry in &self.entries {
+            let (status, seconds, error) = match entry.outcome {
+                Outcome::Rendered(time) => ("rendered", time.as_secs_f64(), ""),
+                Outcome::Skipped => ("skipped", 0.0, ""),
+                Outcome::Failed(ref e) => ("failed", 0.0, &e[..]),
+            };
+            writeln!(
+                out,
+                "{}\t{}\t{}\t{}\t{:.3}\t{}",
+                field(&entry.input),
+                field(&entry.flame),
+                field(&entry.output),
+                status,
+                seconds,
+                field(error)
+            )?;
+        }
+        out.flush()
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} rendered, {} skipped, {} failed in {:.1}s",
+            self.rendered(),
+            self.skipped(),
+            self.failed(),
+            self.elapsed.as_secs_f64()
+        )
+    }
+}
+
+struct Job {
+    /// Where the entry goes in the summary.
+    slot: usize,
+    output: String,
+    genome: Genome,
+    format: OutputFormat,
+}
+
+/// Renders every flame in every input, reading directories for their `.flame` and `.json` files.
+/// Outputs that already exist are skipped unless `config.overwrite` is set. `config.jobs` flames
+/// render at once, each on `config.threads` threads. A flame that fails doesn't stop the others. A
+/// flame whose output an earlier flame already claimed fails rather than overwrite it.
+pub fn run(config: &Config) -> Summary {
+    let start = Instant::now();
+    let mut entries = Vec::new();
+    let mut jobs = VecDeque::new();
+    //Each output path, and the entry that claimed it first.
+    let mut claimed: HashMap<String, usize> = HashMap::new();
+    for input in expand_inputs(&config.inputs, &mut entries) {
+        let genomes = match genome::import_file(Path::new(&input)) {
+            Ok(import) => {
+                for warning in &import.warnings {
+                    println!("{}: {}", input, warning);
+                }
+                import.genomes
+            }
+            Err(e) => {
+                entries.push(Entry {
+                    input,
+                    flame: String::new(),
+                    output: String::new(),
+                    outcome: Outcome::Failed(e.to_string()),
+                });
+                continue;
+            }
+        };
+        for (index, genome) in genomes.into_iter().enumerate() {
+            let output = config.output_path(&input, index, genome.flame.name());
+            let exists = Path::new(&output).exists();
+            entries.push(Entry {
+                input: input.clone(),
+                flame: String::from(genome.flame.name()),
+                output: output.clone(),
+                outcome: Outcome::Skipped,
+            });
+            if let Some(&first) = claimed.get(&output) {
+                let outcome = Outcome::Failed(format!(
+                    "output collides with {} in {}",
+                    entries[first].flame, entries[first].input
+                ));
+                entries.last_mut().unwrap().outcome = outcome;
+                continue;
+            }
+            claimed.insert(output.clone(), entries.len() - 1);
+            if exists && !config.overwrite {
+                continue;
+            }
+            jobs.push_back(Job {
+                slot: entries.len() - 1,
+                format: config
+                    .format
+                    .unwrap_or_else(|| OutputFormat::from_path(&output)),
+                output,
+                genome,
+            });
+        }
+    }
+
+    let workers = config.jobs().min(jobs.len()).max(1);
+    let jobs = Arc::new(Mutex::new(jobs));
+    let handles: Vec<_> = (0..workers)
+        .map(|_| {
+            let config = config.clone();
+            let jobs = jobs.clone();
+            thread::spawn(move || {
+                let mut outcomes = Vec::new();
+                loop {
+                    let job = match jobs.lock().unwrap().pop_front() {
+                        Some(job) => job,
+                        None => return outcomes,
+                    };
+                    //One bad genome shouldn't take the rest of the batch down with it.
+                    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+                        render(&config, &job, workers > 1)
+                    }))
+                    .unwrap_or_else(|_| Outcome::Failed(String::from("the render panicked")));
+                    println!("{}: {}", job.output, outcome);
+                    outcomes.push((job.slot, outcome));
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        for (slot, outcome) in handle.join().expect("a batch worker panicked") {
+            entries[slot].outcome = outcome;
+        }
+    }
+    Summary {
+        entries,
+        elapsed: start.elapsed(),
+    }
+}
+
+fn render(config: &Config, job: &Job, quiet: bool) -> Outcome {
+    let output = &job.output;
+    let start = Instant::now();
+    let mut renderer = match config.renderer(&job.genome) {
+        Ok(renderer) => renderer,
+        Err(e) => return Outcome::Failed(e.to_string()),
+    };
+    renderer.set_verbose(!quiet);
+    if let Some(dir) = Path::new(output).parent() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            return Outcome::Failed(e.to_string());
+        }
+    }
+    let genome = config.genome(&job.genome);
+    match ::render_to(
+        &mut renderer,
+        &genome,
+        job.format,
+        config.threads,
+        config.tile_rows,
+        output,
+    ) {
+        Ok(()) => Outcome::Rendered(start.elapsed()),
+        Err(e) => Outcome::Failed(e.to_string()),
+    }
+}
+
+/// Replaces directories with the genome files in them, in name order. Directories that can't be
+/// read are recorded as failures.
+fn expand_inputs(inputs: &[String], entries: &mut Vec<Entry>) -> Vec<String> {
+    let mut files = Vec::new();
+    for input in inputs {
+        if !Path::new(input).is_dir() {
+            files.push(input.clone());
+            continue;
+        }
+        let listing = fs::read_dir(input).and_then(|dir| {
+            dir.map(|entry| entry.map(|e| e.path()))
+                .collect::<io::Result<Vec<_>>>()
+        });
+        match listing {
+            Ok(mut paths) => {
+                paths.retain(|p| {
+                    p.extension().map_or(false, |ext| {
+                        let ext = ext.to_string_lossy();
+                        ext.eq_ignore_ascii_case("flame") || ext.eq_ignore_ascii_case("json")
+                    })
+                });
+                paths.sort();
+                files.extend(paths.iter().map(|p| p.to_string_lossy().into_owned()));
+            }
+            Err(e) => entries.push(Entry {
+                input: input.clone(),
+                flame: String::new(),
+                output: String::new(),
+                outcome: Outcome::Failed(e.to_string()),
+            }),
+        }
+    }
+    files
+}
+
+/// Keeps a report field on one line and in one column.
+fn field(s: &str) -> String {
+    s.replace(|c: char| c == '\t' || c == '\n' || c == '\r', " ")
+}
@@ -0,0 +1,130 @@
+extern crate rustflame;
+
+use rustflame::extract;
+use rustflame::extract::Image;
+use rustflame::extract::SortKey;
+use rustflame::extract::Strategy;
+use rustflame::palette;
+use std::path::Path;
+use std::process;
+
+const USAGE: &str = "\
+usage: rustflame-palette [options] <image.png>
+
+Builds a 256 color flame palette from an image, by default from its eight dominant colors.
+
+  -o, --output PATH      where to write the palette, default the image's name with .ugr; the
+                         extension picks the format: .ugr, .gradient, .map, .xml (flam3) or .ggr
+      --name NAME        the palette's name, default the image's name without extension
+      --line X0,Y0,X1,Y1 the colors along a line, in pixels from the top left
+      --path X,Y;X,Y...  the colors along a path through several points
+      --sort KEY         every pixel, sorted by hue or luminance
+      --kmeans K         the image's K dominant colors, ordered by luminance
+  -h, --help             show this message
+";
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 2 {
+        print!("{}", USAGE);
+        return;
+    }
+    let (image, output, name, strategy) = match parse(&args) {
+        Ok(Some(parsed)) => parsed,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("rustflame-palette: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    let written = Image::load(Path::new(&image))
+        .and_then(|pixels| extract::palette_from_image(&pixels, &strategy, &name))
+        .and_then(|palette| palette::save(Path::new(&output), &[palette]));
+    if let Err(e) = written {
+        eprintln!("rustflame-palette: {}", e);
+        process::exit(1);
+    }
+    println!("Wrote \"{}\" to {}", name, output);
+}
+
+/// The image, output path, palette name and strategy, or `None` if help was asked for.
+fn parse(args: &[String]) -> Result<Option<(String, String, String, Strategy)>, String> {
+    let mut image = None;
+    let mut output = None;
+    let mut name = None;
+    let mut strategy = Strategy::KMeans(8);
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') {
+            if image.replace(arg.clone()).is_some() {
+                return Err(String::from("give one image"));
+            }
+            continue;
+        }
+        let option = &arg[..];
+        //Taken only once the option is known, so a stray flag is reported as unknown.
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} needs a value", option))
+        };
+        match option {
+            "-h" | "--help" => return Ok(None),
+            "-o" | "--output" => output = Some(value()?),
+            "--name" => name = Some(value()?),
+            "--line" => {
+                let value = value()?;
+                let numbers = numbers(option, &value.replace(';', ","))?;
+                if numbers.len() != 4 {
+                    return Err(format!("{} takes X0,Y0,X1,Y1, not {}", option, value));
+                }
+                strategy = Strategy::Path(vec![(numbers[0], numbers[1]), (numbers[2], numbers[3])]);
+            }
+            "--path" => {
+                let value = value()?;
+                let points = value
+                    .split(';')
+                    .map(|point| match numbers(option, point)?[..] {
+                        [x, y] => Ok((x, y)),
+                        _ => Err(format!("{} takes X,Y points, not {}", option, point)),
+                    })
+                    .collect::<Result<_, _>>()?;
+                strategy = Strategy::Path(points);
+            }
+            "--sort" => {
+                strategy = Strategy::Sorted(match &value()?[..] {
+                    "hue" => SortKey::Hue,
+                    "luminance" => SortKey::Luminance,
+                    other => return Err(format!("can't sort by {}", other)),
+                })
+            }
+            "--kmeans" => match value()?.parse() {
+                Ok(k) if k > 0 => strategy = Strategy::KMeans(k),
+                _ => return Err(format!("{} must be a positive whole number", option)),
+            },
+            _ => return Err(format!("unknown option {}", option)),
+        }
+    }
+    let image = image.ok_or_else(|| String::from("no image given"))?;
+    let stem = Path::new(&image)
+        .file_stem()
+        .map_or(String::from("palette"), |s| {
+            s.to_string_lossy().into_owned()
+        });
+    let output = output.unwrap_or_else(|| format!("{}.ugr", stem));
+    palette::Format::from_path(Path::new(&output)).map_err(|e| e.to_string())?;
+    Ok(Some((image, output, name.unwrap_or(stem), strategy)))
+}
+
+fn numbers(option: &str, text: &str) -> Result<Vec<f64>, String> {
+    text.split(',')
+        .map(|n| {
+            n.trim()
+                .parse()
+                .map_err(|_| format!("{} can't read {} as a number", option, n))
+        })
+        .collect()
+}
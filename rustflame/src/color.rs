@@ -1,102 +1,518 @@
-use std::cmp;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+/// A channel given to a color constructor that's out of its range, or not a number.
 #[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ColorError {
+    /// Which channel: "red", "green", "blue", "hue", "saturation" or "value".
+    pub channel: &'static str,
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl fmt::Display for ColorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} is {}, and must be from {} to {}",
+            self.channel, self.value, self.min, self.max
+        )
+    }
+}
+
+impl Error for ColorError {}
+
+fn check(channel: &'static str, value: f32, min: f32, max: f32) -> Result<f32, ColorError> {
+    if value >= min && value <= max {
+        Ok(value)
+    } else {
+        Err(ColorError {
+            channel,
+            value,
+            min,
+            max,
+        })
+    }
+}
+
+/// Around the color wheel into 0 up to but not including 360, with NaN and the infinities as 0.
+fn wrap_hue(h: f32) -> f32 {
+    let h = h.rem_euclid(360.0);
+    //A hue a hair below 0 wraps to 360 when rounded.
+    if (0.0..360.0).contains(&h) {
+        h
+    } else {
+        0.0
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(try_from = "RgbChannels")]
 pub struct ColorFRGB {
     pub r: f32,
     pub g: f32,
     pub b: f32,
 }
 
+/// The channels a `ColorFRGB` is read from, checked by `try_from` on the way in.
+#[derive(Deserialize)]
+struct RgbChannels {
+    r: f32,
+    g: f32,
+    b: f32,
+}
+
+impl TryFrom<RgbChannels> for ColorFRGB {
+    type Error = ColorError;
+
+    fn try_from(c: RgbChannels) -> Result<ColorFRGB, ColorError> {
+        ColorFRGB::try_from((c.r, c.g, c.b))
+    }
+}
+
 impl ColorFRGB {
+    /// `None` unless every channel is from 0 to 1. `try_from` says which isn't.
     pub fn new(red: f32, green: f32, blue: f32) -> Option<ColorFRGB> {
-        if red > 1.0 || red < 0.0 || green > 1.0 || green < 0.0 || blue > 1.0 || blue < 0.0 {
-            None
-        } else {
-            Some(ColorFRGB {
-                r: red,
-                g: green,
-                b: blue,
-            })
-        }
+        ColorFRGB::try_from((red, green, blue)).ok()
+    }
+
+    /// Channels outside 0 to 1 are clamped to them, and NaN is taken as 0.
+    pub fn clamped(red: f32, green: f32, blue: f32) -> ColorFRGB {
+        unit_color(red.into(), green.into(), blue.into())
+    }
+}
+
+/// Into 0 to 1, with NaN as 0.
+pub(crate) fn clamp_unit(v: f64) -> f64 {
+    if v > 0.0 {
+        v.min(1.0)
+    } else {
+        0.0
+    }
+}
+
+/// `ColorFRGB::clamped`, from channels worked out in f64.
+pub(crate) fn unit_color(r: f64, g: f64, b: f64) -> ColorFRGB {
+    ColorFRGB {
+        r: clamp_unit(r) as f32,
+        g: clamp_unit(g) as f32,
+        b: clamp_unit(b) as f32,
     }
 }
 
+/// The color `t` of the way from `a` to `b`.
+pub(crate) fn lerp(a: ColorFRGB, b: ColorFRGB, t: f64) -> ColorFRGB {
+    let t = t as f32;
+    ColorFRGB {
+        r: a.r + (b.r - a.r) * t,
+        g: a.g + (b.g - a.g) * t,
+        b: a.b + (b.b - a.b) * t,
+    }
+}
+
+/// The mean of each channel. `colors` can't be empty.
+pub(crate) fn average(colors: &[ColorFRGB]) -> ColorFRGB {
+    let n = colors.len() as f32;
+    let (r, g, b) = colors
+        .iter()
+        .fold((0.0, 0.0, 0.0), |(r, g, b), c| (r + c.r, g + c.g, b + c.b));
+    ColorFRGB {
+        r: r / n,
+        g: g / n,
+        b: b / n,
+    }
+}
+
+/// Red, green and blue, each from 0 to 1.
+impl TryFrom<(f32, f32, f32)> for ColorFRGB {
+    type Error = ColorError;
+
+    fn try_from((red, green, blue): (f32, f32, f32)) -> Result<ColorFRGB, ColorError> {
+        Ok(ColorFRGB {
+            r: check("red", red, 0.0, 1.0)?,
+            g: check("green", green, 0.0, 1.0)?,
+            b: check("blue", blue, 0.0, 1.0)?,
+        })
+    }
+}
+
+/// Colors out of range are wrapped and clamped into it first, as by `ColorFHSV::wrapped`.
 impl From<ColorFHSV> for ColorFRGB {
     fn from(hsv: ColorFHSV) -> ColorFRGB {
+        let hsv = ColorFHSV::wrapped(hsv.h, hsv.s, hsv.v);
         let chrominance = hsv.v * hsv.s;
-        let sextant = (hsv.h / 60.0).floor() as u8;
+        //Hues a hair below 360 can round up to the seventh sextant, which is red again.
+        let sextant = (hsv.h / 60.0).floor() as u8 % 6;
         let min = hsv.v - chrominance;
         let mid = (chrominance * (1.0 - (((hsv.h / 60.0) % 2.0) - 1.0).abs())) + min;
-        let max = chrominance + min; //should equal value.
-        match sextant {
-            0 => ColorFRGB::new(max, mid, min)
-                .unwrap_or_else(|| panic!("Error in HSV to RGB conversion. This is a bug.")),
-            1 => ColorFRGB::new(mid, max, min)
-                .unwrap_or_else(|| panic!("Error in HSV to RGB conversion. This is a bug.")),
-            2 => ColorFRGB::new(min, max, mid)
-                .unwrap_or_else(|| panic!("Error in HSV to RGB conversion. This is a bug.")),
-            3 => ColorFRGB::new(min, mid, max)
-                .unwrap_or_else(|| panic!("Error in HSV to RGB conversion. This is a bug.")),
-            4 => ColorFRGB::new(mid, min, max)
-                .unwrap_or_else(|| panic!("Error in HSV to RGB conversion. This is a bug.")),
-            5 => ColorFRGB::new(max, min, mid)
-                .unwrap_or_else(|| panic!("Error in HSV to RGB conversion. This is a bug.")),
-            _ => panic!("Invalid sextant in HSV to RGB conversion. This is a bug."),
-        }
+        let max = hsv.v;
+        let (r, g, b) = match sextant {
+            0 => (max, mid, min),
+            1 => (mid, max, min),
+            2 => (min, max, mid),
+            3 => (min, mid, max),
+            4 => (mid, min, max),
+            _ => (max, min, mid),
+        };
+        //Rounding can take a channel a hair outside 0 to 1.
+        ColorFRGB::clamped(r, g, b)
     }
 }
-#[derive(Copy, Clone, PartialEq, Debug)]
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(try_from = "HsvChannels")]
 pub struct ColorFHSV {
     pub h: f32,
     pub s: f32,
     pub v: f32,
 }
 
+/// The channels a `ColorFHSV` is read from, checked by `try_from` on the way in.
+#[derive(Deserialize)]
+struct HsvChannels {
+    h: f32,
+    s: f32,
+    v: f32,
+}
+
+impl TryFrom<HsvChannels> for ColorFHSV {
+    type Error = ColorError;
+
+    fn try_from(c: HsvChannels) -> Result<ColorFHSV, ColorError> {
+        ColorFHSV::try_from((c.h, c.s, c.v))
+    }
+}
+
 impl ColorFHSV {
+    /// `None` unless hue is from 0 to 360 and saturation and value from 0 to 1. `try_from` says
+    /// which isn't.
     pub fn new(hue: f32, saturation: f32, value: f32) -> Option<ColorFHSV> {
-        if hue > 360.0 || hue < 0.0 || saturation > 1.0 || saturation < 0.0 || value > 1.0
-            || value < 0.0
-        {
-            None
-        } else {
-            Some(ColorFHSV {
-                h: hue,
-                s: saturation,
-                v: value,
-            })
+        ColorFHSV::try_from((hue, saturation, value)).ok()
+    }
+
+    /// Hue is wrapped around the color wheel into 0 up to 360, saturation and value are clamped to
+    /// 0 to 1, and NaN is taken as 0.
+    pub fn wrapped(hue: f32, saturation: f32, value: f32) -> ColorFHSV {
+        ColorFHSV {
+            h: wrap_hue(hue),
+            s: clamp_unit(saturation.into()) as f32,
+            v: clamp_unit(value.into()) as f32,
         }
     }
 }
 
+/// Hue from 0 to 360, then saturation and value from 0 to 1.
+impl TryFrom<(f32, f32, f32)> for ColorFHSV {
+    type Error = ColorError;
+
+    fn try_from((hue, saturation, value): (f32, f32, f32)) -> Result<ColorFHSV, ColorError> {
+        Ok(ColorFHSV {
+            h: check("hue", hue, 0.0, 360.0)?,
+            s: check("saturation", saturation, 0.0, 1.0)?,
+            v: check("value", value, 0.0, 1.0)?,
+        })
+    }
+}
+
+/// Colors out of range are clamped into it first, as by `ColorFRGB::clamped`.
 impl From<ColorFRGB> for ColorFHSV {
     fn from(c: ColorFRGB) -> ColorFHSV {
+        let c = ColorFRGB::clamped(c.r, c.g, c.b);
         let max = c.r.max(c.g.max(c.b));
         let min = c.r.min(c.g.min(c.b));
         let chrominance = max - min;
-        let saturation = chrominance / max;
-        let hue;
-        if max == 0.0 {
-            return ColorFHSV {
-                h: 0.0,
-                s: 0.0,
-                v: 0.0,
-            };
-        }
         if chrominance == 0.0 {
+            //Grays, black included, have no hue.
             return ColorFHSV {
                 h: 0.0,
                 s: 0.0,
                 v: max,
             };
         }
-        if max == c.r {
-            hue = 60.0 * (((c.g - c.b) / chrominance) % 6.0);
+        let hue = if max == c.r {
+            //Reds leaning towards blue come out negative, and wrap around to the magentas.
+            60.0 * (((c.g - c.b) / chrominance) % 6.0)
         } else if max == c.g {
-            hue = 60.0 * (((c.b - c.r) / chrominance) + 2.0);
+            60.0 * (((c.b - c.r) / chrominance) + 2.0)
         } else {
-            //max == c.b
-            hue = 60.0 * (((c.r - c.g) / chrominance) + 4.0);
+            60.0 * (((c.r - c.g) / chrominance) + 4.0)
+        };
+        ColorFHSV::wrapped(hue, chrominance / max, max)
+    }
+}
+
+//`ColorFRGB` holds sRGB, gamma encoded as images are. Blending or averaging it darkens and muddies
+//colors; the spaces below don't.
+
+/// Decodes an sRGB channel to linear light, both from 0 to 1.
+pub fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes a channel of linear light as sRGB, both from 0 to 1.
+pub fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+impl ColorFRGB {
+    /// The color's channels in linear light, for blending and accumulating.
+    pub fn to_linear(self) -> ColorFRGB {
+        ColorFRGB {
+            r: srgb_to_linear(self.r),
+            g: srgb_to_linear(self.g),
+            b: srgb_to_linear(self.b),
+        }
+    }
+
+    /// The sRGB color with these channels in linear light.
+    pub fn from_linear(linear: ColorFRGB) -> ColorFRGB {
+        ColorFRGB {
+            r: linear_to_srgb(linear.r),
+            g: linear_to_srgb(linear.g),
+            b: linear_to_srgb(linear.b),
+        }
+    }
+
+    /// Channels in linear light outside 0 to 1, as colors from the wider spaces can come out,
+    /// are clipped to them.
+    fn from_linear_clipped(r: f32, g: f32, b: f32) -> ColorFRGB {
+        let c = ColorFRGB::from_linear(ColorFRGB { r, g, b });
+        ColorFRGB::clamped(c.r, c.g, c.b)
+    }
+}
+
+/// CIE 1931 XYZ under the D65 white point, with white's Y at 1.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ColorXYZ {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+/// D65 white, which sRGB's white is.
+const WHITE: ColorXYZ = ColorXYZ {
+    x: 0.950_47,
+    y: 1.0,
+    z: 1.088_83,
+};
+
+impl From<ColorFRGB> for ColorXYZ {
+    fn from(c: ColorFRGB) -> ColorXYZ {
+        let ColorFRGB { r, g, b } = c.to_linear();
+        ColorXYZ {
+            x: 0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b,
+            y: 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b,
+            z: 0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b,
+        }
+    }
+}
+
+/// Colors outside sRGB are clipped to it.
+impl From<ColorXYZ> for ColorFRGB {
+    fn from(c: ColorXYZ) -> ColorFRGB {
+        ColorFRGB::from_linear_clipped(
+            3.240_454_2 * c.x - 1.537_138_5 * c.y - 0.498_531_4 * c.z,
+            -0.969_266 * c.x + 1.876_010_8 * c.y + 0.041_556 * c.z,
+            0.055_643_4 * c.x - 0.204_025_9 * c.y + 1.057_225_2 * c.z,
+        )
+    }
+}
+
+/// CIE L*a*b* under D65: lightness from 0 to 100, then green to red and blue to yellow.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ColorLab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+/// Where Lab's cube root gives way to a straight line near black.
+const LAB_DELTA: f32 = 6.0 / 29.0;
+
+impl From<ColorXYZ> for ColorLab {
+    fn from(c: ColorXYZ) -> ColorLab {
+        let f = |t: f32| {
+            if t > LAB_DELTA.powi(3) {
+                t.cbrt()
+            } else {
+                t / (3.0 * LAB_DELTA * LAB_DELTA) + 4.0 / 29.0
+            }
+        };
+        let (fx, fy, fz) = (f(c.x / WHITE.x), f(c.y / WHITE.y), f(c.z / WHITE.z));
+        ColorLab {
+            l: 116.0 * fy - 16.0,
+            a: 500.0 * (fx - fy),
+            b: 200.0 * (fy - fz),
         }
-        ColorFHSV::new(hue, saturation, max)
-            .unwrap_or_else(|| panic!("Error in RGB to HSV conversion. This is a bug."))
     }
 }
+
+impl From<ColorLab> for ColorXYZ {
+    fn from(c: ColorLab) -> ColorXYZ {
+        let f = |t: f32| {
+            if t > LAB_DELTA {
+                t.powi(3)
+            } else {
+                3.0 * LAB_DELTA * LAB_DELTA * (t - 4.0 / 29.0)
+            }
+        };
+        let fy = (c.l + 16.0) / 116.0;
+        ColorXYZ {
+            x: WHITE.x * f(fy + c.a / 500.0),
+            y: WHITE.y * f(fy),
+            z: WHITE.z * f(fy - c.b / 200.0),
+        }
+    }
+}
+
+impl From<ColorFRGB> for ColorLab {
+    fn from(c: ColorFRGB) -> ColorLab {
+        ColorLab::from(ColorXYZ::from(c))
+    }
+}
+
+/// Colors outside sRGB are clipped to it.
+impl From<ColorLab> for ColorFRGB {
+    fn from(c: ColorLab) -> ColorFRGB {
+        ColorFRGB::from(ColorXYZ::from(c))
+    }
+}
+
+/// CIE LCh(ab), Lab in polar form: lightness, chroma and hue in degrees from 0 to 360.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ColorLCh {
+    pub l: f32,
+    pub c: f32,
+    pub h: f32,
+}
+
+impl From<ColorLab> for ColorLCh {
+    fn from(c: ColorLab) -> ColorLCh {
+        let (chroma, hue) = to_polar(c.a, c.b);
+        ColorLCh {
+            l: c.l,
+            c: chroma,
+            h: hue,
+        }
+    }
+}
+
+impl From<ColorLCh> for ColorLab {
+    fn from(c: ColorLCh) -> ColorLab {
+        let (a, b) = from_polar(c.c, c.h);
+        ColorLab { l: c.l, a, b }
+    }
+}
+
+impl From<ColorFRGB> for ColorLCh {
+    fn from(c: ColorFRGB) -> ColorLCh {
+        ColorLCh::from(ColorLab::from(c))
+    }
+}
+
+/// Colors outside sRGB are clipped to it.
+impl From<ColorLCh> for ColorFRGB {
+    fn from(c: ColorLCh) -> ColorFRGB {
+        ColorFRGB::from(ColorLab::from(c))
+    }
+}
+
+/// Björn Ottosson's OKLab: lightness from 0 to 1, then green to red and blue to yellow. More
+/// even than CIE Lab, notably in its blues.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ColorOKLab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+impl From<ColorFRGB> for ColorOKLab {
+    fn from(c: ColorFRGB) -> ColorOKLab {
+        let ColorFRGB { r, g, b } = c.to_linear();
+        let l = (0.412_221_47 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+        let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+        let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+        ColorOKLab {
+            l: 0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+            a: 1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+            b: 0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+        }
+    }
+}
+
+/// Colors outside sRGB are clipped to it.
+impl From<ColorOKLab> for ColorFRGB {
+    fn from(c: ColorOKLab) -> ColorFRGB {
+        let l = (c.l + 0.396_337_78 * c.a + 0.215_803_76 * c.b).powi(3);
+        let m = (c.l - 0.105_561_346 * c.a - 0.063_854_17 * c.b).powi(3);
+        let s = (c.l - 0.089_484_18 * c.a - 1.291_485_5 * c.b).powi(3);
+        ColorFRGB::from_linear_clipped(
+            4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s,
+            -1.268_438 * l + 2.609_757_4 * m - 0.341_319_4 * s,
+            -0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s,
+        )
+    }
+}
+
+/// OKLab in polar form: lightness, chroma and hue in degrees from 0 to 360.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ColorOKLCh {
+    pub l: f32,
+    pub c: f32,
+    pub h: f32,
+}
+
+impl From<ColorOKLab> for ColorOKLCh {
+    fn from(c: ColorOKLab) -> ColorOKLCh {
+        let (chroma, hue) = to_polar(c.a, c.b);
+        ColorOKLCh {
+            l: c.l,
+            c: chroma,
+            h: hue,
+        }
+    }
+}
+
+impl From<ColorOKLCh> for ColorOKLab {
+    fn from(c: ColorOKLCh) -> ColorOKLab {
+        let (a, b) = from_polar(c.c, c.h);
+        ColorOKLab { l: c.l, a, b }
+    }
+}
+
+impl From<ColorFRGB> for ColorOKLCh {
+    fn from(c: ColorFRGB) -> ColorOKLCh {
+        ColorOKLCh::from(ColorOKLab::from(c))
+    }
+}
+
+/// Colors outside sRGB are clipped to it.
+impl From<ColorOKLCh> for ColorFRGB {
+    fn from(c: ColorOKLCh) -> ColorFRGB {
+        ColorFRGB::from(ColorOKLab::from(c))
+    }
+}
+
+/// Chroma and hue in degrees from 0 to 360. Grays have hue 0.
+fn to_polar(a: f32, b: f32) -> (f32, f32) {
+    let hue = b.atan2(a).to_degrees();
+    (
+        (a * a + b * b).sqrt(),
+        if hue < 0.0 { hue + 360.0 } else { hue },
+    )
+}
+
+fn from_polar(chroma: f32, hue: f32) -> (f32, f32) {
+    let (sin, cos) = hue.to_radians().sin_cos();
+    (chroma * cos, chroma * sin)
+}
@@ -0,0 +1,319 @@
+//! Palettes taken from images, for matching a flame's colors to a reference photo.
+
+extern crate lodepng;
+
+use self::lodepng::Bitmap;
+use self::lodepng::RGBA;
+use color::average;
+use color::lerp;
+use color::ColorFHSV;
+use color::ColorFRGB;
+use palette::Palette;
+use palette::PaletteError;
+use palette::PALETTE_SIZE;
+use rand::Rng;
+use rand::SeedableRng;
+use rand::XorShiftRng;
+use std::cmp::Ordering;
+use std::path::Path;
+
+/// K-means works on at most this many pixels, spread evenly over the image.
+const MAX_CLUSTERED_PIXELS: usize = 1 << 16;
+const KMEANS_ROUNDS: usize = 32;
+
+/// An image's colors, row by row from the top left.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<ColorFRGB>,
+    /// From 0 to 1 for each pixel. Pixels less than half opaque are left out of sorting and
+    /// clustering.
+    pub alpha: Vec<f32>,
+}
+
+impl Image {
+    /// An opaque image. `pixels` must hold `width * height` colors.
+    pub fn new(width: usize, height: usize, pixels: Vec<ColorFRGB>) -> Image {
+        assert_eq!(pixels.len(), width * height, "the image is the wrong size");
+        Image {
+            width,
+            height,
+            alpha: vec![1.0; pixels.len()],
+            pixels,
+        }
+    }
+
+    pub fn from_bitmap(bitmap: &Bitmap<RGBA<u8>>) -> Image {
+        let channel = |v: u8| v as f32 / 255.0;
+        Image {
+            width: bitmap.width,
+            height: bitmap.height,
+            pixels: bitmap
+                .buffer
+                .iter()
+                .map(|p| ColorFRGB {
+                    r: channel(p.r),
+                    g: channel(p.g),
+                    b: channel(p.b),
+                })
+                .collect(),
+            alpha: bitmap.buffer.iter().map(|p| channel(p.a)).collect(),
+        }
+    }
+
+    /// Reads a PNG.
+    pub fn load(path: &Path) -> Result<Image, PaletteError> {
+        lodepng::decode32_file(path)
+            .map(|bitmap| Image::from_bitmap(&bitmap))
+            .map_err(|e| PaletteError::Image(format!("{}: {}", path.display(), e)))
+    }
+
+    /// The color at a point in pixel coordinates, blended from the nearest four pixels. Points
+    /// outside the image take the color at its edge.
+    pub fn sample(&self, x: f64, y: f64) -> ColorFRGB {
+        let clamp = |v: f64, size: usize| {
+            if v.is_nan() {
+                0.0
+            } else {
+                v.clamp(0.0, (size - 1) as f64)
+            }
+        };
+        let (x, y) = (clamp(x, self.width), clamp(y, self.height));
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+        let at = |x: usize, y: usize| self.pixels[y * self.width + x];
+        let top = lerp(at(x0, y0), at(x1, y0), fx);
+        let bottom = lerp(at(x0, y1), at(x1, y1), fx);
+        lerp(top, bottom, fy)
+    }
+
+    fn opaque_pixels(&self) -> Vec<ColorFRGB> {
+        self.pixels
+            .iter()
+            .zip(&self.alpha)
+            .filter(|&(_, &a)| a >= 0.5)
+            .map(|(&p, _)| p)
+            .collect()
+    }
+}
+
+/// How to pick the palette's colors.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Strategy {
+    /// The colors along a line or path through the image, in pixel coordinates, evenly spaced by
+    /// distance. A line is a path of two points.
+    Path(Vec<(f64, f64)>),
+    /// Every opaque pixel, sorted and split into equal runs, each run giving its average color.
+    Sorted(SortKey),
+    /// The image's dominant colors, found by k-means clustering and ordered by luminance. Each
+    /// takes a share of the palette as big as its share of the image, blending into the next.
+    KMeans(usize),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SortKey {
+    /// Around the color wheel from red, ties going from dark to light.
+    Hue,
+    Luminance,
+}
+
+/// Builds a 256 color palette from an image, named `name`.
+pub fn palette_from_image(
+    image: &Image,
+    strategy: &Strategy,
+    name: &str,
+) -> Result<Palette, PaletteError> {
+    if image.pixels.is_empty() {
+        return Err(PaletteError::Image(String::from("the image is empty")));
+    }
+    let colors = match strategy {
+        Strategy::Path(points) => along_path(image, points)?,
+        Strategy::Sorted(key) => sorted(&opaque(image)?, *key),
+        Strategy::KMeans(clusters) => dominant(&opaque(image)?, *clusters),
+    };
+    Ok(Palette::new(name, colors))
+}
+
+fn opaque(image: &Image) -> Result<Vec<ColorFRGB>, PaletteError> {
+    let pixels = image.opaque_pixels();
+    if pixels.is_empty() {
+        return Err(PaletteError::Image(String::from(
+            "every pixel is transparent",
+        )));
+    }
+    Ok(pixels)
+}
+
+fn along_path(image: &Image, points: &[(f64, f64)]) -> Result<Vec<ColorFRGB>, PaletteError> {
+    if points.is_empty() {
+        return Err(PaletteError::Image(String::from("the path has no points")));
+    }
+    let lengths: Vec<f64> = points
+        .windows(2)
+        .map(|pair| ((pair[1].0 - pair[0].0).powi(2) + (pair[1].1 - pair[0].1).powi(2)).sqrt())
+        .collect();
+    let total: f64 = lengths.iter().sum();
+    let colors = (0..PALETTE_SIZE)
+        .map(|i| {
+            let mut distance = total * i as f64 / (PALETTE_SIZE - 1) as f64;
+            for (segment, &length) in lengths.iter().enumerate() {
+                if distance <= length && length > 0.0 {
+                    let t = distance / length;
+                    let (a, b) = (points[segment], points[segment + 1]);
+                    return image.sample(a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+                }
+                distance -= length;
+            }
+            let (x, y) = points[points.len() - 1];
+            image.sample(x, y)
+        })
+        .collect();
+    Ok(colors)
+}
+
+fn sorted(pixels: &[ColorFRGB], key: SortKey) -> Vec<ColorFRGB> {
+    let mut pixels = pixels.to_vec();
+    match key {
+        SortKey::Hue => {
+            let mut keyed: Vec<(f32, f32, ColorFRGB)> = pixels
+                .iter()
+                .map(|&p| (ColorFHSV::from(p).h, luminance(p), p))
+                .collect();
+            keyed.sort_by(|a, b| {
+                (a.0, a.1)
+                    .partial_cmp(&(b.0, b.1))
+                    .unwrap_or(Ordering::Equal)
+            });
+            pixels = keyed.into_iter().map(|(_, _, p)| p).collect();
+        }
+        SortKey::Luminance => pixels.sort_by(|&a, &b| {
+            luminance(a)
+                .partial_cmp(&luminance(b))
+                .unwrap_or(Ordering::Equal)
+        }),
+    }
+    (0..PALETTE_SIZE)
+        .map(|i| {
+            let start = i * pixels.len() / PALETTE_SIZE;
+            let end = ((i + 1) * pixels.len() / PALETTE_SIZE).max(start + 1);
+            average(&pixels[start..end.min(pixels.len())])
+        })
+        .collect()
+}
+
+fn dominant(pixels: &[ColorFRGB], clusters: usize) -> Vec<ColorFRGB> {
+    let stride = (pixels.len() / MAX_CLUSTERED_PIXELS).max(1);
+    let samples: Vec<ColorFRGB> = pixels.iter().step_by(stride).cloned().collect();
+    let mut centers = initial_centers(&samples, clusters.max(1).min(samples.len()));
+    let mut nearest = vec![0; samples.len()];
+    for _ in 0..KMEANS_ROUNDS {
+        let mut moved = false;
+        for (sample, cluster) in samples.iter().zip(nearest.iter_mut()) {
+            let closest = closest(&centers, *sample);
+            moved |= closest != *cluster;
+            *cluster = closest;
+        }
+        for (k, center) in centers.iter_mut().enumerate() {
+            let members: Vec<ColorFRGB> = samples
+                .iter()
+                .zip(&nearest)
+                .filter(|&(_, &c)| c == k)
+                .map(|(&s, _)| s)
+                .collect();
+            if !members.is_empty() {
+                *center = average(&members);
+            }
+        }
+        if !moved {
+            break;
+        }
+    }
+
+    let mut found: Vec<(ColorFRGB, usize)> = centers
+        .iter()
+        .enumerate()
+        .map(|(k, &center)| (center, nearest.iter().filter(|&&c| c == k).count()))
+        .filter(|&(_, size)| size > 0)
+        .collect();
+    found.sort_by(|a, b| {
+        luminance(a.0)
+            .partial_cmp(&luminance(b.0))
+            .unwrap_or(Ordering::Equal)
+    });
+    //Each color sits in the middle of its share of the palette, blending into its neighbors.
+    let mut stops = Vec::with_capacity(found.len());
+    let mut before = 0;
+    for &(color, size) in &found {
+        stops.push((
+            (before as f64 + size as f64 / 2.0) / samples.len() as f64,
+            color,
+        ));
+        before += size;
+    }
+    (0..PALETTE_SIZE)
+        .map(|i| {
+            let t = (i as f64 + 0.5) / PALETTE_SIZE as f64;
+            match stops.iter().position(|&(position, _)| position >= t) {
+                Some(0) => stops[0].1,
+                None => stops[stops.len() - 1].1,
+                Some(next) => {
+                    let (p0, c0) = stops[next - 1];
+                    let (p1, c1) = stops[next];
+                    lerp(c0, c1, (t - p0) / (p1 - p0))
+                }
+            }
+        })
+        .collect()
+}
+
+/// k-means++: each center is picked with odds growing with its distance from those already
+/// picked. Seeded, so the same image always gives the same palette.
+fn initial_centers(samples: &[ColorFRGB], clusters: usize) -> Vec<ColorFRGB> {
+    let mut rng = XorShiftRng::from_seed([0x9E37_79B9, 0x243F_6A88, 0x85A3_08D3, 0x1319_8A2E]);
+    let mut centers = vec![samples[rng.gen_range(0, samples.len())]];
+    let mut distances: Vec<f32> = samples.iter().map(|&s| distance(s, centers[0])).collect();
+    while centers.len() < clusters {
+        let total: f32 = distances.iter().sum();
+        if total <= 0.0 {
+            //Fewer distinct colors than clusters.
+            break;
+        }
+        let mut pick = rng.gen::<f32>() * total;
+        let mut chosen = samples.len() - 1;
+        for (i, &d) in distances.iter().enumerate() {
+            if pick < d {
+                chosen = i;
+                break;
+            }
+            pick -= d;
+        }
+        let center = samples[chosen];
+        centers.push(center);
+        for (d, &s) in distances.iter_mut().zip(samples) {
+            *d = d.min(distance(s, center));
+        }
+    }
+    centers
+}
+
+fn closest(centers: &[ColorFRGB], color: ColorFRGB) -> usize {
+    let mut best = 0;
+    for (k, &center) in centers.iter().enumerate() {
+        if distance(color, center) < distance(color, centers[best]) {
+            best = k;
+        }
+    }
+    best
+}
+
+/// Squared distance in RGB.
+fn distance(a: ColorFRGB, b: ColorFRGB) -> f32 {
+    (a.r - b.r).powi(2) + (a.g - b.g).powi(2) + (a.b - b.b).powi(2)
+}
+
+/// Rec. 709 luma.
+fn luminance(c: ColorFRGB) -> f32 {
+    0.2126 * c.r + 0.7152 * c.g + 0.0722 * c.b
+}
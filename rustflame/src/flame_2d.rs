@@ -4,18 +4,36 @@ extern crate rgb;
 
 use self::lodepng::Bitmap;
 use self::lodepng::RGB;
+use self::lodepng::RGBA;
 use color::ColorFRGB;
+use output::OutputPixel;
 use rand::Rng;
+use rand::SeedableRng;
+use rand::XorShiftRng;
+use std::error::Error;
+use std::fmt;
+use std::io;
 use std::iter::Sum;
 use std::ops::Add;
 use std::ops::Mul;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
 use std::time::Duration;
 use std::time::Instant;
+use variations;
+use variations::VariationError;
 
+#[derive(Clone)]
 pub struct Flame {
     name: String,
     pub transforms: Vec<Transform>,
     pub colors: Vec<ColorFRGB>,
+    /// How often each transform is picked, relative to the others.
+    pub weights: Vec<f64>,
+    /// Shown where the flame has no density. Ignored by transparent output.
+    pub background: ColorFRGB,
     //Plenty more to be added later
     //final transform(s)
     //color associations
@@ -29,14 +47,36 @@ impl Flame {
             name,
             transforms: Vec::new(),
             colors: Vec::new(),
+            weights: Vec::new(),
+            background: ColorFRGB {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+            },
         }
     }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
     pub fn add_transform(&mut self, t: Transform, c: ColorFRGB) {
         self.transforms.push(t);
         self.colors.push(c);
+        self.weights.push(1.0);
+    }
+    /// Removes the transform at `index`, along with its color and weight. Returns false if there
+    /// is no such transform.
+    pub fn remove_transform(&mut self, index: usize) -> bool {
+        if index >= self.transforms.len() {
+            return false;
+        }
+        self.transforms.remove(index);
+        self.colors.remove(index);
+        self.weights.remove(index);
+        true
     }
 }
 
+#[derive(Clone)]
 pub enum Transform {
     AffineOnly(AffineTransform),              //Linear, basically
     Chaining(Box<Transform>, Box<Transform>), //For ridic shit... but we don't handle an affine at all here. kinda odd?
@@ -60,8 +100,114 @@ impl Transform {
             }
         }
     }
+
+    /// The transform's affine part. Chained transforms have none.
+    pub fn affine(&self) -> Option<&AffineTransform> {
+        match self {
+            Transform::AffineOnly(affine)
+            | Transform::Basic(affine, _)
+            | Transform::Sum(affine, _) => Some(affine),
+            Transform::Chaining(_, _) => None,
+        }
+    }
+
+    pub fn affine_mut(&mut self) -> Option<&mut AffineTransform> {
+        match self {
+            Transform::AffineOnly(affine)
+            | Transform::Basic(affine, _)
+            | Transform::Sum(affine, _) => Some(affine),
+            Transform::Chaining(_, _) => None,
+        }
+    }
+
+    /// The weighted variations the affine part feeds into. A purely affine transform has none, and
+    /// behaves as `linear` at weight 1.
+    pub fn variations(&self) -> Vec<(f64, &TransformFunction)> {
+        match self {
+            Transform::AffineOnly(_) => Vec::new(),
+            Transform::Basic(_, function) => vec![(1.0, &**function)],
+            Transform::Sum(_, functions) => functions.iter().map(|(w, f)| (*w, &**f)).collect(),
+            Transform::Chaining(_, _) => Vec::new(),
+        }
+    }
+
+    /// The weight the transform gives the named variation, zero if it doesn't use it.
+    pub fn variation_weight(&self, name: &str) -> f64 {
+        match self {
+            Transform::AffineOnly(_) if name == "linear" => 1.0,
+            _ => self
+                .variations()
+                .iter()
+                .find(|(_, f)| f.name() == name)
+                .map_or(0.0, |&(w, _)| w),
+        }
+    }
+
+    /// Sets the weight of the named variation, adding it if the transform doesn't use it yet. A
+    /// weight of zero removes it. The transform is turned into a `Sum` to hold the variations.
+    pub fn set_variation_weight(&mut self, name: &str, weight: f64) -> Result<(), VariationError> {
+        let functions = self.variations_mut()?;
+        match functions.iter().position(|(_, f)| f.name() == name) {
+            Some(i) if weight == 0.0 => {
+                functions.remove(i);
+            }
+            Some(i) => functions[i].0 = weight,
+            None if weight == 0.0 => variations::create(name).map(|_| ())?,
+            None => functions.push((weight, variations::create(name)?)),
+        }
+        Ok(())
+    }
+
+    /// Sets a parameter of a variation the transform uses.
+    pub fn set_variation_param(
+        &mut self,
+        name: &str,
+        param: &str,
+        value: f64,
+    ) -> Result<(), VariationError> {
+        let functions = self.variations_mut()?;
+        let function = match functions.iter_mut().find(|(_, f)| f.name() == name) {
+            Some((_, function)) => function,
+            None => return Err(VariationError::NotPresent(String::from(name))),
+        };
+        if function.set_param(param, value) {
+            Ok(())
+        } else {
+            Err(VariationError::UnknownParam {
+                variation: String::from(name),
+                param: String::from(param),
+            })
+        }
+    }
+
+    /// Turns the transform into its `Sum` form, keeping what it computes, and returns the sum.
+    fn variations_mut(
+        &mut self,
+    ) -> Result<&mut Vec<(f64, Box<TransformFunction>)>, VariationError> {
+        let sum = match self {
+            Transform::AffineOnly(affine) => {
+                Transform::Sum(*affine, vec![(1.0, variations::create("linear")?)])
+            }
+            Transform::Basic(affine, function) => {
+                Transform::Sum(*affine, vec![(1.0, function.clone())])
+            }
+            Transform::Sum(_, _) => return self.sum_functions(),
+            Transform::Chaining(_, _) => return Err(VariationError::Chained),
+        };
+        *self = sum;
+        self.sum_functions()
+    }
+
+    fn sum_functions(&mut self) -> Result<&mut Vec<(f64, Box<TransformFunction>)>, VariationError> {
+        match self {
+            Transform::Sum(_, functions) => Ok(functions),
+            _ => Err(VariationError::Chained),
+        }
+    }
 }
 
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct AffineTransform {
     pub xx: f64,
     pub xy: f64,
@@ -78,25 +224,157 @@ impl AffineTransform {
             y: input.x * self.xy + input.y * self.yy + self.cy,
         }
     }
+
+    /// The transform as the triangle editors draw: where it sends the origin and the two unit
+    /// vectors.
+    pub fn triangle(&self) -> Triangle {
+        let o = Point {
+            x: self.cx,
+            y: self.cy,
+        };
+        Triangle {
+            o,
+            x: o + Point {
+                x: self.xx,
+                y: self.xy,
+            },
+            y: o + Point {
+                x: self.yx,
+                y: self.yy,
+            },
+        }
+    }
+
+    /// The transform that sends the origin to `o` and the unit vectors to `x` and `y`. Any three
+    /// points make a transform, though a flat triangle collapses the plane onto a line.
+    pub fn from_triangle(triangle: &Triangle) -> AffineTransform {
+        let Triangle { o, x, y } = *triangle;
+        AffineTransform {
+            xx: x.x - o.x,
+            xy: x.y - o.y,
+            yx: y.x - o.x,
+            yy: y.y - o.y,
+            cx: o.x,
+            cy: o.y,
+        }
+    }
+
+    /// Rotates the triangle by `angle` radians, counterclockwise, around `pivot`.
+    pub fn rotate(&mut self, angle: f64, pivot: Point) {
+        let (sin, cos) = angle.sin_cos();
+        self.map_triangle([[cos, -sin], [sin, cos]], pivot);
+    }
+
+    /// Scales the triangle by `sx` horizontally and `sy` vertically, away from `pivot`.
+    pub fn scale(&mut self, sx: f64, sy: f64, pivot: Point) {
+        self.map_triangle([[sx, 0.0], [0.0, sy]], pivot);
+    }
+
+    /// Shears the triangle about `pivot`: points move right by `kx` times their height above it,
+    /// and up by `ky` times their distance to the right of it.
+    pub fn shear(&mut self, kx: f64, ky: f64, pivot: Point) {
+        self.map_triangle([[1.0, kx], [ky, 1.0]], pivot);
+    }
+
+    /// Mirrors the triangle across the vertical line through `pivot`.
+    pub fn flip_horizontal(&mut self, pivot: Point) {
+        self.scale(-1.0, 1.0, pivot);
+    }
+
+    /// Mirrors the triangle across the horizontal line through `pivot`.
+    pub fn flip_vertical(&mut self, pivot: Point) {
+        self.scale(1.0, -1.0, pivot);
+    }
+
+    /// Moves the whole triangle.
+    pub fn translate(&mut self, offset: Point) {
+        self.cx += offset.x;
+        self.cy += offset.y;
+    }
+
+    /// Moves one corner of the triangle, leaving the other two where they are.
+    pub fn move_vertex(&mut self, vertex: Vertex, to: Point) {
+        let mut triangle = self.triangle();
+        match vertex {
+            Vertex::O => triangle.o = to,
+            Vertex::X => triangle.x = to,
+            Vertex::Y => triangle.y = to,
+        }
+        *self = AffineTransform::from_triangle(&triangle);
+    }
+
+    //Applies a linear map, given as rows, to every corner of the triangle, holding pivot fixed.
+    fn map_triangle(&mut self, m: [[f64; 2]; 2], pivot: Point) {
+        let apply = |x: f64, y: f64| (m[0][0] * x + m[0][1] * y, m[1][0] * x + m[1][1] * y);
+        let (xx, xy) = apply(self.xx, self.xy);
+        let (yx, yy) = apply(self.yx, self.yy);
+        let (cx, cy) = apply(self.cx - pivot.x, self.cy - pivot.y);
+        *self = AffineTransform {
+            xx,
+            xy,
+            yx,
+            yy,
+            cx: cx + pivot.x,
+            cy: cy + pivot.y,
+        };
+    }
+}
+
+/// The three points of an affine transform's triangle, in flame coordinates with y up. Editors
+/// that draw y down should flip the points on the way in and out.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Triangle {
+    pub o: Point,
+    pub x: Point,
+    pub y: Point,
+}
+
+impl Triangle {
+    /// The triangle's center, a natural pivot for rotating and scaling.
+    pub fn centroid(&self) -> Point {
+        (self.o + self.x + self.y) * (1.0 / 3.0)
+    }
 }
 
-pub trait TransformFunction {
+/// A corner of a transform's triangle.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Vertex {
+    /// The origin's image, drawn as the triangle's corner.
+    O,
+    X,
+    Y,
+}
+
+//Send + Sync so a flame can be handed to a render thread.
+pub trait TransformFunction: Send + Sync {
     fn transform(&self, input: &Point) -> Point;
+    fn box_clone(&self) -> Box<TransformFunction>;
+    /// The name the function is known by in flame files, see `variations`.
+    fn name(&self) -> &'static str;
+    fn param_names(&self) -> Vec<&'static str> {
+        Vec::new()
+    }
+    fn param(&self, _name: &str) -> Option<f64> {
+        None
+    }
+    /// Sets a parameter, returning false if the function has no parameter of that name.
+    fn set_param(&mut self, _name: &str, _value: f64) -> bool {
+        false
+    }
 }
-struct SinusoidalTransform;
-impl TransformFunction for SinusoidalTransform {
-    fn transform(&self, input: &Point) -> Point {
-        Point {
-            x: input.x.sin(),
-            y: input.y.sin(),
-        }
+
+impl Clone for Box<TransformFunction> {
+    fn clone(&self) -> Box<TransformFunction> {
+        self.box_clone()
     }
 }
 
-#[derive(Copy, Clone)]
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Point {
-    x: f64,
-    y: f64,
+    pub x: f64,
+    pub y: f64,
 }
 
 impl Add for Point {
@@ -167,12 +445,179 @@ impl RenderBin {
     }
 }
 
+/// Single-precision counterpart to `RenderBin`, at half the memory per pixel. The color sums stop
+/// gaining precision after roughly 2^24 hits in one bin, which only matters for very long renders
+/// of very small images.
+#[derive(Copy, Clone, Default)]
+struct CompactRenderBin {
+    r: f32,
+    g: f32,
+    b: f32,
+    h: u32,
+}
+
+/// A histogram cell the chaos game can plot into.
+trait HistogramBin: Copy + Default + Send + 'static {
+    fn register_hit(&mut self, c: ColorFRGB);
+    /// Adds in the hits from the same bin of another thread's histogram.
+    fn merge(&mut self, other: &Self);
+    fn hits(&self) -> u64;
+    /// Widens the bin to full precision for tonemapping.
+    fn to_render_bin(&self) -> RenderBin;
+}
+
+impl HistogramBin for RenderBin {
+    fn register_hit(&mut self, c: ColorFRGB) {
+        RenderBin::register_hit(self, c)
+    }
+    fn merge(&mut self, other: &RenderBin) {
+        self.r += other.r;
+        self.g += other.g;
+        self.b += other.b;
+        self.h += other.h;
+    }
+    fn hits(&self) -> u64 {
+        self.h
+    }
+    fn to_render_bin(&self) -> RenderBin {
+        *self
+    }
+}
+
+impl HistogramBin for CompactRenderBin {
+    fn register_hit(&mut self, c: ColorFRGB) {
+        self.h = self.h.saturating_add(1);
+        self.r += c.r;
+        self.g += c.g;
+        self.b += c.b;
+    }
+    fn merge(&mut self, other: &CompactRenderBin) {
+        self.h = self.h.saturating_add(other.h);
+        self.r += other.r;
+        self.g += other.g;
+        self.b += other.b;
+    }
+    fn hits(&self) -> u64 {
+        self.h as u64
+    }
+    fn to_render_bin(&self) -> RenderBin {
+        RenderBin {
+            r: self.r as f64,
+            g: self.g as f64,
+            b: self.b as f64,
+            h: self.h as u64,
+        }
+    }
+}
+
+/// Storage precision of the render histogram.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BinPrecision {
+    /// f64 color sums and u64 hit counts, 32 bytes per pixel.
+    Double,
+    /// f32 color sums and u32 hit counts, 16 bytes per pixel.
+    Single,
+}
+
+/// When the chaos game should stop iterating.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Termination {
+    Time(Duration),
+    Iterations(usize),
+    /// Whichever of the time and the iteration count runs out first.
+    FirstOf(Duration, usize),
+}
+
+/// Iterations per pixel a thumbnail stops at, if its time budget hasn't run out first.
+pub const THUMBNAIL_SAMPLES_PER_PIXEL: usize = 2000;
+/// How long a thumbnail may iterate for, in milliseconds.
+pub const THUMBNAIL_BUDGET_MS: u64 = 40;
+
+/// How many iterations run between calls to a render's check-in, which reports progress and can
+/// cancel it.
+const CHECK_IN_INTERVAL: usize = 1 << 14;
+
+/// Follows a render from the thread running it, see `Renderer::render_monitored`.
+pub trait RenderMonitor<P> {
+    /// Called regularly with the fraction of the render done so far, from 0.0 to 1.0. Returning
+    /// false cancels the render.
+    fn progress(&mut self, done: f64) -> bool;
+    /// How many preview frames to produce, spread evenly over the render. Each one tonemaps the
+    /// whole image, so keep it small.
+    fn preview_frames(&self) -> u32 {
+        0
+    }
+    /// Receives an image of the render so far.
+    fn preview(&mut self, _frame: &Bitmap<P>) {}
+}
+
+/// How a genome asks to be rendered: flame files carry these alongside the flame.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub gamma: f64,
+    pub vibrancy: f64,
+    /// Iterations per pixel, flam3's `quality`.
+    pub quality: f64,
+    /// Seeds the iteration, making the render repeatable. Without one, every render differs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Iterate for this long, rather than to `quality`.
+    #[serde(with = "::json::seconds", skip_serializing_if = "Option::is_none")]
+    pub time: Option<Duration>,
+    pub precision: BinPrecision,
+}
+
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings {
+            width: 1920,
+            height: 1080,
+            gamma: 2.2,
+            vibrancy: 0.75,
+            quality: 50.0,
+            seed: None,
+            time: None,
+            precision: BinPrecision::Double,
+        }
+    }
+}
+
+/// Why `Renderer::from_settings` can't render a flame.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RendererError {
+    /// The image is zero pixels wide or high.
+    EmptyImage,
+    /// The flame has no transforms, or none with any weight, so there's nothing to iterate.
+    NoTransforms,
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RendererError::EmptyImage => write!(f, "the image has no pixels"),
+            RendererError::NoTransforms => write!(f, "the flame has no weighted transforms"),
+        }
+    }
+}
+
+impl Error for RendererError {}
+
+#[derive(Clone)]
 pub struct Renderer {
     image_width: u32,
     image_height: u32,
     gamma: f64,
     vibrancy: f64,
+    termination: Termination,
+    seed: Option<[u32; 4]>,
+    precision: BinPrecision,
     flame: Flame,
+    /// Whether to log timings to stdout.
+    verbose: bool,
 }
 
 //TODO: Create a RendererBuilder
@@ -189,148 +634,522 @@ impl Renderer {
             image_height,
             gamma,
             vibrancy,
+            termination: Termination::Time(Duration::from_secs(60)),
+            seed: None,
+            precision: BinPrecision::Double,
             flame,
+            verbose: true,
         }
     }
+
+    /// A renderer for `flame` as `settings` describe, stopping after `time`, or failing that
+    /// `quality` iterations per pixel.
+    pub fn from_settings(
+        settings: &RenderSettings,
+        flame: Flame,
+    ) -> Result<Renderer, RendererError> {
+        if settings.width == 0 || settings.height == 0 {
+            return Err(RendererError::EmptyImage);
+        }
+        if !flame.weights.iter().any(|&w| w > 0.0) {
+            return Err(RendererError::NoTransforms);
+        }
+        let mut renderer = Renderer::new(
+            settings.width,
+            settings.height,
+            settings.gamma,
+            settings.vibrancy,
+            flame,
+        );
+        let pixels = settings.width as f64 * settings.height as f64;
+        let iterations = (settings.quality.max(0.0) * pixels).min(usize::MAX as f64);
+        renderer.set_termination(match settings.time {
+            Some(time) => Termination::Time(time),
+            None => Termination::Iterations((iterations as usize).max(1)),
+        });
+        if let Some(seed) = settings.seed {
+            renderer.set_seed([seed as u32, (seed >> 32) as u32, 0x9E37_79B9, 0x7F4A_7C15]);
+        }
+        renderer.set_precision(settings.precision);
+        Ok(renderer)
+    }
+
+    /// A renderer for quick, rough previews such as browser tiles and mutation candidates. It
+    /// iterates for at most `THUMBNAIL_BUDGET_MS`, or `THUMBNAIL_SAMPLES_PER_PIXEL` iterations per
+    /// pixel if that comes first, into a single precision histogram, and logs nothing. Keep the
+    /// image small: the budget is fixed, so a bigger thumbnail is only a noisier one.
+    pub fn thumbnail(image_width: u32, image_height: u32, flame: Flame) -> Renderer {
+        let pixels = image_width as usize * image_height as usize;
+        let mut renderer = Renderer::new(image_width, image_height, 2.2, 0.75, flame);
+        renderer.set_termination(Termination::FirstOf(
+            Duration::from_millis(THUMBNAIL_BUDGET_MS),
+            pixels.saturating_mul(THUMBNAIL_SAMPLES_PER_PIXEL),
+        ));
+        renderer.set_precision(BinPrecision::Single);
+        renderer.set_verbose(false);
+        renderer
+    }
+    pub fn image_width(&self) -> u32 {
+        self.image_width
+    }
+    pub fn image_height(&self) -> u32 {
+        self.image_height
+    }
+    pub fn flame(&self) -> &Flame {
+        &self.flame
+    }
+    pub fn set_termination(&mut self, termination: Termination) {
+        self.termination = termination;
+    }
+    /// Fixes the random sequence used for iteration, making renders with a
+    /// `Termination::Iterations` limit reproducible. An all-zero seed is not allowed by the
+    /// generator, and is nudged.
+    pub fn set_seed(&mut self, mut seed: [u32; 4]) {
+        if seed == [0; 4] {
+            seed[0] = 1;
+        }
+        self.seed = Some(seed);
+    }
+    pub fn set_precision(&mut self, precision: BinPrecision) {
+        self.precision = precision;
+    }
+    pub fn set_verbose(&mut self, verbose: bool) {
+        self.verbose = verbose;
+    }
     pub fn render_unthreaded(&mut self) -> Bitmap<RGB<u8>> {
+        self.render_unthreaded_as()
+    }
+
+    /// Renders to any supported pixel type. `RGBA<u8>` gives a transparent background, with alpha
+    /// taken from the flame's density.
+    pub fn render_unthreaded_as<P: OutputPixel>(&mut self) -> Bitmap<P> {
+        self.render_threaded_as(1)
+    }
+
+    /// Renders like `render_unthreaded_as`, with `threads` threads each playing their own chaos
+    /// game into their own histogram, summed at the end. An iteration limit is shared out between
+    /// the threads, while a time limit applies to each. Every thread holds a whole histogram, so
+    /// memory use grows with the thread count.
+    pub fn render_threaded_as<P: OutputPixel>(&mut self, threads: usize) -> Bitmap<P> {
+        match self.precision {
+            BinPrecision::Double => self.render_with::<RenderBin, P>(threads),
+            BinPrecision::Single => self.render_with::<CompactRenderBin, P>(threads),
+        }
+    }
+
+    fn render_with<B: HistogramBin, P: OutputPixel>(&mut self, threads: usize) -> Bitmap<P> {
         let render_start = Instant::now();
-        //TODO: consider using an intermediate struct to return to allow for post-processing.
-        let pixels = (self.image_width * self.image_height) as usize;
-        let mut render_array = Vec::<RenderBin>::with_capacity(pixels);
-        for p in 0..pixels {
-            //Hopefully this does this right,and doesn't do anything ludicrous.
-            render_array.push(RenderBin::default())
-        }
-        println!(
-            "render_array initialized with {} elements, expected {}",
-            render_array.len(),
-            pixels
-        );
-        //TODO: Consider render areas other than the biunit square.
-        let dw = 2.0 / self.image_width as f64;
-        let dh = 2.0 / self.image_height as f64;
-        let mut points = Vec::<Point>::new();
-        for i in 0..10 {
-            for j in 0..10 {
-                points.push(Point {
-                    x: i as f64 * 0.1,
-                    y: j as f64 * 0.1,
-                });
-                if i != 0 || j != 0 {
-                    points.push(Point {
-                        x: i as f64 * -0.1,
-                        y: j as f64 * -0.1,
+        let render_array = self.accumulate::<B>(threads, &mut |_, _| true);
+        let ret = self.to_bitmap(&render_array);
+        if self.verbose {
+            println!("[{:?}] Finished render", render_start.elapsed());
+        }
+        ret
+    }
+
+    /// Renders like `render_unthreaded_as`, reporting progress and preview frames to `monitor` as
+    /// it goes. Returns `None` if the monitor cancelled the render.
+    pub fn render_monitored<P, M>(&mut self, monitor: &mut M) -> Option<Bitmap<P>>
+    where
+        P: OutputPixel,
+        M: RenderMonitor<P>,
+    {
+        match self.precision {
+            BinPrecision::Double => self.render_monitored_with::<RenderBin, P, M>(monitor),
+            BinPrecision::Single => self.render_monitored_with::<CompactRenderBin, P, M>(monitor),
+        }
+    }
+
+    fn render_monitored_with<B, P, M>(&mut self, monitor: &mut M) -> Option<Bitmap<P>>
+    where
+        B: HistogramBin,
+        P: OutputPixel,
+        M: RenderMonitor<P>,
+    {
+        //Frame k of n is due once the render is k / (n + 1) done; the finished image is the last.
+        let steps = monitor.preview_frames() as f64 + 1.0;
+        let mut next_frame = 1.0;
+        let mut cancelled = false;
+        let render_array = {
+            let this = &*self;
+            this.accumulate::<B>(1, &mut |done, bins| {
+                if !monitor.progress(done) {
+                    cancelled = true;
+                    return false;
+                }
+                if next_frame < steps && done * steps >= next_frame {
+                    next_frame = (done * steps).floor() + 1.0;
+                    monitor.preview(&this.to_bitmap(bins));
+                }
+                true
+            })
+        };
+        if cancelled {
+            return None;
+        }
+        monitor.progress(1.0);
+        Some(self.to_bitmap(&render_array))
+    }
+
+    /// Tonemaps a whole-image histogram.
+    fn to_bitmap<B: HistogramBin, P: OutputPixel>(&self, bins: &[B]) -> Bitmap<P> {
+        let max_hits = max_hits(bins);
+        let background = self.flame.background;
+        let buffer = bins
+            .iter()
+            .map(|bin| P::from_tonemapped(self.tonemap(bin.to_render_bin(), max_hits), background))
+            .collect();
+        Bitmap {
+            buffer,
+            width: self.image_width as usize,
+            height: self.image_height as usize,
+        }
+    }
+
+    /// Renders the raw histogram as linear floating point, for grading and compositing outside of
+    /// rustflame. Color channels are the summed hit colors and alpha is the hit count, both divided
+    /// by the hit count of the densest pixel, so the densest pixel has an alpha of 1. No gamma,
+    /// vibrancy or clipping is applied, and the color channels are premultiplied by alpha.
+    pub fn render_density(&mut self) -> Bitmap<RGBA<f32>> {
+        match self.precision {
+            BinPrecision::Double => self.render_density_with::<RenderBin>(),
+            BinPrecision::Single => self.render_density_with::<CompactRenderBin>(),
+        }
+    }
+
+    fn render_density_with<B: HistogramBin>(&mut self) -> Bitmap<RGBA<f32>> {
+        let render_array = self.accumulate::<B>(1, &mut |_, _| true);
+        let max_hits = max_hits(&render_array) as f64;
+        let buffer = render_array
+            .into_iter()
+            .map(|bin| {
+                let bin = bin.to_render_bin();
+                RGBA {
+                    r: (bin.r / max_hits) as f32,
+                    g: (bin.g / max_hits) as f32,
+                    b: (bin.b / max_hits) as f32,
+                    a: (bin.h as f64 / max_hits) as f32,
+                }
+            })
+            .collect();
+        Bitmap {
+            buffer,
+            width: self.image_width as usize,
+            height: self.image_height as usize,
+        }
+    }
+
+    /// Allocates a histogram for the whole image and runs the chaos game into it, calling
+    /// `check_in` as `iterate` does. With more than one thread, the helper threads iterate into
+    /// histograms of their own, which are merged in once they finish; `check_in` only sees the
+    /// calling thread's share, and cancelling it stops them all.
+    fn accumulate<B: HistogramBin>(
+        &self,
+        threads: usize,
+        check_in: &mut FnMut(f64, &[B]) -> bool,
+    ) -> Vec<B> {
+        let render_start = Instant::now();
+        let pixels = self.image_width as usize * self.image_height as usize;
+        let threads = threads.max(1);
+        let stop = Arc::new(AtomicBool::new(false));
+        let helpers: Vec<_> = (1..threads)
+            .map(|k| {
+                let renderer = self.clone();
+                let stop = stop.clone();
+                thread::spawn(move || {
+                    let mut bins = vec![B::default(); pixels];
+                    renderer.iterate_share(k, threads, &mut bins, &mut |_, _| {
+                        !stop.load(Ordering::Relaxed)
                     });
+                    bins
+                })
+            })
+            .collect();
+        let mut render_array = vec![B::default(); pixels];
+        if self.verbose {
+            println!(
+                "render_array initialized with {} elements, expected {}",
+                render_array.len(),
+                pixels
+            );
+        }
+        let iterations = self.iterate_share(0, threads, &mut render_array, &mut |done, bins| {
+            let keep_going = check_in(done, bins);
+            if !keep_going {
+                stop.store(true, Ordering::Relaxed);
+            }
+            keep_going
+        });
+        for helper in helpers {
+            let bins = helper.join().expect("a render thread panicked");
+            for (bin, other) in render_array.iter_mut().zip(&bins) {
+                bin.merge(other);
+            }
+        }
+        if self.verbose {
+            println!(
+                "[{:?}] Stopped iterating, {} iterations complete on the first of {} threads",
+                render_start.elapsed(),
+                iterations,
+                threads
+            );
+        }
+        render_array
+    }
+
+    /// Runs thread `k` of `threads`'s part of the render. Thread 0 plays the same game a single
+    /// threaded render would, and the others are seeded from it.
+    fn iterate_share<B: HistogramBin>(
+        &self,
+        k: usize,
+        threads: usize,
+        bins: &mut [B],
+        check_in: &mut FnMut(f64, &[B]) -> bool,
+    ) -> usize {
+        let share = |limit: usize| limit / threads + if k < limit % threads { 1 } else { 0 };
+        let termination = match self.termination {
+            Termination::Time(duration) => Termination::Time(duration),
+            Termination::Iterations(limit) => Termination::Iterations(share(limit)),
+            Termination::FirstOf(duration, limit) => Termination::FirstOf(duration, share(limit)),
+        };
+        match self.seed {
+            Some(mut seed) => {
+                seed[0] ^= (k as u32).wrapping_mul(0x9E37_79B9);
+                if seed == [0; 4] {
+                    seed[0] = 1;
+                }
+                self.iterate(
+                    &mut XorShiftRng::from_seed(seed),
+                    bins,
+                    0,
+                    self.image_height,
+                    termination,
+                    check_in,
+                )
+            }
+            None => self.iterate(
+                &mut rand::thread_rng(),
+                bins,
+                0,
+                self.image_height,
+                termination,
+                check_in,
+            ),
+        }
+    }
+
+    /// Renders the image in horizontal bands of `band_height` rows, so only one band's histogram is
+    /// ever held in memory. Each finished band is handed to `sink` with the index of its first row,
+    /// in top-to-bottom order.
+    ///
+    /// Every band replays the same seeded chaos game and keeps only the hits that land inside it.
+    /// The whole image is walked twice: once to find the brightest bin for normalization, and once
+    /// to produce pixels. A `Termination::Time` limit is spent on the first band, and the iteration
+    /// count it reached is then reused for every other band so they all share one exposure.
+    pub fn render_tiled<P, F>(&mut self, band_height: u32, sink: F) -> io::Result<()>
+    where
+        P: OutputPixel,
+        F: FnMut(u32, &[P]) -> io::Result<()>,
+    {
+        match self.precision {
+            BinPrecision::Double => self.render_tiled_with::<RenderBin, P, F>(band_height, sink),
+            BinPrecision::Single => {
+                self.render_tiled_with::<CompactRenderBin, P, F>(band_height, sink)
+            }
+        }
+    }
+
+    fn render_tiled_with<B, P, F>(&mut self, band_height: u32, mut sink: F) -> io::Result<()>
+    where
+        B: HistogramBin,
+        P: OutputPixel,
+        F: FnMut(u32, &[P]) -> io::Result<()>,
+    {
+        let render_start = Instant::now();
+        let band_height = band_height.max(1).min(self.image_height.max(1));
+        let seed = match self.seed {
+            Some(seed) => seed,
+            None => {
+                let mut seed = rand::thread_rng().gen::<[u32; 4]>();
+                seed[0] |= 1;
+                seed
+            }
+        };
+        let mut termination = self.termination;
+        let mut max_hits = 0u64;
+        let mut bins = Vec::<B>::new();
+        for pass in 0..2 {
+            let mut first_row = 0u32;
+            while first_row < self.image_height {
+                let rows = band_height.min(self.image_height - first_row);
+                bins.clear();
+                bins.resize(rows as usize * self.image_width as usize, B::default());
+                let iterations = self.iterate(
+                    &mut XorShiftRng::from_seed(seed),
+                    &mut bins,
+                    first_row,
+                    rows,
+                    termination,
+                    &mut |_, _| true,
+                );
+                termination = Termination::Iterations(iterations);
+                if pass == 0 {
+                    max_hits = max_hits.max(self::max_hits(&bins));
+                } else {
+                    let band: Vec<P> = bins
+                        .iter()
+                        .map(|bin| {
+                            P::from_tonemapped(
+                                self.tonemap(bin.to_render_bin(), max_hits),
+                                self.flame.background,
+                            )
+                        })
+                        .collect();
+                    sink(first_row, &band)?;
                 }
+                first_row += rows;
+            }
+            if self.verbose {
+                println!(
+                    "[{:?}] Finished tiled pass {} of 2",
+                    render_start.elapsed(),
+                    pass + 1
+                );
             }
         }
-        println!("{} starting points generated", points.len());
+        Ok(())
+    }
+
+    //TODO: Consider render areas other than the biunit square.
+    /// Runs the chaos game, accumulating hits that land in `bins`, which cover the `rows` whole
+    /// image rows starting at `first_row`. Every `CHECK_IN_INTERVAL` iterations `check_in` is given
+    /// the fraction of the work done and the bins so far, and can stop iterating early by
+    /// returning false. Returns the number of iterations performed.
+    fn iterate<R: Rng, B: HistogramBin>(
+        &self,
+        rng: &mut R,
+        bins: &mut [B],
+        first_row: u32,
+        rows: u32,
+        termination: Termination,
+        check_in: &mut FnMut(f64, &[B]) -> bool,
+    ) -> usize {
+        let iterate_start = Instant::now();
+        let cumulative_weights = cumulative_weights(&self.flame);
+        let total_weight = match cumulative_weights.last() {
+            Some(&total) if self.image_width > 0 && self.image_height > 0 => total,
+            //No transforms, or no pixels to plot them on.
+            _ => return 0,
+        };
+        let dw = 2.0 / self.image_width as f64;
+        let dh = 2.0 / self.image_height as f64;
+        let mut points = starting_points();
         //TODO: consider a bigint for iterations.
         let mut iterations = 0usize;
         let mut keep_looping = true;
-        let iterate_start = Instant::now();
-        let iterate_duration = Duration::from_secs(60);
-        let num_xforms = self.flame.transforms.len();
+        let last_xform = self.flame.transforms.len() - 1;
         let mut selected_xform: usize;
-        let mut rng = rand::thread_rng();
-        println!(
-            "[{:?}] Starting iteration with {} transforms, {} points",
-            render_start.elapsed(),
-            num_xforms,
-            points.len()
-        );
-        //for now, hardcoding termination based on time.
-        //TODO: Termination options, stored as part of the struct at creation. Cancellation.
-
         while keep_looping {
             let sel = iterations % points.len();
             let p0 = points[sel];
-            selected_xform = rng.gen::<u32>() as usize % num_xforms;
+            let pick = rng.gen::<f64>() * total_weight;
+            selected_xform = cumulative_weights
+                .iter()
+                .position(|&c| pick < c)
+                .unwrap_or(last_xform);
             let p1 = self.flame.transforms[selected_xform].transform(&p0);
             if p1.x.abs() > 1.0 || p1.y.abs() > 1.0 {
                 //ignore this transformation if it falls outside -
                 //for now, this means that point is unaffected and will receive another transform later.
                 //We may change that ro replacement with a new, random point that isn't plotted.
-
             } else {
-                //Plot the point
-                let px = p1.x + 1.0;
-                let py = p1.y + 1.0;
-                let bx = px / dw;
-                let by = py / dh;
-                let idx = bx.trunc() as usize + (self.image_width * by.trunc() as u32) as usize;
-                if idx < render_array.len() {
-                    render_array[idx].register_hit(self.flame.colors[selected_xform]);
-                } else {
-                    println!(
-                        "[{:?}] Attempted to plot point out of range! idx:{} len:{} bx:{} by{}",
-                        render_start.elapsed(),
-                        idx,
-                        render_array.len(),
-                        bx,
-                        by
-                    );
+                //Plot the point, if it falls in the rows we're accumulating.
+                let col = ((p1.x + 1.0) / dw).trunc() as u32;
+                let row = ((p1.y + 1.0) / dh).trunc() as u32;
+                if col < self.image_width && row >= first_row && row - first_row < rows {
+                    let idx = col as usize + self.image_width as usize * (row - first_row) as usize;
+                    bins[idx].register_hit(self.flame.colors[selected_xform]);
                 }
                 points[sel] = p1;
             }
             iterations += 1;
-            //Stop iterating when the duration has elapsed.
-            keep_looping = (iterate_start.elapsed() < iterate_duration)
-        }
-        println!(
-            "[{:?}] Stopped iterating, {} iterations complete",
-            render_start.elapsed(),
-            iterations
-        );
-        let mut max_hits = 0u64;
-        for bin in &render_array {
-            if bin.h > max_hits {
-                max_hits = bin.h;
+            keep_looping = match termination {
+                Termination::Time(duration) => iterate_start.elapsed() < duration,
+                Termination::Iterations(limit) => iterations < limit,
+                Termination::FirstOf(duration, limit) => {
+                    iterations < limit && iterate_start.elapsed() < duration
+                }
+            };
+            if keep_looping && iterations % CHECK_IN_INTERVAL == 0 {
+                let done = match termination {
+                    Termination::Time(duration) => {
+                        iterate_start.elapsed().as_secs_f64() / duration.as_secs_f64()
+                    }
+                    Termination::Iterations(limit) => iterations as f64 / limit as f64,
+                    Termination::FirstOf(duration, limit) => f64::max(
+                        iterate_start.elapsed().as_secs_f64() / duration.as_secs_f64(),
+                        iterations as f64 / limit as f64,
+                    ),
+                };
+                keep_looping = check_in(done.min(1.0), bins);
             }
         }
-        let mut pixel_buffer = Vec::<RGB<u8>>::with_capacity(pixels);
-        for mut bin in render_array {
-            //The ordering here is enforced,right? goodness, I hope so.
-            bin.normalize_by(max_hits);
-            let rf = ((bin.h as f64).powf(1.0 / self.gamma) * self.vibrancy * bin.r)
-                + (bin.r.powf(1.0 / self.gamma) * (1.0 - self.vibrancy));
-            let gf = ((bin.h as f64).powf(1.0 / self.gamma) * self.vibrancy * bin.g)
-                + (bin.g.powf(1.0 / self.gamma) * (1.0 - self.vibrancy));
-            let bf = ((bin.h as f64).powf(1.0 / self.gamma) * self.vibrancy * bin.b)
-                + (bin.b.powf(1.0 / self.gamma) * (1.0 - self.vibrancy));
-
-            let r = if rf <= 0.0 {
-                0u8
-            } else if rf >= 1.0 {
-                255u8
-            } else {
-                (rf * 255.0) as u8
-            };
-            let g = if gf <= 0.0 {
-                0u8
-            } else if gf >= 1.0 {
-                255u8
-            } else {
-                (gf * 255.0) as u8
-            };
-            let b = if bf <= 0.0 {
-                0u8
-            } else if bf >= 1.0 {
-                255u8
-            } else {
-                (bf * 255.0) as u8
-            };
+        iterations
+    }
 
-            let pixel = RGB { r, g, b };
-            pixel_buffer.push(pixel);
+    /// Applies gamma and vibrancy to a bin. The result is unclipped and premultiplied, with alpha
+    /// put through the same curve as the color channels, as flam3 does.
+    fn tonemap(&self, mut bin: RenderBin, max_hits: u64) -> RGBA<f64> {
+        let density = bin.h as f64 / max_hits as f64;
+        bin.normalize_by(max_hits);
+        let brightness = (bin.h as f64).powf(1.0 / self.gamma) * self.vibrancy;
+        let channel =
+            |c: f64| (brightness * c) + (c.powf(1.0 / self.gamma) * (1.0 - self.vibrancy));
+        RGBA {
+            r: channel(bin.r),
+            g: channel(bin.g),
+            b: channel(bin.b),
+            a: channel(density),
         }
+    }
+}
 
-        let ret = Bitmap {
-            buffer: pixel_buffer,
-            width: self.image_width as usize,
-            height: self.image_height as usize,
-        };
-        println!("[{:?}] Finished render", render_start.elapsed());
-        ret
+fn starting_points() -> Vec<Point> {
+    let mut points = Vec::<Point>::new();
+    for i in 0..10 {
+        for j in 0..10 {
+            points.push(Point {
+                x: i as f64 * 0.1,
+                y: j as f64 * 0.1,
+            });
+            if i != 0 || j != 0 {
+                points.push(Point {
+                    x: i as f64 * -0.1,
+                    y: j as f64 * -0.1,
+                });
+            }
+        }
     }
+    points
+}
+
+/// Running totals of the transform weights, to pick a transform with one random number. Missing
+/// weights count as 1, and if no transform has any weight they are all picked equally.
+fn cumulative_weights(flame: &Flame) -> Vec<f64> {
+    let weights: Vec<f64> = (0..flame.transforms.len())
+        .map(|i| flame.weights.get(i).cloned().unwrap_or(1.0).max(0.0))
+        .collect();
+    let uniform = weights.iter().all(|&w| w == 0.0);
+    let mut total = 0.0;
+    weights
+        .iter()
+        .map(|&w| {
+            total += if uniform { 1.0 } else { w };
+            total
+        })
+        .collect()
+}
+
+fn max_hits<B: HistogramBin>(bins: &[B]) -> u64 {
+    bins.iter().map(|bin| bin.hits()).max().unwrap_or(0)
 }
@@ -0,0 +1,741 @@
+//! Reading flame genomes from flam3, Apophysis and JWildfire `.flame` files.
+//!
+//! Each `<xform>` becomes a transform with its `coefs`, its `weight`, and the variations rustflame
+//! knows along with their parameters. A transform's `color` picks its flat color from the flame's
+//! `<palette>`. Of JWildfire's `<layer>`s, the first visible one is rendered, and a flame with none
+//! is an error. JWildfire's variations are read where they share flam3's names and parameters, and
+//! of its 3D ones only `linear3D` and `spherical3D`, as the flat variations they match.
+//!
+//! The camera, final and post transforms, and anything else rustflame can't render yet are
+//! skipped. `import` reports the ones that change how a flame looks as `Warning`s: unknown
+//! variations, extra layers and final transforms, and the features below when they're set.
+//!
+//! * The camera's `center`, `rotate`, `zoom` and `scale`, unless they frame the square from -1 to 1
+//!   that rustflame stretches over the image.
+//! * JWildfire's extended camera: `cam_pitch`, `cam_yaw`, `cam_persp` and `cam_dof`.
+//! * Gradient mixing, on a flame or layer.
+//! * An xform's `material`, weighting field, `post` transform, `chaos` (xaos) or `opacity`.
+//!
+//! Genomes are written back out the same way, one `<color>` per transform, and rendered PNGs carry
+//! theirs in a `flam3_genome` text chunk as flam3's do.
+
+use color::ColorFRGB;
+use flame_2d::AffineTransform;
+use flame_2d::BinPrecision;
+use flame_2d::Flame;
+use flame_2d::RenderSettings;
+use flame_2d::Transform;
+use json;
+use json::JsonError;
+use output;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use variations;
+use variations::VariationError;
+use xml;
+use xml::Element;
+use xml::XmlError;
+
+/// A flame and the settings its file asks it to be rendered with.
+#[derive(Clone, Serialize)]
+pub struct Genome {
+    pub flame: Flame,
+    pub settings: RenderSettings,
+}
+
+#[derive(Debug)]
+pub enum GenomeError {
+    Io(io::Error),
+    Xml(XmlError),
+    /// The document holds no `<flame>` elements, or the image no genome.
+    NoFlames,
+    BadAttribute {
+        element: String,
+        attribute: String,
+        value: String,
+    },
+    Variation(VariationError),
+    Json(JsonError),
+    /// Every `<layer>` of the named flame is hidden, leaving nothing to render.
+    NoVisibleLayer(String),
+}
+
+impl fmt::Display for GenomeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GenomeError::Io(e) => e.fmt(f),
+            GenomeError::Xml(e) => e.fmt(f),
+            GenomeError::NoFlames => write!(f, "there are no flames in the file"),
+            GenomeError::BadAttribute {
+                element,
+                attribute,
+                value,
+            } => write!(
+                f,
+                "<{}> has an unreadable {}=\"{}\"",
+                element, attribute, value
+            ),
+            GenomeError::Variation(e) => e.fmt(f),
+            GenomeError::Json(e) => e.fmt(f),
+            GenomeError::NoVisibleLayer(name) => {
+                write!(f, "flame \"{}\" has no visible layer", name)
+            }
+        }
+    }
+}
+
+impl Error for GenomeError {}
+
+impl From<io::Error> for GenomeError {
+    fn from(e: io::Error) -> GenomeError {
+        GenomeError::Io(e)
+    }
+}
+
+impl From<XmlError> for GenomeError {
+    fn from(e: XmlError) -> GenomeError {
+        GenomeError::Xml(e)
+    }
+}
+
+impl From<JsonError> for GenomeError {
+    fn from(e: JsonError) -> GenomeError {
+        GenomeError::Json(e)
+    }
+}
+
+impl From<VariationError> for GenomeError {
+    fn from(e: VariationError) -> GenomeError {
+        GenomeError::Variation(e)
+    }
+}
+
+/// A genome file's flames, and what in them couldn't be rendered as their author saw them.
+#[derive(Clone)]
+pub struct Import {
+    pub genomes: Vec<Genome>,
+    pub warnings: Vec<Warning>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    /// The flame's index in its file.
+    pub flame: usize,
+    pub kind: WarningKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum WarningKind {
+    /// Visible layers after the first, which is the only one rendered.
+    ExtraLayers(usize),
+    /// The xform renders without a variation the registry doesn't have.
+    UnknownVariation {
+        xform: usize,
+        name: String,
+    },
+    FinalTransform,
+    /// An attribute asking for something rustflame can't render, on an xform or, for `None`, on
+    /// the flame or its layer.
+    Unsupported {
+        xform: Option<usize>,
+        attribute: String,
+        value: String,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "flame {}: ", self.flame)?;
+        match &self.kind {
+            WarningKind::ExtraLayers(count) => write!(
+                f,
+                "only the first layer is rendered, {} more are left out",
+                count
+            ),
+            WarningKind::UnknownVariation { xform, name } => {
+                write!(
+                    f,
+                    "xform {} uses {}, which isn't a known variation and is left out",
+                    xform, name
+                )?;
+                if name.contains("3D") {
+                    let read: Vec<&str> = JWILDFIRE_NAMES
+                        .iter()
+                        .map(|&(jwildfire, _)| jwildfire)
+                        .collect();
+                    write!(
+                        f,
+                        " (of JWildfire's 3D variations, only {} are read)",
+                        read.join(" and ")
+                    )?;
+                }
+                Ok(())
+            }
+            WarningKind::FinalTransform => write!(f, "the final transform is left out"),
+            WarningKind::Unsupported {
+                xform,
+                attribute,
+                value,
+            } => {
+                if let Some(xform) = xform {
+                    write!(f, "xform {} ", xform)?;
+                }
+                write!(
+                    f,
+                    "{}=\"{}\" can't be rendered and is ignored",
+                    attribute, value
+                )
+            }
+        }
+    }
+}
+
+/// The PNG text keyword a rendered image's genome is kept under.
+pub const PNG_KEYWORD: &str = "flam3_genome";
+
+/// Reads every flame in a file, in file order. The file can also be a PNG with its genome
+/// embedded, or a JSON document as `json` describes.
+pub fn load(path: &Path) -> Result<Vec<Genome>, GenomeError> {
+    import_file(path).map(|import| import.genomes)
+}
+
+/// Reads every flame in a file, as `load` does, along with the warnings `import` gives.
+pub fn import_file(path: &Path) -> Result<Import, GenomeError> {
+    let bytes = fs::read(path)?;
+    if output::is_png(&bytes) {
+        return from_png(&bytes).map(without_warnings);
+    }
+    let text =
+        String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let body = text.trim_start_matches('\u{feff}');
+    if body.trim_start().starts_with('{') {
+        return Ok(without_warnings(json::from_str(body)?));
+    }
+    import(&text)
+}
+
+fn without_warnings(genomes: Vec<Genome>) -> Import {
+    Import {
+        genomes,
+        warnings: Vec::new(),
+    }
+}
+
+/// Reads the genome embedded in a PNG rendered by rustflame or flam3.
+pub fn from_png(png: &[u8]) -> Result<Vec<Genome>, GenomeError> {
+    match output::png_text(png)?
+        .into_iter()
+        .find(|(keyword, _)| keyword == PNG_KEYWORD)
+    {
+        Some((_, text)) => parse(&text),
+        None => Err(GenomeError::NoFlames),
+    }
+}
+
+/// Reads every flame in a document: a lone `<flame>`, or a collection such as `<flames>` holding
+/// several.
+pub fn parse(text: &str) -> Result<Vec<Genome>, GenomeError> {
+    import(text).map(|import| import.genomes)
+}
+
+/// Reads every flame in a document, as `parse` does, warning about what it had to leave out.
+pub fn import(text: &str) -> Result<Import, GenomeError> {
+    let root = xml::parse(text)?;
+    let mut flames = Vec::new();
+    collect_flames(&root, &mut flames);
+    if flames.is_empty() {
+        return Err(GenomeError::NoFlames);
+    }
+    let mut warnings = Vec::new();
+    let genomes = flames
+        .into_iter()
+        .enumerate()
+        .map(|(i, flame)| read_genome(flame, i, &mut warnings))
+        .collect::<Result<_, _>>()?;
+    Ok(Import { genomes, warnings })
+}
+
+/// Writes genomes as a flame document: a lone `<flame>`, or `<flames>` holding several. Chained
+/// transforms have no place in a flame file, and fail with `VariationError::Chained`.
+pub fn to_xml(genomes: &[Genome]) -> Result<String, GenomeError> {
+    let mut flames = genomes
+        .iter()
+        .map(write_genome)
+        .collect::<Result<Vec<_>, _>>()?;
+    let root = if flames.len() == 1 {
+        flames.pop().unwrap()
+    } else {
+        let mut root = Element::new("flames");
+        root.children = flames;
+        root
+    };
+    Ok(root.write())
+}
+
+fn collect_flames<'a>(element: &'a Element, flames: &mut Vec<&'a Element>) {
+    if element.name == "flame" {
+        flames.push(element);
+    } else {
+        for child in &element.children {
+            collect_flames(child, flames);
+        }
+    }
+}
+
+fn read_genome(
+    element: &Element,
+    index: usize,
+    warnings: &mut Vec<Warning>,
+) -> Result<Genome, GenomeError> {
+    let mut warn = |kind| warnings.push(Warning { flame: index, kind });
+    let mut flame = Flame::new(String::from(element.attr("name").unwrap_or("")));
+    let mut settings = RenderSettings::default();
+    if let Some(size) = numbers::<u32>(element, "size", 2)? {
+        if size.contains(&0) {
+            return Err(GenomeError::BadAttribute {
+                element: element.name.clone(),
+                attribute: String::from("size"),
+                value: String::from(element.attr("size").unwrap_or("")),
+            });
+        }
+        settings.width = size[0];
+        settings.height = size[1];
+    }
+    if let Some(gamma) = number(element, "gamma")? {
+        settings.gamma = gamma;
+    }
+    if let Some(vibrancy) = number(element, "vibrancy")? {
+        settings.vibrancy = vibrancy;
+    }
+    if let Some(quality) = number(element, "quality")? {
+        settings.quality = quality;
+    }
+    //What rustflame rendered with beyond flam3's settings, so its images can be rendered again.
+    let bad_attribute = |name: &str| GenomeError::BadAttribute {
+        element: element.name.clone(),
+        attribute: String::from(name),
+        value: String::from(element.attr(name).unwrap_or("")),
+    };
+    if let Some(seed) = numbers::<u64>(element, "rustflame_seed", 1)? {
+        settings.seed = Some(seed[0]);
+    }
+    if let Some(time) = number(element, "rustflame_time")? {
+        settings.time =
+            Some(Duration::try_from_secs_f64(time).map_err(|_| bad_attribute("rustflame_time"))?);
+    }
+    if let Some(precision) = element.attr("rustflame_precision") {
+        settings.precision = match precision.trim() {
+            "double" => BinPrecision::Double,
+            "single" => BinPrecision::Single,
+            _ => return Err(bad_attribute("rustflame_precision")),
+        };
+    }
+    if let Some(background) = numbers::<f32>(element, "background", 3)? {
+        flame.background = ColorFRGB::clamped(background[0], background[1], background[2]);
+    }
+    //JWildfire keeps a flame's xforms and palette in its layers.
+    let mut layers: Vec<&Element> = element
+        .children_named("layer")
+        .filter(|layer| layer.attr("visible").map_or(true, |v| !is_off(v)))
+        .collect();
+    if element.children_named("xform").next().is_some() {
+        layers.insert(0, element);
+    } else if layers.is_empty() && element.children_named("layer").next().is_some() {
+        return Err(GenomeError::NoVisibleLayer(flame.name().to_string()));
+    }
+    let layer = layers.first().cloned().unwrap_or(element);
+    if layers.len() > 1 {
+        warn(WarningKind::ExtraLayers(layers.len() - 1));
+    }
+    let mut holders = vec![element];
+    if layer.name == "layer" {
+        holders.push(layer);
+    }
+    for (name, value) in holders.iter().flat_map(|e| e.attributes.iter()) {
+        if unsupported_flame_feature(name, value) || moves_camera(name, value, &settings) {
+            warn(WarningKind::Unsupported {
+                xform: None,
+                attribute: name.clone(),
+                value: value.clone(),
+            });
+        }
+    }
+    if layer.children_named("finalxform").next().is_some() {
+        warn(WarningKind::FinalTransform);
+    }
+    let has_palette = |e: &Element| {
+        e.children_named("palette").next().is_some() || e.children_named("color").next().is_some()
+    };
+    let palette = read_palette(if has_palette(layer) { layer } else { element })?;
+    for (i, xform) in layer.children_named("xform").enumerate() {
+        let (transform, weight, color) = read_xform(xform, i, &palette, &mut warn)?;
+        flame.add_transform(transform, color);
+        *flame.weights.last_mut().unwrap() = weight;
+    }
+    Ok(Genome { flame, settings })
+}
+
+fn write_genome(genome: &Genome) -> Result<Element, GenomeError> {
+    let flame = &genome.flame;
+    let settings = &genome.settings;
+    let mut element = Element::new("flame");
+    element.set_attr("name", flame.name());
+    element.set_attr("size", format!("{} {}", settings.width, settings.height));
+    element.set_attr("gamma", settings.gamma);
+    element.set_attr("vibrancy", settings.vibrancy);
+    element.set_attr("quality", settings.quality);
+    if let Some(seed) = settings.seed {
+        element.set_attr("rustflame_seed", seed);
+    }
+    if let Some(time) = settings.time {
+        element.set_attr("rustflame_time", time.as_secs_f64());
+    }
+    element.set_attr(
+        "rustflame_precision",
+        match settings.precision {
+            BinPrecision::Double => "double",
+            BinPrecision::Single => "single",
+        },
+    );
+    let background = flame.background;
+    element.set_attr(
+        "background",
+        format!("{} {} {}", background.r, background.g, background.b),
+    );
+    for (i, transform) in flame.transforms.iter().enumerate() {
+        //Each transform gets a palette entry of its own, as far as the palette goes.
+        let color = i.min(255) as f64 / 255.0;
+        //The renderer weighs transforms past the end of `weights` as 1, and so does the file.
+        let weight = flame.weights.get(i).cloned().unwrap_or(1.0);
+        element
+            .children
+            .push(write_xform(transform, weight, color)?);
+    }
+    for (i, color) in flame.colors.iter().enumerate().take(256) {
+        let mut entry = Element::new("color");
+        entry.set_attr("index", i);
+        entry.set_attr(
+            "rgb",
+            format!(
+                "{} {} {}",
+                color.r * 255.0,
+                color.g * 255.0,
+                color.b * 255.0
+            ),
+        );
+        element.children.push(entry);
+    }
+    Ok(element)
+}
+
+fn write_xform(transform: &Transform, weight: f64, color: f64) -> Result<Element, GenomeError> {
+    let affine = transform.affine().ok_or(VariationError::Chained)?;
+    let mut xform = Element::new("xform");
+    xform.set_attr("weight", weight);
+    xform.set_attr("color", color);
+    xform.set_attr(
+        "coefs",
+        format!(
+            "{} {} {} {} {} {}",
+            affine.xx, affine.xy, affine.yx, affine.yy, affine.cx, affine.cy
+        ),
+    );
+    if let Transform::AffineOnly(_) = transform {
+        xform.set_attr("linear", 1);
+    }
+    for (weight, function) in transform.variations() {
+        xform.set_attr(function.name(), weight);
+        for param in function.param_names() {
+            if let Some(value) = function.param(param) {
+                xform.set_attr(param, value);
+            }
+        }
+    }
+    Ok(xform)
+}
+
+fn read_xform(
+    xform: &Element,
+    index: usize,
+    palette: &[ColorFRGB],
+    warn: &mut FnMut(WarningKind),
+) -> Result<(Transform, f64, ColorFRGB), GenomeError> {
+    let affine = match numbers::<f64>(xform, "coefs", 6)? {
+        Some(c) => AffineTransform {
+            xx: c[0],
+            xy: c[1],
+            yx: c[2],
+            yy: c[3],
+            cx: c[4],
+            cy: c[5],
+        },
+        None => AffineTransform {
+            xx: 1.0,
+            xy: 0.0,
+            yx: 0.0,
+            yy: 1.0,
+            cx: 0.0,
+            cy: 0.0,
+        },
+    };
+    let mut functions = Vec::new();
+    for (name, value) in &xform.attributes {
+        let registered = registry_name(name);
+        if variations::param_names(registered).is_none() {
+            if unsupported_xform_feature(name, value) {
+                warn(WarningKind::Unsupported {
+                    xform: Some(index),
+                    attribute: name.clone(),
+                    value: value.clone(),
+                });
+            } else if is_unknown_variation(xform, name, value) {
+                warn(WarningKind::UnknownVariation {
+                    xform: index,
+                    name: name.clone(),
+                });
+            }
+            continue;
+        }
+        let weight = parse_attr::<f64>(xform, name, value)?;
+        if weight == 0.0 {
+            continue;
+        }
+        let mut function = variations::create(registered)?;
+        for param in variations::param_names(registered).unwrap() {
+            if let Some(value) = number(xform, param)? {
+                function.set_param(param, value);
+            }
+        }
+        functions.push((weight, function));
+    }
+    let transform = match functions.len() {
+        0 => Transform::AffineOnly(affine),
+        1 if functions[0].0 == 1.0 && functions[0].1.name() == "linear" => {
+            Transform::AffineOnly(affine)
+        }
+        _ => Transform::Sum(affine, functions),
+    };
+    let weight = number(xform, "weight")?.unwrap_or(1.0).max(0.0);
+    let index = number(xform, "color")?.unwrap_or(0.0).max(0.0).min(1.0);
+    let color = palette[(index * (palette.len() - 1) as f64).round() as usize];
+    Ok((transform, weight, color))
+}
+
+/// JWildfire's names for registry variations, where they differ. These 3D variations match their
+/// flat counterparts while z stays 0, as it always does here; its others bend the plane through z,
+/// and are left out as unknown.
+const JWILDFIRE_NAMES: &[(&str, &str)] = &[("linear3D", "linear"), ("spherical3D", "spherical")];
+
+fn registry_name(name: &str) -> &str {
+    JWILDFIRE_NAMES
+        .iter()
+        .find(|(jwildfire, _)| *jwildfire == name)
+        .map_or(name, |(_, registered)| registered)
+}
+
+/// Xform attributes that aren't variations or their parameters. Those ending in '_' name families
+/// of them.
+const XFORM_ATTRIBUTES: &[&str] = &[
+    "weight",
+    "color",
+    "symmetry",
+    "color_symmetry",
+    "color_speed",
+    "coefs",
+    "post",
+    "chaos",
+    "opacity",
+    "name",
+    "animate",
+    "var_color",
+    "plotmode",
+    "material",
+    "material_speed",
+    "mirror_pre_post_translations",
+    "color_type",
+    "draw_mode",
+    "target_color",
+    "weighting",
+    "weighting_",
+    "mod_",
+    "motion_",
+    "dc_",
+];
+
+fn is_xform_attribute(name: &str) -> bool {
+    XFORM_ATTRIBUTES
+        .iter()
+        .any(|a| name == *a || (a.ends_with('_') && name.starts_with(a)))
+}
+
+/// Whether an xform attribute names a variation that isn't in the registry: a number that's
+/// neither a known attribute nor the parameter of some other variation.
+fn is_unknown_variation(xform: &Element, name: &str, value: &str) -> bool {
+    if is_xform_attribute(name) || value.trim().parse::<f64>().is_err() {
+        return false;
+    }
+    !xform.attributes.iter().any(|(other, _)| {
+        !is_xform_attribute(other)
+            && name.len() > other.len()
+            && name.starts_with(other.as_str())
+            && name[other.len()..].starts_with('_')
+    })
+}
+
+/// Whether a flame or layer attribute sets JWildfire's extended camera or gradient mixing.
+fn unsupported_flame_feature(name: &str, value: &str) -> bool {
+    match name {
+        "cam_pitch" | "cam_yaw" | "cam_persp" | "cam_perspective" | "cam_dof" => !is_off(value),
+        name => {
+            (name == "mix" || name.starts_with("mixer") || name.contains("gradient_mix"))
+                && !is_off(value)
+        }
+    }
+}
+
+/// Whether a flam3 camera attribute frames anything but the square from -1 to 1 on both axes,
+/// which is all rustflame renders. `scale` is in pixels per unit, so only a square image has one
+/// that fits.
+fn moves_camera(name: &str, value: &str, settings: &RenderSettings) -> bool {
+    match name {
+        "center" => value
+            .split_whitespace()
+            .any(|v| v.parse::<f64>() != Ok(0.0)),
+        "rotate" | "zoom" => !is_off(value),
+        "scale" => value.trim().parse::<f64>().map_or(true, |scale| {
+            let fits = f64::from(settings.width) / 2.0;
+            settings.width != settings.height || (scale - fits).abs() > 1e-6 * fits
+        }),
+        _ => false,
+    }
+}
+
+/// Whether an xform attribute sets a feature rustflame can't render.
+fn unsupported_xform_feature(name: &str, value: &str) -> bool {
+    let all = |expected: &[f64]| {
+        let values: Vec<f64> = value
+            .split_whitespace()
+            .filter_map(|v| v.parse().ok())
+            .collect();
+        values
+            .iter()
+            .zip(expected.iter().cycle())
+            .all(|(v, e)| v == e)
+    };
+    match name {
+        "material" | "material_speed" => !is_off(value),
+        "opacity" | "chaos" => !all(&[1.0]),
+        "post" => !all(&[1.0, 0.0, 0.0, 1.0, 0.0, 0.0]),
+        "weighting" => !is_off(value),
+        name => name.starts_with("weighting") && name.ends_with("type") && !is_off(value),
+    }
+}
+
+/// Whether a value leaves a feature off: zero, empty, or spelled out as off.
+fn is_off(value: &str) -> bool {
+    let value = value.trim();
+    value.is_empty()
+        || value.parse::<f64>().map_or(false, |v| v == 0.0)
+        || ["off", "none", "false"]
+            .iter()
+            .any(|off| value.eq_ignore_ascii_case(off))
+}
+
+/// Reads a flame's 256-entry palette, given as hex rows in `<palette>` or as `<color>` elements.
+/// Missing entries are white.
+fn read_palette(flame: &Element) -> Result<Vec<ColorFRGB>, GenomeError> {
+    let white = ColorFRGB {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+    };
+    let mut palette = vec![white; 256];
+    if let Some(element) = flame.children_named("palette").next() {
+        let digits: Vec<u8> = element
+            .text
+            .bytes()
+            .filter(|b| !b.is_ascii_whitespace())
+            .collect();
+        //Alpha is ignored, wherever it sits.
+        let (channels, first) = match element.attr("format") {
+            Some("RGBA") => (4, 0),
+            Some("ARGB") => (4, 1),
+            _ => (3, 0),
+        };
+        for (entry, hex) in palette.iter_mut().zip(digits.chunks(channels * 2)) {
+            if hex.len() < channels * 2 {
+                break;
+            }
+            let channel = |i: usize| -> Result<f32, GenomeError> {
+                let pair = ::std::str::from_utf8(&hex[i * 2..i * 2 + 2]).unwrap_or("");
+                u8::from_str_radix(pair, 16)
+                    .map(|v| v as f32 / 255.0)
+                    .map_err(|_| GenomeError::BadAttribute {
+                        element: String::from("palette"),
+                        attribute: String::from("text"),
+                        value: String::from(pair),
+                    })
+            };
+            *entry = ColorFRGB::clamped(channel(first)?, channel(first + 1)?, channel(first + 2)?);
+        }
+    }
+    for color in flame.children_named("color") {
+        let index = number(color, "index")?.unwrap_or(-1.0);
+        if let Some(rgb) = numbers::<f32>(color, "rgb", 3)? {
+            if index >= 0.0 && (index as usize) < palette.len() {
+                palette[index as usize] =
+                    ColorFRGB::clamped(rgb[0] / 255.0, rgb[1] / 255.0, rgb[2] / 255.0);
+            }
+        }
+    }
+    Ok(palette)
+}
+
+fn parse_attr<T: FromStr>(element: &Element, name: &str, value: &str) -> Result<T, GenomeError> {
+    value.trim().parse().map_err(|_| GenomeError::BadAttribute {
+        element: element.name.clone(),
+        attribute: String::from(name),
+        value: String::from(value),
+    })
+}
+
+fn number(element: &Element, name: &str) -> Result<Option<f64>, GenomeError> {
+    match element.attr(name) {
+        Some(value) => parse_attr(element, name, value).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Reads an attribute holding exactly `count` space-separated numbers.
+fn numbers<T: FromStr>(
+    element: &Element,
+    name: &str,
+    count: usize,
+) -> Result<Option<Vec<T>>, GenomeError> {
+    let value = match element.attr(name) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    let parsed: Result<Vec<T>, GenomeError> = value
+        .split_whitespace()
+        .map(|v| parse_attr(element, name, v))
+        .collect();
+    match parsed {
+        Ok(ref values) if values.len() != count => Err(GenomeError::BadAttribute {
+            element: element.name.clone(),
+            attribute: String::from(name),
+            value: String::from(value),
+        }),
+        Ok(values) => Ok(Some(values)),
+        Err(_) => Err(GenomeError::BadAttribute {
+            element: element.name.clone(),
+            attribute: String::from(name),
+            value: String::from(value),
+        }),
+    }
+}
@@ -0,0 +1,352 @@
+//! Reversible edits to a flame, and an undo/redo history built from them.
+
+use color::ColorFRGB;
+use flame_2d::AffineTransform;
+use flame_2d::Flame;
+use flame_2d::Transform;
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::time::Duration;
+use std::time::Instant;
+use variations::VariationError;
+
+/// A single change to a flame. Applying one returns the edit that reverses it.
+#[derive(Clone)]
+pub enum Edit {
+    /// Inserts a transform at `index`, which may be the transform count to append.
+    InsertTransform {
+        index: usize,
+        transform: Transform,
+        color: ColorFRGB,
+        weight: f64,
+    },
+    RemoveTransform {
+        index: usize,
+    },
+    /// Swaps in a whole transform, keeping its color and weight.
+    ReplaceTransform {
+        index: usize,
+        transform: Transform,
+    },
+    SetAffine {
+        index: usize,
+        affine: AffineTransform,
+    },
+    SetColor {
+        index: usize,
+        color: ColorFRGB,
+    },
+    SetWeight {
+        index: usize,
+        weight: f64,
+    },
+    SetVariationWeight {
+        index: usize,
+        variation: String,
+        weight: f64,
+    },
+    SetVariationParam {
+        index: usize,
+        variation: String,
+        param: String,
+        value: f64,
+    },
+    SetBackground(ColorFRGB),
+}
+
+/// Why an edit couldn't be applied. The flame is left unchanged.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EditError {
+    /// `count` is how many transforms have the color and weight an edit needs, which is fewer
+    /// than the flame has if its `colors` or `weights` fall short.
+    NoTransform {
+        index: usize,
+        count: usize,
+    },
+    /// The transform is chained, and has no affine part.
+    NoAffine(usize),
+    Variation(VariationError),
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EditError::NoTransform { index, count } => write!(
+                f,
+                "transform {} is out of range, the flame has {}",
+                index, count
+            ),
+            EditError::NoAffine(index) => write!(f, "transform {} has no affine part", index),
+            EditError::Variation(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for EditError {}
+
+impl From<VariationError> for EditError {
+    fn from(e: VariationError) -> EditError {
+        EditError::Variation(e)
+    }
+}
+
+impl Edit {
+    /// Applies the edit, returning the edit that undoes it.
+    pub fn apply(self, flame: &mut Flame) -> Result<Edit, EditError> {
+        match self {
+            Edit::InsertTransform {
+                index,
+                transform,
+                color,
+                weight,
+            } => {
+                if index > editable(flame) {
+                    return Err(no_transform(flame, index));
+                }
+                flame.transforms.insert(index, transform);
+                flame.colors.insert(index, color);
+                flame.weights.insert(index, weight);
+                Ok(Edit::RemoveTransform { index })
+            }
+            Edit::RemoveTransform { index } => {
+                check_index(flame, index)?;
+                let undo = Edit::InsertTransform {
+                    index,
+                    transform: flame.transforms[index].clone(),
+                    color: flame.colors[index],
+                    weight: flame.weights[index],
+                };
+                flame.remove_transform(index);
+                Ok(undo)
+            }
+            Edit::ReplaceTransform { index, transform } => {
+                check_index(flame, index)?;
+                let old = mem::replace(&mut flame.transforms[index], transform);
+                Ok(Edit::ReplaceTransform {
+                    index,
+                    transform: old,
+                })
+            }
+            Edit::SetAffine { index, affine } => {
+                check_index(flame, index)?;
+                let current = flame.transforms[index]
+                    .affine_mut()
+                    .ok_or(EditError::NoAffine(index))?;
+                let old = mem::replace(current, affine);
+                Ok(Edit::SetAffine { index, affine: old })
+            }
+            Edit::SetColor { index, color } => {
+                check_index(flame, index)?;
+                let old = mem::replace(&mut flame.colors[index], color);
+                Ok(Edit::SetColor { index, color: old })
+            }
+            Edit::SetWeight { index, weight } => {
+                check_index(flame, index)?;
+                let old = mem::replace(&mut flame.weights[index], weight);
+                Ok(Edit::SetWeight { index, weight: old })
+            }
+            //Variation edits can change the transform's shape, so they are undone wholesale.
+            Edit::SetVariationWeight {
+                index,
+                variation,
+                weight,
+            } => {
+                check_index(flame, index)?;
+                let mut transform = flame.transforms[index].clone();
+                transform.set_variation_weight(&variation, weight)?;
+                Edit::ReplaceTransform { index, transform }.apply(flame)
+            }
+            Edit::SetVariationParam {
+                index,
+                variation,
+                param,
+                value,
+            } => {
+                check_index(flame, index)?;
+                let mut transform = flame.transforms[index].clone();
+                transform.set_variation_param(&variation, &param, value)?;
+                Edit::ReplaceTransform { index, transform }.apply(flame)
+            }
+            Edit::SetBackground(color) => {
+                let old = mem::replace(&mut flame.background, color);
+                Ok(Edit::SetBackground(old))
+            }
+        }
+    }
+
+    /// Whether two edits change the same value, so that a quick run of them can be undone as one.
+    fn same_target(&self, other: &Edit) -> bool {
+        match (self, other) {
+            (Edit::SetAffine { index: a, .. }, Edit::SetAffine { index: b, .. })
+            | (Edit::SetColor { index: a, .. }, Edit::SetColor { index: b, .. })
+            | (Edit::SetWeight { index: a, .. }, Edit::SetWeight { index: b, .. }) => a == b,
+            (
+                Edit::SetVariationWeight {
+                    index: a,
+                    variation: va,
+                    ..
+                },
+                Edit::SetVariationWeight {
+                    index: b,
+                    variation: vb,
+                    ..
+                },
+            ) => a == b && va == vb,
+            (
+                Edit::SetVariationParam {
+                    index: a,
+                    variation: va,
+                    param: pa,
+                    ..
+                },
+                Edit::SetVariationParam {
+                    index: b,
+                    variation: vb,
+                    param: pb,
+                    ..
+                },
+            ) => a == b && va == vb && pa == pb,
+            (Edit::SetBackground(_), Edit::SetBackground(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// How many transforms have a color and a weight to edit along with them.
+fn editable(flame: &Flame) -> usize {
+    flame
+        .transforms
+        .len()
+        .min(flame.colors.len())
+        .min(flame.weights.len())
+}
+
+fn check_index(flame: &Flame, index: usize) -> Result<(), EditError> {
+    if index < editable(flame) {
+        Ok(())
+    } else {
+        Err(no_transform(flame, index))
+    }
+}
+
+fn no_transform(flame: &Flame, index: usize) -> EditError {
+    EditError::NoTransform {
+        index,
+        count: editable(flame),
+    }
+}
+
+struct Entry {
+    /// Reverses the entry.
+    undo: Edit,
+    /// The most recent edit folded into the entry, to match later edits against.
+    last: Edit,
+    at: Instant,
+}
+
+/// Owns a flame and records every edit made through it, so edits can be undone and redone.
+///
+/// Edits to the same value that arrive within the coalescing window of each other, such as the
+/// stream of edits from dragging a slider, are folded into one undo step. `seal` ends a run early,
+/// for example when the slider is released.
+pub struct EditHistory {
+    flame: Flame,
+    undo: Vec<Entry>,
+    redo: Vec<Edit>,
+    coalesce_window: Duration,
+    sealed: bool,
+}
+
+impl EditHistory {
+    pub fn new(flame: Flame) -> EditHistory {
+        EditHistory {
+            flame,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            coalesce_window: Duration::from_millis(500),
+            sealed: true,
+        }
+    }
+    pub fn flame(&self) -> &Flame {
+        &self.flame
+    }
+    pub fn into_flame(self) -> Flame {
+        self.flame
+    }
+    pub fn set_coalesce_window(&mut self, window: Duration) {
+        self.coalesce_window = window;
+    }
+
+    /// Applies an edit and records it. Redo history is dropped.
+    pub fn apply(&mut self, edit: Edit) -> Result<(), EditError> {
+        let now = Instant::now();
+        let coalesce = !self.sealed
+            && match self.undo.last() {
+                Some(top) => top.last.same_target(&edit) && now - top.at <= self.coalesce_window,
+                None => false,
+            };
+        let undo = edit.clone().apply(&mut self.flame)?;
+        self.redo.clear();
+        self.sealed = false;
+        if coalesce {
+            let top = self.undo.last_mut().unwrap();
+            top.last = edit;
+            top.at = now;
+        } else {
+            self.undo.push(Entry {
+                undo,
+                last: edit,
+                at: now,
+            });
+        }
+        Ok(())
+    }
+
+    /// Stops the next edit from being folded into the last one.
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Reverses the most recent undo step. Returns false if there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let entry = match self.undo.pop() {
+            Some(entry) => entry,
+            None => return false,
+        };
+        //Undo edits were recorded against this exact flame, so they always apply.
+        let redo = entry
+            .undo
+            .apply(&mut self.flame)
+            .expect("undo history out of step with the flame");
+        self.redo.push(redo);
+        self.sealed = true;
+        true
+    }
+
+    /// Reapplies the most recently undone step. Returns false if there is nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let edit = match self.redo.pop() {
+            Some(edit) => edit,
+            None => return false,
+        };
+        let undo = edit
+            .clone()
+            .apply(&mut self.flame)
+            .expect("redo history out of step with the flame");
+        self.undo.push(Entry {
+            undo,
+            last: edit,
+            at: Instant::now(),
+        });
+        self.sealed = true;
+        true
+    }
+}
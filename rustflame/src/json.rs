@@ -0,0 +1,467 @@
+//! Genomes as JSON, for tools that would rather not read flam3 XML.
+//!
+//! A document holds the version of its schema and a list of genomes:
+//!
+//! ```json
+//! {
+//!   "schema": 1,
+//!   "genomes": [
+//!     {
+//!       "flame": {
+//!         "name": "Spark",
+//!         "background": { "r": 0.0, "g": 0.0, "b": 0.0 },
+//!         "transforms": [
+//!           {
+//!             "weight": 0.5,
+//!             "color": { "r": 1.0, "g": 0.5, "b": 0.0 },
+//!             "transform": {
+//!               "kind": "sum",
+//!               "affine": { "xx": 1.0, "xy": 0.0, "yx": 0.0, "yy": 1.0, "cx": 0.0, "cy": 0.0 },
+//!               "variations": [
+//!                 { "weight": 0.7, "name": "blob", "params": { "blob_low": 0.2 } },
+//!                 { "weight": 0.3, "name": "linear", "params": {} }
+//!               ]
+//!             }
+//!           }
+//!         ]
+//!       },
+//!       "settings": {
+//!         "width": 1920, "height": 1080, "gamma": 2.2, "vibrancy": 0.75, "quality": 50.0,
+//!         "seed": 7, "time": 12.5, "precision": "double"
+//!       }
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Colors have channels from 0 to 1. A transform's `kind` is one of
+//!
+//! * `affine`: just `affine`.
+//! * `basic`: `affine` feeding a single `variation`, an object with a `name` and `params`.
+//! * `sum`: `affine` feeding the weighted sum of `variations`.
+//! * `chain`: transform `first`, then transform `then`, with no affine of their own.
+//!
+//! Variations are named as in `variations::names`, their parameters as in
+//! `variations::param_names`. Parameters left out keep their defaults, as does a missing transform
+//! `weight` (1), flame `background` (black) or any of the `settings`. Of those, `seed` and `time`,
+//! in seconds, are only written when set, and `precision` is `double` or `single`. Documents no
+//! render could use are rejected: colors out of range, an empty image size, a gamma or quality
+//! that isn't positive, or an affine coefficient that isn't finite.
+//!
+//! `SCHEMA_VERSION` goes up whenever a document would stop meaning the same thing, and documents
+//! from any other version are rejected rather than misread.
+
+extern crate serde;
+extern crate serde_json;
+
+use self::serde::de;
+use self::serde::de::Error as DeError;
+use self::serde::ser::SerializeMap;
+use self::serde::ser::SerializeStruct;
+use self::serde::Deserialize;
+use self::serde::Deserializer;
+use self::serde::Serialize;
+use self::serde::Serializer;
+use color::ColorFRGB;
+use flame_2d::AffineTransform;
+use flame_2d::Flame;
+use flame_2d::RenderSettings;
+use flame_2d::Transform;
+use flame_2d::TransformFunction;
+use genome::Genome;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use variations;
+use variations::VariationError;
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum JsonError {
+    /// The document isn't JSON, or doesn't follow the schema.
+    Json(serde_json::Error),
+    /// The document follows another version of the schema.
+    UnsupportedSchema(u32),
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JsonError::Json(e) => e.fmt(f),
+            JsonError::UnsupportedSchema(version) => write!(
+                f,
+                "the document follows schema {}, and only schema {} can be read",
+                version, SCHEMA_VERSION
+            ),
+        }
+    }
+}
+
+impl Error for JsonError {}
+
+impl From<serde_json::Error> for JsonError {
+    fn from(e: serde_json::Error) -> JsonError {
+        JsonError::Json(e)
+    }
+}
+
+/// Writes genomes as an indented document.
+pub fn to_string(genomes: &[Genome]) -> Result<String, JsonError> {
+    let document = DocumentRef {
+        schema: SCHEMA_VERSION,
+        genomes,
+    };
+    Ok(serde_json::to_string_pretty(&document)?)
+}
+
+/// Reads the genomes in a document, checking its schema version before anything else.
+pub fn from_str(text: &str) -> Result<Vec<Genome>, JsonError> {
+    let header: Header = serde_json::from_str(text)?;
+    if header.schema != SCHEMA_VERSION {
+        return Err(JsonError::UnsupportedSchema(header.schema));
+    }
+    let document: Document = serde_json::from_str(text)?;
+    Ok(document.genomes)
+}
+
+#[derive(Serialize)]
+struct DocumentRef<'a> {
+    schema: u32,
+    genomes: &'a [Genome],
+}
+
+#[derive(Deserialize)]
+struct Header {
+    schema: u32,
+}
+
+#[derive(Deserialize)]
+struct Document {
+    genomes: Vec<Genome>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GenomeRepr {
+    flame: Flame,
+    #[serde(default)]
+    settings: RenderSettings,
+}
+
+impl<'de> Deserialize<'de> for Genome {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Genome, D::Error> {
+        let repr = GenomeRepr::deserialize(deserializer)?;
+        check_settings(&repr.settings).map_err(D::Error::custom)?;
+        Ok(Genome {
+            flame: repr.flame,
+            settings: repr.settings,
+        })
+    }
+}
+
+/// Settings no render could use are rejected here rather than by the renderer, with the document
+/// still at hand to blame.
+fn check_settings(settings: &RenderSettings) -> Result<(), String> {
+    if settings.width == 0 || settings.height == 0 {
+        return Err(format!(
+            "image size {}x{} is empty",
+            settings.width, settings.height
+        ));
+    }
+    for &(name, value) in &[("gamma", settings.gamma), ("quality", settings.quality)] {
+        if !value.is_finite() || value <= 0.0 {
+            return Err(format!("{} {} must be positive", name, value));
+        }
+    }
+    if !settings.vibrancy.is_finite() {
+        return Err(format!("vibrancy {} is not finite", settings.vibrancy));
+    }
+    Ok(())
+}
+
+//Flames keep their transforms, colors and weights side by side; documents keep each transform with
+//its color and weight.
+#[derive(Serialize)]
+struct FlameRef<'a> {
+    name: &'a str,
+    background: ColorFRGB,
+    transforms: Vec<XformRef<'a>>,
+}
+
+#[derive(Serialize)]
+struct XformRef<'a> {
+    weight: f64,
+    color: ColorFRGB,
+    transform: &'a Transform,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FlameRepr {
+    name: String,
+    #[serde(default = "black")]
+    background: ColorFRGB,
+    transforms: Vec<XformRepr>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct XformRepr {
+    #[serde(default = "one")]
+    weight: f64,
+    color: ColorFRGB,
+    transform: Transform,
+}
+
+fn black() -> ColorFRGB {
+    ColorFRGB {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+    }
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+impl Serialize for Flame {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        FlameRef {
+            name: self.name(),
+            background: self.background,
+            transforms: self
+                .transforms
+                .iter()
+                .zip(&self.colors)
+                .zip(&self.weights)
+                .map(|((transform, &color), &weight)| XformRef {
+                    weight,
+                    color,
+                    transform,
+                })
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Flame {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Flame, D::Error> {
+        let repr = FlameRepr::deserialize(deserializer)?;
+        let mut flame = Flame::new(repr.name);
+        flame.background = repr.background;
+        for xform in repr.transforms {
+            if !(xform.weight >= 0.0 && xform.weight.is_finite()) {
+                return Err(D::Error::invalid_value(
+                    de::Unexpected::Float(xform.weight),
+                    &"a finite, non-negative weight",
+                ));
+            }
+            flame.add_transform(xform.transform, xform.color);
+            *flame.weights.last_mut().unwrap() = xform.weight;
+        }
+        Ok(flame)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum TransformRef<'a> {
+    Affine {
+        affine: &'a AffineTransform,
+    },
+    Basic {
+        affine: &'a AffineTransform,
+        variation: &'a (TransformFunction + 'static),
+    },
+    Sum {
+        affine: &'a AffineTransform,
+        variations: Vec<WeightedRef<'a>>,
+    },
+    Chain {
+        first: &'a Transform,
+        then: &'a Transform,
+    },
+}
+
+#[derive(Serialize)]
+struct WeightedRef<'a> {
+    weight: f64,
+    #[serde(flatten)]
+    function: &'a (TransformFunction + 'static),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+enum TransformRepr {
+    Affine {
+        affine: AffineTransform,
+    },
+    Basic {
+        affine: AffineTransform,
+        variation: Box<TransformFunction>,
+    },
+    Sum {
+        affine: AffineTransform,
+        variations: Vec<Weighted>,
+    },
+    Chain {
+        first: Box<Transform>,
+        then: Box<Transform>,
+    },
+}
+
+#[derive(Deserialize)]
+struct Weighted {
+    weight: f64,
+    #[serde(flatten)]
+    function: Box<TransformFunction>,
+}
+
+impl Serialize for Transform {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Transform::AffineOnly(affine) => TransformRef::Affine { affine },
+            Transform::Basic(affine, function) => TransformRef::Basic {
+                affine,
+                variation: &**function,
+            },
+            Transform::Sum(affine, functions) => TransformRef::Sum {
+                affine,
+                variations: functions
+                    .iter()
+                    .map(|(weight, function)| WeightedRef {
+                        weight: *weight,
+                        function: &**function,
+                    })
+                    .collect(),
+            },
+            Transform::Chaining(first, then) => TransformRef::Chain { first, then },
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Transform {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Transform, D::Error> {
+        let repr = TransformRepr::deserialize(deserializer)?;
+        match repr {
+            TransformRepr::Affine { ref affine }
+            | TransformRepr::Basic { ref affine, .. }
+            | TransformRepr::Sum { ref affine, .. } => {
+                check_affine(affine).map_err(D::Error::custom)?
+            }
+            TransformRepr::Chain { .. } => {}
+        }
+        Ok(match repr {
+            TransformRepr::Affine { affine } => Transform::AffineOnly(affine),
+            TransformRepr::Basic { affine, variation } => Transform::Basic(affine, variation),
+            TransformRepr::Sum { affine, variations } => Transform::Sum(
+                affine,
+                variations
+                    .into_iter()
+                    .map(|v| (v.weight, v.function))
+                    .collect(),
+            ),
+            TransformRepr::Chain { first, then } => Transform::Chaining(first, then),
+        })
+    }
+}
+
+fn check_affine(affine: &AffineTransform) -> Result<(), String> {
+    let coefficients = [
+        affine.xx, affine.xy, affine.yx, affine.yy, affine.cx, affine.cy,
+    ];
+    if coefficients.iter().all(|c| c.is_finite()) {
+        Ok(())
+    } else {
+        Err(format!(
+            "affine {:?} has a coefficient that isn't finite",
+            coefficients
+        ))
+    }
+}
+
+/// `RenderSettings::time` as a number of seconds.
+pub mod seconds {
+    use super::serde::de::Error as DeError;
+    use super::serde::Deserialize;
+    use super::serde::Deserializer;
+    use super::serde::Serializer;
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(
+        time: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match time {
+            Some(time) => serializer.serialize_some(&time.as_secs_f64()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        match Option::<f64>::deserialize(deserializer)? {
+            Some(seconds) => Duration::try_from_secs_f64(seconds)
+                .map(Some)
+                .map_err(|_| D::Error::custom(format!("time {} is not a length of time", seconds))),
+            None => Ok(None),
+        }
+    }
+}
+
+/// A variation is written as its registered name and its parameters, so only the variations in
+/// the registry can be read back.
+impl Serialize for TransformFunction {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut function = serializer.serialize_struct("TransformFunction", 2)?;
+        function.serialize_field("name", self.name())?;
+        function.serialize_field("params", &Params(self))?;
+        function.end()
+    }
+}
+
+/// The parameters in the order the variation lists them.
+struct Params<'a>(&'a TransformFunction);
+
+impl<'a> Serialize for Params<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let names = self.0.param_names();
+        let mut params = serializer.serialize_map(Some(names.len()))?;
+        for name in names {
+            if let Some(value) = self.0.param(name) {
+                params.serialize_entry(name, &value)?;
+            }
+        }
+        params.end()
+    }
+}
+
+#[derive(Deserialize)]
+struct FunctionRepr {
+    name: String,
+    #[serde(default)]
+    params: BTreeMap<String, f64>,
+}
+
+impl<'de> Deserialize<'de> for Box<TransformFunction> {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Box<TransformFunction>, D::Error> {
+        let repr = FunctionRepr::deserialize(deserializer)?;
+        let mut function = variations::create(&repr.name).map_err(D::Error::custom)?;
+        for (param, value) in repr.params {
+            if !function.set_param(&param, value) {
+                return Err(D::Error::custom(VariationError::UnknownParam {
+                    variation: repr.name,
+                    param,
+                }));
+            }
+        }
+        Ok(function)
+    }
+}
@@ -1,12 +1,30 @@
-#![feature(assoc_unix_epoch)]
-#[macro_use]
-extern crate nom;
+extern crate lodepng;
 extern crate rand;
+#[macro_use]
+extern crate serde_derive;
 
-use nom::IResult;
+use color::ColorFRGB;
+use flame_2d::BinPrecision;
+use flame_2d::RenderSettings;
+use flame_2d::Renderer;
+use flame_2d::RendererError;
+use genome::Genome;
+use lodepng::RGB;
+use lodepng::RGBA;
+use output::PngBandWriter;
+use output::PngPixel;
+use std::convert::TryFrom;
 use std::error::Error;
+use std::fmt;
 use std::fs::File;
-use std::io::Read;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::slice;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
 
 #[cfg(test)]
 mod tests;
@@ -15,47 +33,498 @@ pub mod flame_2d;
 
 pub mod color;
 
+pub mod output;
+
+pub mod history;
+
+pub mod variations;
+
+pub mod genome;
+
+pub mod json;
+
+pub mod batch;
+
+pub mod palette;
+
+pub mod extract;
+
+mod xml;
+
+pub const USAGE: &str = "\
+usage: rustflame [options] <genome file or directory>...
+
+Renders one flame from each genome file, or with --batch every flame in every file, reading
+directories for their .flame and .json files. Genome files are flam3 XML or rustflame's JSON.
+PNGs rendered by rustflame or flam3 can be given as genome files too: they carry the genome they
+were rendered from.
+
+  -o, --output PATH      where to write each image, default {name}.png. {name}, {index} and
+                         {file} are replaced by the flame's name, its index in its file and the
+                         file's name without extension
+  -f, --format FORMAT    png, png-rgba, png16, png16-rgba or pfm; by default taken from the
+                         output's extension, or png
+  -n, --name NAME        render the flame with this name
+  -i, --index N          render the flame at this index, counting from 0 (default 0)
+  -W, --width N          image width, overriding the genome's
+  -H, --height N         image height, overriding the genome's
+  -q, --quality N        iterations per pixel, overriding the genome's
+  -t, --time SECONDS     iterate for this long instead of to a quality
+  -j, --threads N        render threads (default 1)
+  -s, --seed N           seed the iteration, making renders repeatable
+      --gamma G          overrides the genome's gamma
+      --vibrancy V       overrides the genome's vibrancy
+      --background R,G,B overrides the genome's background, channels from 0 to 1
+      --precision P      histogram precision, double (default) or single
+      --tile-rows N      render and write PNGs N rows at a time, so a large print never holds
+                         the whole image in memory; tiles render on one thread each
+  -b, --batch            render every flame; the output must use {name} or {index}
+      --overwrite        with --batch, render flames whose output already exists
+      --jobs N           with --batch, how many flames render at once, by default as many as
+                         fit on the machine's cores at --threads each
+      --report PATH      with --batch, write a tab-separated summary of every render
+  -h, --help             show this message
+";
+
+/// The image formats renders can be written as.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OutputFormat {
+    Png,
+    /// A transparent background, with alpha from the flame's density.
+    PngRgba,
+    Png16,
+    Png16Rgba,
+    /// The unclipped, linear image as a Portable FloatMap.
+    Pfm,
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        match name {
+            "png" => Some(OutputFormat::Png),
+            "png-rgba" => Some(OutputFormat::PngRgba),
+            "png16" => Some(OutputFormat::Png16),
+            "png16-rgba" => Some(OutputFormat::Png16Rgba),
+            "pfm" => Some(OutputFormat::Pfm),
+            _ => None,
+        }
+    }
+
+    /// PFM for a `.pfm` path, and PNG for anything else.
+    pub fn from_path(path: &str) -> OutputFormat {
+        match Path::new(path).extension() {
+            Some(ext) if ext.to_string_lossy().eq_ignore_ascii_case("pfm") => OutputFormat::Pfm,
+            _ => OutputFormat::Png,
+        }
+    }
+}
+
+/// Which flame to render from each genome file.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Selection {
+    Index(usize),
+    Name(String),
+}
+
+impl fmt::Display for Selection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Selection::Index(index) => write!(f, "at index {}", index),
+            Selection::Name(name) => write!(f, "named \"{}\"", name),
+        }
+    }
+}
+
+/// Command-line options. Anything left `None` comes from the genome.
+#[derive(Clone, Debug, PartialEq)]
 pub struct Config {
-    filename: String,
+    pub inputs: Vec<String>,
+    /// Where to write each image, see `USAGE` for the placeholders.
+    pub output: String,
+    pub format: Option<OutputFormat>,
+    pub selection: Selection,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub quality: Option<f64>,
+    pub time: Option<Duration>,
+    pub threads: usize,
+    pub seed: Option<u64>,
+    pub gamma: Option<f64>,
+    pub vibrancy: Option<f64>,
+    pub background: Option<ColorFRGB>,
+    pub precision: Option<BinPrecision>,
+    /// Streams PNGs out in bands of this many rows, see `Renderer::render_tiled`.
+    pub tile_rows: Option<u32>,
+    pub batch: bool,
+    pub overwrite: bool,
+    pub jobs: Option<usize>,
+    pub report: Option<String>,
+    /// `-h` or `--help` was given, so nothing else is read and the usage should be shown.
+    pub help: bool,
 }
+
 impl Config {
-    pub fn new(args: &[String]) -> Result<Config, &'static str> {
-        if args.len() < 2 {
-            return Err("Too few arguments");
+    /// Reads the options from the program's arguments, the first of which is the program name.
+    /// Options take their value as the next argument or after an '='.
+    pub fn new(args: &[String]) -> Result<Config, String> {
+        let mut config = Config {
+            inputs: Vec::new(),
+            output: String::from("{name}.png"),
+            format: None,
+            selection: Selection::Index(0),
+            width: None,
+            height: None,
+            quality: None,
+            time: None,
+            threads: 1,
+            seed: None,
+            gamma: None,
+            vibrancy: None,
+            background: None,
+            precision: None,
+            tile_rows: None,
+            batch: false,
+            overwrite: false,
+            jobs: None,
+            report: None,
+            help: false,
+        };
+        let mut selected = false;
+        let mut args = args.iter().skip(1);
+        while let Some(arg) = args.next() {
+            if !arg.starts_with('-') || arg == "-" {
+                config.inputs.push(arg.clone());
+                continue;
+            }
+            let (option, inline) = match arg.find('=') {
+                Some(i) => (&arg[..i], Some(&arg[i + 1..])),
+                None => (&arg[..], None),
+            };
+            match option {
+                "-h" | "--help" => {
+                    config.help = true;
+                    return Ok(config);
+                }
+                "-b" | "--batch" => {
+                    config.batch = true;
+                    continue;
+                }
+                "--overwrite" => {
+                    config.overwrite = true;
+                    continue;
+                }
+                "-n" | "--name" | "-i" | "--index" => selected = true,
+                _ => {}
+            }
+            let mut value = || match inline {
+                Some(value) => Ok(String::from(value)),
+                None => args
+                    .next()
+                    .cloned()
+                    .ok_or_else(|| format!("{} needs a value", option)),
+            };
+            match option {
+                "-o" | "--output" => config.output = value()?,
+                "-f" | "--format" => {
+                    let name = value()?;
+                    config.format = Some(
+                        OutputFormat::from_name(&name)
+                            .ok_or_else(|| format!("unknown output format {}", name))?,
+                    );
+                }
+                "-n" | "--name" => config.selection = Selection::Name(value()?),
+                "-i" | "--index" => config.selection = Selection::Index(parse(option, &value()?)?),
+                "-W" | "--width" => config.width = Some(positive(option, &value()?)?),
+                "-H" | "--height" => config.height = Some(positive(option, &value()?)?),
+                "-q" | "--quality" => config.quality = Some(non_negative(option, &value()?)?),
+                "-t" | "--time" => {
+                    let seconds = non_negative(option, &value()?)?;
+                    config.time = Some(Duration::from_millis((seconds * 1000.0) as u64));
+                }
+                "-j" | "--threads" => config.threads = positive::<usize>(option, &value()?)?,
+                "-s" | "--seed" => config.seed = Some(parse(option, &value()?)?),
+                "--gamma" => {
+                    let gamma: f64 = parse(option, &value()?)?;
+                    if !gamma.is_finite() || gamma <= 0.0 {
+                        return Err(format!("{} must be positive", option));
+                    }
+                    config.gamma = Some(gamma);
+                }
+                "--vibrancy" => config.vibrancy = Some(parse(option, &value()?)?),
+                "--background" => {
+                    let value = value()?;
+                    let channels: Vec<f32> = value
+                        .split(',')
+                        .map(|c| parse(option, c.trim()))
+                        .collect::<Result<_, _>>()?;
+                    config.background = match channels[..] {
+                        [r, g, b] => Some(
+                            ColorFRGB::try_from((r, g, b))
+                                .map_err(|e| format!("{} {}: {}", option, value, e))?,
+                        ),
+                        _ => {
+                            return Err(format!(
+                                "{} takes three channels from 0 to 1, not {}",
+                                option, value
+                            ))
+                        }
+                    };
+                }
+                "--jobs" => config.jobs = Some(positive(option, &value()?)?),
+                "--report" => config.report = Some(value()?),
+                "--precision" => {
+                    config.precision = match &value()?[..] {
+                        "double" => Some(BinPrecision::Double),
+                        "single" => Some(BinPrecision::Single),
+                        other => return Err(format!("unknown precision {}", other)),
+                    }
+                }
+                "--tile-rows" => config.tile_rows = Some(positive(option, &value()?)?),
+                _ => return Err(format!("unknown option {}", option)),
+            }
+        }
+        if config.inputs.is_empty() {
+            return Err(String::from("no genome files given"));
+        }
+        if config.batch {
+            if selected {
+                return Err(String::from(
+                    "--batch renders every flame, so can't pick one",
+                ));
+            }
+            if !config.output.contains("{name}") && !config.output.contains("{index}") {
+                return Err(format!(
+                    "every flame would be written to {}; use {{name}} or {{index}} in the output",
+                    config.output
+                ));
+            }
+        }
+        let format = config
+            .format
+            .unwrap_or_else(|| OutputFormat::from_path(&config.output));
+        if config.tile_rows.is_some() && format == OutputFormat::Pfm {
+            return Err(String::from("--tile-rows only streams PNGs"));
+        }
+        let placeholders = ["{name}", "{index}", "{file}"];
+        if config.inputs.len() > 1 && !placeholders.iter().any(|p| config.output.contains(p)) {
+            return Err(format!(
+                "every render would be written to {}; use {{name}} or {{file}} in the output",
+                config.output
+            ));
         }
-        let filename = args[1].clone();
-        Ok(Config { filename })
+        Ok(config)
+    }
+
+    /// How many flames a batch renders at once.
+    pub fn jobs(&self) -> usize {
+        self.jobs.unwrap_or_else(|| {
+            let cores = thread::available_parallelism().map_or(1, |n| n.get());
+            (cores / self.threads).max(1)
+        })
+    }
+
+    /// The genome with the command-line overrides applied, as it's rendered.
+    pub fn genome(&self, genome: &Genome) -> Genome {
+        let mut flame = genome.flame.clone();
+        if let Some(background) = self.background {
+            flame.background = background;
+        }
+        Genome {
+            flame,
+            settings: self.settings(&genome.settings),
+        }
+    }
+
+    /// The genome's render settings with the command-line overrides applied. A quality given on
+    /// the command line also overrides the genome's time budget.
+    pub fn settings(&self, genome: &RenderSettings) -> RenderSettings {
+        let time = match (self.time, self.quality) {
+            (Some(time), _) => Some(time),
+            (None, Some(_)) => None,
+            (None, None) => genome.time,
+        };
+        RenderSettings {
+            width: self.width.unwrap_or(genome.width),
+            height: self.height.unwrap_or(genome.height),
+            gamma: self.gamma.unwrap_or(genome.gamma),
+            vibrancy: self.vibrancy.unwrap_or(genome.vibrancy),
+            quality: self.quality.unwrap_or(genome.quality),
+            seed: self.seed.or(genome.seed),
+            time,
+            precision: self.precision.unwrap_or(genome.precision),
+        }
+    }
+
+    /// A renderer for the genome, with every option applied.
+    pub fn renderer(&self, genome: &Genome) -> Result<Renderer, RendererError> {
+        let genome = self.genome(genome);
+        Renderer::from_settings(&genome.settings, genome.flame)
+    }
+
+    /// Where to write the render of the flame at `index` in `input`.
+    pub fn output_path(&self, input: &str, index: usize, name: &str) -> String {
+        let file = Path::new(input)
+            .file_stem()
+            .map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+        let name = if name.is_empty() {
+            format!("{}-{}", file, index)
+        } else {
+            file_safe(name)
+        };
+        self.output
+            .replace("{name}", &name)
+            .replace("{index}", &index.to_string())
+            .replace("{file}", &file)
     }
 }
+
+/// Renders the selected flame from each input, stopping at the first failure. A batch carries on
+/// past failures, and reports how many there were at the end.
 pub fn run(config: Config) -> Result<(), Box<Error>> {
-    // first up, .flame file parsing
-
-    let mut f = File::open(config.filename)?;
-    let mut bytes: Vec<u8> = Vec::new();
-    let mut unprocessed;
-    let count = f.read_to_end(&mut bytes)?;
-    //read the 'flames' tag, if it exists.
-    let res: IResult<&[u8], &[u8]> = flames_tag(&bytes);
-    match res {
-        IResult::Done(remaining, result) => {
-            unprocessed = remaining;
-            println!(
-                "This group of flames is named: {}",
-                String::from_utf8(result.to_vec())?
-            )
-        }
-        IResult::Error(err) => {
-            println!("Error reading \"flames\" tag: {:?}", err);
-        }
-        IResult::Incomplete(needed) => println!("Not enough characters were given..."),
+    if config.batch {
+        let summary = batch::run(&config);
+        println!("{}", summary);
+        if let Some(ref report) = config.report {
+            summary
+                .write_report(BufWriter::new(File::create(report)?))
+                .map_err(|e| format!("{}: {}", report, e))?;
+        }
+        return match summary.failed() {
+            0 => Ok(()),
+            failed => Err(From::from(format!("{} of the batch failed", failed))),
+        };
+    }
+    for input in &config.inputs {
+        let import =
+            genome::import_file(Path::new(input)).map_err(|e| format!("{}: {}", input, e))?;
+        let (index, genome) = select(&import.genomes, &config.selection)
+            .ok_or_else(|| format!("{}: there is no flame {}", input, config.selection))?;
+        for warning in import.warnings.iter().filter(|w| w.flame == index) {
+            println!("{}: {}", input, warning);
+        }
+        let path = config.output_path(input, index, genome.flame.name());
+        let format = config
+            .format
+            .unwrap_or_else(|| OutputFormat::from_path(&path));
+        let start = Instant::now();
+        let mut renderer = config
+            .renderer(genome)
+            .map_err(|e| format!("{}: {}", input, e))?;
+        render_to(
+            &mut renderer,
+            &config.genome(genome),
+            format,
+            config.threads,
+            config.tile_rows,
+            &path,
+        )
+        .map_err(|e| format!("{}: {}", path, e))?;
+        println!(
+            "Rendered \"{}\" to {} in {:?}",
+            genome.flame.name(),
+            path,
+            start.elapsed()
+        );
     }
     Ok(())
 }
-named!(
-    flames_tag,
-    dbg!(ws!(delimited!(
-        ws!(dbg!(tag!("<"))),
-        ws!(take_until!(">")),
-        ws!(tag!(">"))
-    )))
-);
+
+/// Finds the selected flame and its index.
+pub fn select<'a>(genomes: &'a [Genome], selection: &Selection) -> Option<(usize, &'a Genome)> {
+    match selection {
+        Selection::Index(index) => genomes.get(*index).map(|g| (*index, g)),
+        Selection::Name(name) => genomes
+            .iter()
+            .enumerate()
+            .find(|(_, g)| g.flame.name() == name),
+    }
+}
+
+/// Renders and writes the image. PNGs carry `genome`, which should be what `renderer` renders, so
+/// that `genome::load` can read it back. With `tile_rows`, PNGs are rendered and written a band of
+/// that many rows at a time on this thread, rather than whole on `threads`.
+pub fn render_to(
+    renderer: &mut Renderer,
+    genome: &Genome,
+    format: OutputFormat,
+    threads: usize,
+    tile_rows: Option<u32>,
+    path: &str,
+) -> Result<(), Box<Error>> {
+    match format {
+        OutputFormat::Png => write_png::<RGB<u8>>(renderer, genome, threads, tile_rows, path),
+        OutputFormat::PngRgba => write_png::<RGBA<u8>>(renderer, genome, threads, tile_rows, path),
+        OutputFormat::Png16 => write_png::<RGB<u16>>(renderer, genome, threads, tile_rows, path),
+        OutputFormat::Png16Rgba => {
+            write_png::<RGBA<u16>>(renderer, genome, threads, tile_rows, path)
+        }
+        OutputFormat::Pfm => {
+            let bitmap = renderer.render_threaded_as::<RGB<f32>>(threads);
+            output::write_atomically(Path::new(path), |w| output::write_pfm(w, &bitmap))?;
+            Ok(())
+        }
+    }
+}
+
+fn write_png<P: PngPixel>(
+    renderer: &mut Renderer,
+    genome: &Genome,
+    threads: usize,
+    tile_rows: Option<u32>,
+    path: &str,
+) -> Result<(), Box<Error>> {
+    let text = genome::to_xml(slice::from_ref(genome))?;
+    match tile_rows {
+        Some(rows) => {
+            let (width, height) = (renderer.image_width(), renderer.image_height());
+            output::write_atomically(Path::new(path), |w| {
+                let mut png = PngBandWriter::<_, P>::new(w, width, height)?;
+                png.write_text(genome::PNG_KEYWORD, &text)?;
+                renderer.render_tiled(rows, |_, band: &[P]| png.write_rows(band))?;
+                png.finish().map(|_| ())
+            })?;
+        }
+        None => {
+            let bitmap = renderer.render_threaded_as::<P>(threads);
+            let png = output::encode_png(&bitmap)?;
+            let png = output::add_png_text(&png, genome::PNG_KEYWORD, &text)?;
+            output::write_atomically(Path::new(path), |w| w.write_all(&png))?;
+        }
+    }
+    Ok(())
+}
+
+/// Replaces characters that can't appear in file names.
+fn file_safe(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+fn parse<T: FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} can't be {}", option, value))
+}
+
+fn positive<T: FromStr + PartialOrd + Default>(option: &str, value: &str) -> Result<T, String> {
+    let n: T = parse(option, value)?;
+    if n > T::default() {
+        Ok(n)
+    } else {
+        Err(format!("{} must be at least 1", option))
+    }
+}
+
+fn non_negative(option: &str, value: &str) -> Result<f64, String> {
+    let n: f64 = parse(option, value)?;
+    if n >= 0.0 && n.is_finite() {
+        Ok(n)
+    } else {
+        Err(format!("{} can't be {}", option, value))
+    }
+}
@@ -0,0 +1,484 @@
+extern crate flate2;
+extern crate lodepng;
+
+use self::flate2::read::ZlibDecoder;
+use self::flate2::write::ZlibEncoder;
+use self::flate2::Compression;
+use self::flate2::Crc;
+use self::lodepng::Bitmap;
+use self::lodepng::ColorType;
+use self::lodepng::RGB;
+use self::lodepng::RGBA;
+use color::clamp_unit;
+use color::ColorFRGB;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::process;
+
+const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// A pixel type a render can be written out as.
+pub trait OutputPixel: Copy {
+    /// Builds a pixel from an unclipped, premultiplied tonemapped value. Opaque pixel types
+    /// composite it over `background`.
+    fn from_tonemapped(c: RGBA<f64>, background: ColorFRGB) -> Self;
+}
+
+impl OutputPixel for RGB<u8> {
+    fn from_tonemapped(c: RGBA<f64>, background: ColorFRGB) -> RGB<u8> {
+        let uncovered = 1.0 - clamp_unit(c.a);
+        RGB {
+            r: quantize_u8(c.r + uncovered * background.r as f64),
+            g: quantize_u8(c.g + uncovered * background.g as f64),
+            b: quantize_u8(c.b + uncovered * background.b as f64),
+        }
+    }
+}
+
+impl OutputPixel for RGBA<u8> {
+    fn from_tonemapped(c: RGBA<f64>, _background: ColorFRGB) -> RGBA<u8> {
+        let alpha = clamp_unit(c.a);
+        if alpha <= 0.0 {
+            return RGBA {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0,
+            };
+        }
+        RGBA {
+            r: quantize_u8(c.r / alpha),
+            g: quantize_u8(c.g / alpha),
+            b: quantize_u8(c.b / alpha),
+            a: quantize_u8(alpha),
+        }
+    }
+}
+
+impl OutputPixel for RGB<u16> {
+    fn from_tonemapped(c: RGBA<f64>, background: ColorFRGB) -> RGB<u16> {
+        let uncovered = 1.0 - clamp_unit(c.a);
+        RGB {
+            r: quantize_u16(c.r + uncovered * background.r as f64),
+            g: quantize_u16(c.g + uncovered * background.g as f64),
+            b: quantize_u16(c.b + uncovered * background.b as f64),
+        }
+    }
+}
+
+impl OutputPixel for RGBA<u16> {
+    fn from_tonemapped(c: RGBA<f64>, _background: ColorFRGB) -> RGBA<u16> {
+        let alpha = clamp_unit(c.a);
+        if alpha <= 0.0 {
+            return RGBA {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0,
+            };
+        }
+        RGBA {
+            r: quantize_u16(c.r / alpha),
+            g: quantize_u16(c.g / alpha),
+            b: quantize_u16(c.b / alpha),
+            a: quantize_u16(alpha),
+        }
+    }
+}
+
+/// A pixel type that can be stored in a PNG.
+pub trait PngPixel: OutputPixel {
+    /// lodepng's color types use the PNG header codes as their discriminants.
+    const COLOR_TYPE: ColorType;
+    const BIT_DEPTH: u8;
+    /// Appends the pixel's samples in PNG (big-endian) byte order.
+    fn push_samples(&self, out: &mut Vec<u8>);
+}
+
+impl PngPixel for RGB<u8> {
+    const COLOR_TYPE: ColorType = ColorType::RGB;
+    const BIT_DEPTH: u8 = 8;
+    fn push_samples(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[self.r, self.g, self.b]);
+    }
+}
+
+impl PngPixel for RGBA<u8> {
+    const COLOR_TYPE: ColorType = ColorType::RGBA;
+    const BIT_DEPTH: u8 = 8;
+    fn push_samples(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[self.r, self.g, self.b, self.a]);
+    }
+}
+
+impl PngPixel for RGB<u16> {
+    const COLOR_TYPE: ColorType = ColorType::RGB;
+    const BIT_DEPTH: u8 = 16;
+    fn push_samples(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.r.to_be_bytes());
+        out.extend_from_slice(&self.g.to_be_bytes());
+        out.extend_from_slice(&self.b.to_be_bytes());
+    }
+}
+
+impl PngPixel for RGBA<u16> {
+    const COLOR_TYPE: ColorType = ColorType::RGBA;
+    const BIT_DEPTH: u8 = 16;
+    fn push_samples(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.r.to_be_bytes());
+        out.extend_from_slice(&self.g.to_be_bytes());
+        out.extend_from_slice(&self.b.to_be_bytes());
+        out.extend_from_slice(&self.a.to_be_bytes());
+    }
+}
+
+impl OutputPixel for RGB<f32> {
+    /// Keeps the tonemapped value unclipped, so highlights survive for grading downstream.
+    fn from_tonemapped(c: RGBA<f64>, background: ColorFRGB) -> RGB<f32> {
+        let uncovered = 1.0 - clamp_unit(c.a);
+        RGB {
+            r: (c.r + uncovered * background.r as f64) as f32,
+            g: (c.g + uncovered * background.g as f64) as f32,
+            b: (c.b + uncovered * background.b as f64) as f32,
+        }
+    }
+}
+
+/// Writes a color Portable FloatMap: little-endian f32 samples, stored bottom row first.
+pub fn write_pfm<W: Write>(mut writer: W, bitmap: &Bitmap<RGB<f32>>) -> io::Result<()> {
+    //A negative scale marks the samples as little-endian.
+    write!(writer, "PF\n{} {}\n-1.0\n", bitmap.width, bitmap.height)?;
+    let mut row_buffer = Vec::with_capacity(bitmap.width * 12);
+    for row in bitmap.buffer.chunks(bitmap.width.max(1)).rev() {
+        row_buffer.clear();
+        for pixel in row {
+            row_buffer.extend_from_slice(&pixel.r.to_bits().to_le_bytes());
+            row_buffer.extend_from_slice(&pixel.g.to_bits().to_le_bytes());
+            row_buffer.extend_from_slice(&pixel.b.to_bits().to_le_bytes());
+        }
+        writer.write_all(&row_buffer)?;
+    }
+    writer.flush()
+}
+
+/// Creates `path` through `write`, which writes to a temporary file beside it that is renamed into
+/// place only once complete. A render that fails or is killed part way never leaves a truncated
+/// image behind, or clobbers the one already there.
+pub fn write_atomically<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    let name = path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} doesn't name a file", path.display()),
+        )
+    })?;
+    let temp = path.with_file_name(format!(".{}.{}.tmp", name.to_string_lossy(), process::id()));
+    let written = File::create(&temp)
+        .and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            write(&mut writer)?;
+            writer.into_inner().map_err(|e| e.into_error())?.sync_all()
+        })
+        .and_then(|()| fs::rename(&temp, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written
+}
+
+/// Encodes a whole image as a PNG at the pixel type's bit depth, for the cases `lodepng::encode24`
+/// and `encode32` don't cover.
+pub fn encode_png<P: PngPixel>(bitmap: &Bitmap<P>) -> Result<Vec<u8>, lodepng::Error> {
+    let mut samples = Vec::with_capacity(bitmap.buffer.len() * 8);
+    for pixel in &bitmap.buffer {
+        pixel.push_samples(&mut samples);
+    }
+    lodepng::encode_memory(
+        &samples,
+        bitmap.width,
+        bitmap.height,
+        P::COLOR_TYPE,
+        P::BIT_DEPTH as u32,
+    )
+}
+
+/// Clamps to [0, 1], mapping NaN to 0.
+fn quantize_u8(v: f64) -> u8 {
+    if v <= 0.0 {
+        0u8
+    } else if v >= 1.0 {
+        255u8
+    } else {
+        (v * 255.0) as u8
+    }
+}
+
+fn quantize_u16(v: f64) -> u16 {
+    if v <= 0.0 {
+        0u16
+    } else if v >= 1.0 {
+        65535u16
+    } else {
+        (v * 65535.0) as u16
+    }
+}
+
+/// Writes a PNG a band of rows at a time, so an image never has to be held in memory all at once.
+/// Pairs with `Renderer::render_tiled`.
+pub struct PngBandWriter<W: Write, P: PngPixel> {
+    writer: W,
+    width: u32,
+    height: u32,
+    rows_written: u32,
+    encoder: ZlibEncoder<Vec<u8>>,
+    row_buffer: Vec<u8>,
+    pixel: PhantomData<P>,
+}
+
+impl<W: Write, P: PngPixel> PngBandWriter<W, P> {
+    /// Starts an image of `width` by `height` pixels, neither of which can be 0.
+    pub fn new(mut writer: W, width: u32, height: u32) -> io::Result<PngBandWriter<W, P>> {
+        if width == 0 || height == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("a {}x{} image has no pixels", width, height),
+            ));
+        }
+        writer.write_all(&PNG_SIGNATURE)?;
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&width.to_be_bytes());
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        //Default compression and filtering, no interlacing.
+        ihdr.extend_from_slice(&[P::BIT_DEPTH, P::COLOR_TYPE as u8, 0, 0, 0]);
+        write_chunk(&mut writer, b"IHDR", &ihdr)?;
+        Ok(PngBandWriter {
+            writer,
+            width,
+            height,
+            rows_written: 0,
+            encoder: ZlibEncoder::new(Vec::new(), Compression::default()),
+            row_buffer: Vec::new(),
+            pixel: PhantomData,
+        })
+    }
+
+    /// Appends whole rows of pixels, compressing them into an IDAT chunk.
+    pub fn write_rows(&mut self, data: &[P]) -> io::Result<()> {
+        let stride = self.width as usize;
+        if data.len() % stride != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "band data is not a whole number of rows",
+            ));
+        }
+        let rows = (data.len() / stride) as u32;
+        if self.rows_written + rows > self.height {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "more rows written than the image height",
+            ));
+        }
+        for row in data.chunks(stride) {
+            //Filter type 0: the row is stored as-is.
+            self.row_buffer.clear();
+            self.row_buffer.push(0);
+            for pixel in row {
+                pixel.push_samples(&mut self.row_buffer);
+            }
+            self.encoder.write_all(&self.row_buffer)?;
+        }
+        self.rows_written += rows;
+        self.encoder.flush()?;
+        self.write_pending_idat()
+    }
+
+    /// Writes a text chunk, see `add_png_text`. It can come before the first band or after the
+    /// last, as a PNG's image data has to be in one unbroken run of chunks.
+    pub fn write_text(&mut self, keyword: &str, text: &str) -> io::Result<()> {
+        if self.rows_written > 0 && self.rows_written < self.height {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "text can't be written between bands",
+            ));
+        }
+        if self.rows_written > 0 {
+            //Every row is in, so the rest of the compressed stream goes out ahead of the text.
+            self.encoder.try_finish()?;
+        }
+        self.write_pending_idat()?;
+        let (kind, data) = text_chunk(keyword, text)?;
+        write_chunk(&mut self.writer, kind, &data)
+    }
+
+    /// Finishes the compressed stream and writes the trailing chunks, returning the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.rows_written != self.height {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "image finished before all rows were written",
+            ));
+        }
+        self.encoder.try_finish()?;
+        self.write_pending_idat()?;
+        write_chunk(&mut self.writer, b"IEND", &[])?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_pending_idat(&mut self) -> io::Result<()> {
+        if self.encoder.get_ref().is_empty() {
+            return Ok(());
+        }
+        write_chunk(&mut self.writer, b"IDAT", self.encoder.get_ref())?;
+        self.encoder.get_mut().clear();
+        Ok(())
+    }
+}
+
+fn write_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    writer.write_all(&crc.sum().to_be_bytes())
+}
+
+pub fn is_png(data: &[u8]) -> bool {
+    data.starts_with(&PNG_SIGNATURE)
+}
+
+/// Adds a text chunk to an encoded PNG, just after its header. The text goes in a `tEXt` chunk if
+/// it's ASCII, so that the simplest readers find it, and in an uncompressed `iTXt` otherwise.
+pub fn add_png_text(png: &[u8], keyword: &str, text: &str) -> io::Result<Vec<u8>> {
+    let chunks = png_chunks(png)?;
+    let header_end = match chunks.first() {
+        Some(&(ref kind, _, end)) if kind == b"IHDR" => end,
+        _ => return Err(invalid_png("the image doesn't start with a header")),
+    };
+    let (kind, data) = text_chunk(keyword, text)?;
+    let mut out = Vec::with_capacity(png.len() + data.len() + 12);
+    out.extend_from_slice(&png[..header_end]);
+    write_chunk(&mut out, kind, &data)?;
+    out.extend_from_slice(&png[header_end..]);
+    Ok(out)
+}
+
+/// Reads every `tEXt`, `zTXt` and `iTXt` chunk in a PNG, as keywords and their text.
+pub fn png_text(png: &[u8]) -> io::Result<Vec<(String, String)>> {
+    let mut texts = Vec::new();
+    for (kind, data, _) in png_chunks(png)? {
+        let (keyword, rest) = match data.iter().position(|&b| b == 0) {
+            Some(i) => (latin1(&data[..i]), &data[i + 1..]),
+            None => continue,
+        };
+        let text = match &kind {
+            b"tEXt" => latin1(rest),
+            b"zTXt" if rest.len() > 1 => latin1(&inflate(&rest[1..])?),
+            b"iTXt" if rest.len() > 2 => {
+                let compressed = rest[0] == 1;
+                //Skip the language tag and the translated keyword.
+                let mut fields = rest[2..].splitn(3, |&b| b == 0);
+                let text = match (fields.next(), fields.next(), fields.next()) {
+                    (Some(_), Some(_), Some(text)) => text,
+                    _ => return Err(invalid_png("an iTXt chunk is cut short")),
+                };
+                let text = if compressed {
+                    inflate(text)?
+                } else {
+                    text.to_vec()
+                };
+                String::from_utf8(text).map_err(|_| invalid_png("an iTXt chunk isn't UTF-8"))?
+            }
+            _ => continue,
+        };
+        texts.push((keyword, text));
+    }
+    Ok(texts)
+}
+
+/// A chunk's type and data, and where it ends in the image.
+type Chunk<'a> = ([u8; 4], &'a [u8], usize);
+
+/// Splits a PNG into its chunks, checking their CRCs on the way.
+fn png_chunks(png: &[u8]) -> io::Result<Vec<Chunk>> {
+    if !is_png(png) {
+        return Err(invalid_png("not a PNG"));
+    }
+    let mut chunks = Vec::new();
+    let mut pos = PNG_SIGNATURE.len();
+    while pos < png.len() {
+        if png.len() - pos < 12 {
+            return Err(invalid_png("the image is cut short"));
+        }
+        let len = four_bytes(&png[pos..]);
+        let len = u32::from_be_bytes(len) as usize;
+        if png.len() - pos - 12 < len {
+            return Err(invalid_png("the image is cut short"));
+        }
+        let kind = four_bytes(&png[pos + 4..]);
+        let data = &png[pos + 8..pos + 8 + len];
+        let end = pos + 12 + len;
+        let mut crc = Crc::new();
+        crc.update(&kind);
+        crc.update(data);
+        if crc.sum() != u32::from_be_bytes(four_bytes(&png[end - 4..])) {
+            return Err(invalid_png("a chunk's CRC doesn't match"));
+        }
+        chunks.push((kind, data, end));
+        pos = end;
+        if &kind == b"IEND" {
+            break;
+        }
+    }
+    Ok(chunks)
+}
+
+fn four_bytes(bytes: &[u8]) -> [u8; 4] {
+    [bytes[0], bytes[1], bytes[2], bytes[3]]
+}
+
+fn text_chunk(keyword: &str, text: &str) -> io::Result<(&'static [u8; 4], Vec<u8>)> {
+    //Keywords are 1 to 79 printable Latin-1 characters; ASCII keeps them the same in UTF-8.
+    let printable = keyword.bytes().all(|b| (32..127).contains(&b));
+    if keyword.is_empty() || keyword.len() > 79 || !printable {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("\"{}\" can't be a PNG text keyword", keyword),
+        ));
+    }
+    let mut data = Vec::with_capacity(keyword.len() + text.len() + 5);
+    data.extend_from_slice(keyword.as_bytes());
+    data.push(0);
+    if text.is_ascii() {
+        data.extend_from_slice(text.as_bytes());
+        return Ok((b"tEXt", data));
+    }
+    //Uncompressed, with no language tag or translated keyword.
+    data.extend_from_slice(&[0, 0, 0, 0]);
+    data.extend_from_slice(text.as_bytes());
+    Ok((b"iTXt", data))
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+fn inflate(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    ZlibDecoder::new(data).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+fn invalid_png(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,743 @@
+//! Gradients, and the files artists keep them in: Apophysis and UltraFractal `.ugr`/`.gradient`
+//! files, Fractint `.map` files, flam3's numbered palettes file and GIMP `.ggr` gradients.
+
+use color::average;
+use color::clamp_unit;
+use color::lerp;
+use color::unit_color;
+use color::ColorFHSV;
+use color::ColorFRGB;
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use xml;
+use xml::Element;
+use xml::XmlError;
+
+/// How many colors a flame's palette holds, and what the readers resample gradients to.
+pub const PALETTE_SIZE: usize = 256;
+
+/// Evenly spaced colors running from one end of a gradient to the other.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Palette {
+    pub name: String,
+    pub colors: Vec<ColorFRGB>,
+}
+
+impl Palette {
+    pub fn new(name: &str, colors: Vec<ColorFRGB>) -> Palette {
+        Palette {
+            name: String::from(name),
+            colors,
+        }
+    }
+
+    /// The color `t` of the way along the gradient, from 0 to 1, blended from the nearest two. An
+    /// empty palette is black throughout.
+    pub fn sample(&self, t: f64) -> ColorFRGB {
+        if self.colors.is_empty() {
+            return unit_color(0.0, 0.0, 0.0);
+        }
+        let last = self.colors.len().saturating_sub(1);
+        let position = clamp_unit(t) * last as f64;
+        let i = (position.floor() as usize).min(last);
+        let next = (i + 1).min(last);
+        lerp(self.colors[i], self.colors[next], position - i as f64)
+    }
+
+    /// The colors as hue, saturation and value.
+    pub fn hsv(&self) -> Vec<ColorFHSV> {
+        self.colors.iter().map(|&c| ColorFHSV::from(c)).collect()
+    }
+
+    /// The gradient sampled at `count` evenly spaced points, ends included.
+    pub fn resample(&self, count: usize) -> Palette {
+        let steps = count.saturating_sub(1).max(1) as f64;
+        let colors = (0..count).map(|i| self.sample(i as f64 / steps)).collect();
+        Palette::new(&self.name, colors)
+    }
+
+    //The edits below are the ones in Apophysis's gradient editor. Each gives a new palette, named
+    //as this one.
+
+    /// Turns every hue around the color wheel by `degrees`, either way.
+    pub fn rotate_hue(&self, degrees: f32) -> Palette {
+        self.map_hsv(|c| ColorFHSV::wrapped(c.h + degrees, c.s, c.v))
+    }
+
+    /// Multiplies every saturation by `factor`, keeping within 0 to 1.
+    pub fn scale_saturation(&self, factor: f32) -> Palette {
+        self.map_hsv(|c| ColorFHSV::wrapped(c.h, c.s * factor, c.v))
+    }
+
+    /// Multiplies every value by `factor`, keeping within 0 to 1.
+    pub fn scale_value(&self, factor: f32) -> Palette {
+        self.map_hsv(|c| ColorFHSV::wrapped(c.h, c.s, c.v * factor))
+    }
+
+    /// Each color's complement, one minus each channel.
+    pub fn invert(&self) -> Palette {
+        self.map(|c| ColorFRGB {
+            r: 1.0 - c.r,
+            g: 1.0 - c.g,
+            b: 1.0 - c.b,
+        })
+    }
+
+    /// The gradient running the other way.
+    pub fn reverse(&self) -> Palette {
+        let colors = self.colors.iter().rev().cloned().collect();
+        Palette::new(&self.name, colors)
+    }
+
+    /// Moves every color `steps` places up the palette, those off the end wrapping around to the
+    /// start. Negative steps move colors down.
+    pub fn rotate(&self, steps: isize) -> Palette {
+        let mut colors = self.colors.clone();
+        if !colors.is_empty() {
+            let steps = steps.rem_euclid(colors.len() as isize) as usize;
+            colors.rotate_right(steps);
+        }
+        Palette::new(&self.name, colors)
+    }
+
+    /// Averages each color with the `radius` colors either side of it. The ends of the palette
+    /// don't wrap around, so blur only with their own side.
+    pub fn blur(&self, radius: usize) -> Palette {
+        let len = self.colors.len();
+        //No window is wider than the palette, however large the radius.
+        let radius = radius.min(len);
+        let colors = (0..len)
+            .map(|i| average(&self.colors[i.saturating_sub(radius)..(i + radius + 1).min(len)]))
+            .collect();
+        Palette::new(&self.name, colors)
+    }
+
+    /// This palette blended `t` of the way, from 0 to 1, towards `other`. `other` is resampled to
+    /// this palette's length first if the two differ. Either being empty leaves nothing to blend,
+    /// and gives this palette back.
+    pub fn interpolate(&self, other: &Palette, t: f64) -> Palette {
+        if self.colors.is_empty() || other.colors.is_empty() {
+            return self.clone();
+        }
+        let other = if other.colors.len() == self.colors.len() {
+            other.clone()
+        } else {
+            other.resample(self.colors.len())
+        };
+        let colors = self
+            .colors
+            .iter()
+            .zip(&other.colors)
+            .map(|(&a, &b)| lerp(a, b, clamp_unit(t)))
+            .collect();
+        Palette::new(&self.name, colors)
+    }
+
+    /// Rounds each channel to the nearest of `levels` evenly spaced steps from 0 to 1, at least
+    /// two.
+    pub fn posterize(&self, levels: usize) -> Palette {
+        let steps = levels.max(2) as f32 - 1.0;
+        let round = |v: f32| (v.clamp(0.0, 1.0) * steps).round() / steps;
+        self.map(|c| ColorFRGB {
+            r: round(c.r),
+            g: round(c.g),
+            b: round(c.b),
+        })
+    }
+
+    fn map<F: Fn(ColorFRGB) -> ColorFRGB>(&self, f: F) -> Palette {
+        Palette::new(&self.name, self.colors.iter().map(|&c| f(c)).collect())
+    }
+
+    fn map_hsv<F: Fn(ColorFHSV) -> ColorFHSV>(&self, f: F) -> Palette {
+        self.map(|c| ColorFRGB::from(f(ColorFHSV::from(c))))
+    }
+}
+
+#[derive(Debug)]
+pub enum PaletteError {
+    Io(io::Error),
+    Xml(XmlError),
+    Syntax {
+        /// 1-based.
+        line: usize,
+        message: String,
+    },
+    /// The file's extension isn't one of the formats.
+    UnknownFormat(String),
+    /// The format holds one palette a file, and was given some other number.
+    NotOnePalette(usize),
+    /// An image couldn't be read, or had nothing to take colors from.
+    Image(String),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaletteError::Io(e) => e.fmt(f),
+            PaletteError::Xml(e) => e.fmt(f),
+            PaletteError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+            PaletteError::UnknownFormat(name) => {
+                write!(
+                    f,
+                    "{} isn't a .ugr, .gradient, .map, .xml or .ggr file",
+                    name
+                )
+            }
+            PaletteError::NotOnePalette(count) => {
+                write!(f, "the format holds one palette, not {}", count)
+            }
+            PaletteError::Image(message) => message.fmt(f),
+        }
+    }
+}
+
+impl Error for PaletteError {}
+
+impl From<io::Error> for PaletteError {
+    fn from(e: io::Error) -> PaletteError {
+        PaletteError::Io(e)
+    }
+}
+
+impl From<XmlError> for PaletteError {
+    fn from(e: XmlError) -> PaletteError {
+        PaletteError::Xml(e)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Format {
+    /// Apophysis and UltraFractal gradients, `.ugr` and `.gradient`.
+    Ugr,
+    /// Fractint color maps.
+    Map,
+    /// flam3's numbered palettes, as in `flam3-palettes.xml`.
+    Flam3,
+    /// GIMP gradients.
+    Ggr,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Result<Format, PaletteError> {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match &extension[..] {
+            "ugr" | "gradient" => Ok(Format::Ugr),
+            "map" => Ok(Format::Map),
+            "xml" => Ok(Format::Flam3),
+            "ggr" => Ok(Format::Ggr),
+            _ => Err(PaletteError::UnknownFormat(path.display().to_string())),
+        }
+    }
+}
+
+/// Reads every palette in a file, in the format its extension names. A `.map` palette is named
+/// after its file.
+pub fn load(path: &Path) -> Result<Vec<Palette>, PaletteError> {
+    let format = Format::from_path(path)?;
+    let text = fs::read_to_string(path)?;
+    match format {
+        Format::Ugr => read_ugr(&text),
+        Format::Map => {
+            let name = path
+                .file_stem()
+                .map_or(String::new(), |s| s.to_string_lossy().into_owned());
+            read_map(&text, &name).map(|palette| vec![palette])
+        }
+        Format::Flam3 => read_flam3(&text),
+        Format::Ggr => read_ggr(&text).map(|palette| vec![palette]),
+    }
+}
+
+/// Writes palettes in the format the path's extension names. `.map` and `.ggr` files hold exactly
+/// one.
+pub fn save(path: &Path, palettes: &[Palette]) -> Result<(), PaletteError> {
+    let text = match Format::from_path(path)? {
+        Format::Ugr => write_ugr(palettes),
+        Format::Map => write_map(only(palettes)?),
+        Format::Flam3 => write_flam3(palettes),
+        Format::Ggr => write_ggr(only(palettes)?),
+    };
+    fs::write(path, text)?;
+    Ok(())
+}
+
+fn only(palettes: &[Palette]) -> Result<&Palette, PaletteError> {
+    match palettes {
+        [palette] => Ok(palette),
+        _ => Err(PaletteError::NotOnePalette(palettes.len())),
+    }
+}
+
+/// Where UltraFractal gradients put their color stops, from 0 to 399.
+const UGR_POSITIONS: usize = 400;
+
+/// Reads the gradients in a `.ugr` or `.gradient` file. Each is a block such as
+///
+/// ```text
+/// sunset {
+/// gradient:
+///   title="sunset" smooth=no
+///   index=0 color=255
+///   index=200 color=16711680
+/// }
+/// ```
+///
+/// with stops at positions from 0 to 399, their colors packed as red + 256 green + 65536 blue.
+/// Gradients of exactly 256 stops, as Apophysis writes them, are read as they are; others are
+/// blended between their stops into 256 colors.
+pub fn read_ugr(text: &str) -> Result<Vec<Palette>, PaletteError> {
+    let mut palettes = Vec::new();
+    let mut rest = text;
+    let mut line = 1;
+    while let Some(open) = rest.find('{') {
+        let close = rest[open..].find('}').map(|i| open + i).ok_or_else(|| {
+            syntax(
+                line + newlines(&rest[..open]),
+                "the gradient is never closed",
+            )
+        })?;
+        let block_line = line + newlines(&rest[..open]);
+        let header = rest[..open].lines().last().unwrap_or("").trim();
+        let body = &rest[open + 1..close];
+        palettes.push(read_ugr_block(header, body, block_line)?);
+        line += newlines(&rest[..=close]);
+        rest = &rest[close + 1..];
+    }
+    if palettes.is_empty() {
+        return Err(syntax(1, "there are no gradients in the file"));
+    }
+    Ok(palettes)
+}
+
+fn read_ugr_block(header: &str, body: &str, line: usize) -> Result<Palette, PaletteError> {
+    let name = match body.find("title=\"") {
+        Some(start) => {
+            let title = &body[start + 7..];
+            String::from(&title[..title.find('"').unwrap_or(title.len())])
+        }
+        None => String::from(header),
+    };
+    let mut stops: Vec<(usize, ColorFRGB)> = Vec::new();
+    let mut index = None;
+    for (offset, text) in body.lines().enumerate() {
+        for token in text.split_whitespace() {
+            let bad = || syntax(line + offset, &format!("can't read {}", token));
+            if let Some(value) = token.strip_prefix("index=") {
+                index = Some(value.parse::<usize>().map_err(|_| bad())?);
+            } else if let Some(value) = token.strip_prefix("color=") {
+                let packed = value.parse::<u32>().map_err(|_| bad())?;
+                let position = index.take().ok_or_else(bad)?;
+                stops.push((position, unpack(packed)));
+            }
+        }
+    }
+    if stops.is_empty() {
+        return Err(syntax(line, &format!("gradient {} has no colors", name)));
+    }
+    stops.sort_by_key(|&(position, _)| position);
+    if stops.len() == PALETTE_SIZE {
+        return Ok(Palette::new(
+            &name,
+            stops.into_iter().map(|(_, c)| c).collect(),
+        ));
+    }
+    let last = (UGR_POSITIONS - 1) as f64;
+    let colors = (0..PALETTE_SIZE)
+        .map(|i| {
+            let position = i as f64 * last / (PALETTE_SIZE - 1) as f64;
+            let after = stops
+                .iter()
+                .position(|&(p, _)| p as f64 >= position)
+                .unwrap_or(stops.len() - 1);
+            let (p1, c1) = stops[after];
+            match after.checked_sub(1).map(|before| stops[before]) {
+                Some((p0, c0)) if (p1 as f64) > position => {
+                    lerp(c0, c1, (position - p0 as f64) / (p1 - p0) as f64)
+                }
+                _ => c1,
+            }
+        })
+        .collect();
+    Ok(Palette::new(&name, colors))
+}
+
+/// Writes gradients as a `.ugr` file, one block each.
+pub fn write_ugr(palettes: &[Palette]) -> String {
+    let mut out = String::new();
+    for palette in palettes {
+        let title: String = palette
+            .name
+            .chars()
+            .map(|c| match c {
+                '{' | '}' | '"' => '_',
+                c if c.is_whitespace() => '_',
+                c => c,
+            })
+            .collect();
+        out.push_str(&format!("{} {{\ngradient:\n", title));
+        out.push_str(&format!(" title=\"{}\" smooth=no\n", title));
+        let steps = palette.colors.len().saturating_sub(1).max(1);
+        for (i, color) in palette.colors.iter().enumerate() {
+            let position = (i * (UGR_POSITIONS - 1) + steps / 2) / steps;
+            out.push_str(&format!(" index={} color={}\n", position, pack(*color)));
+        }
+        out.push_str("}\n\n");
+    }
+    out
+}
+
+/// Reads a Fractint `.map` file: a line of red, green and blue from 0 to 255 for each color, each
+/// optionally followed by a comment.
+pub fn read_map(text: &str, name: &str) -> Result<Palette, PaletteError> {
+    let mut colors = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        let channels: Vec<u8> = line
+            .split_whitespace()
+            .take(3)
+            .map(|v| v.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| syntax(i + 1, "expected three channels from 0 to 255"))?;
+        if channels.len() < 3 {
+            return Err(syntax(i + 1, "expected three channels from 0 to 255"));
+        }
+        colors.push(from_bytes(channels[0], channels[1], channels[2]));
+    }
+    if colors.is_empty() {
+        return Err(syntax(1, "the map has no colors"));
+    }
+    Ok(Palette::new(name, colors))
+}
+
+pub fn write_map(palette: &Palette) -> String {
+    palette
+        .colors
+        .iter()
+        .map(|&c| {
+            let (r, g, b) = to_bytes(c);
+            format!("{} {} {}\n", r, g, b)
+        })
+        .collect()
+}
+
+/// Reads flam3's palettes file: a `<palette>` for each, with its `name` and a `data` attribute of
+/// eight hex digits a color, `00RRGGBB`. Palettes listing `<color index rgb>` elements instead
+/// are read too.
+pub fn read_flam3(text: &str) -> Result<Vec<Palette>, PaletteError> {
+    let root = xml::parse(text)?;
+    let elements: Vec<&Element> = if root.name == "palette" {
+        vec![&root]
+    } else {
+        root.children_named("palette").collect()
+    };
+    let mut palettes = Vec::new();
+    for element in elements {
+        let name = element.attr("name").unwrap_or("");
+        let colors = match element.attr("data") {
+            Some(data) => read_hex(data, name)?,
+            None => read_indexed(element, name)?,
+        };
+        palettes.push(Palette::new(name, colors));
+    }
+    if palettes.is_empty() {
+        return Err(syntax(1, "there are no palettes in the file"));
+    }
+    Ok(palettes)
+}
+
+fn read_hex(data: &str, name: &str) -> Result<Vec<ColorFRGB>, PaletteError> {
+    let digits: Vec<char> = data.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(8) {
+        return Err(syntax(
+            1,
+            &format!("palette {} doesn't hold whole colors", name),
+        ));
+    }
+    digits
+        .chunks(8)
+        .map(|hex| {
+            let hex: String = hex.iter().collect();
+            u32::from_str_radix(&hex, 16)
+                .map(|argb| unpack_rgb(argb & 0x00ff_ffff))
+                .map_err(|_| syntax(1, &format!("palette {} has a bad color {}", name, hex)))
+        })
+        .collect()
+}
+
+fn read_indexed(element: &Element, name: &str) -> Result<Vec<ColorFRGB>, PaletteError> {
+    let mut colors = vec![ColorFRGB::new(0.0, 0.0, 0.0).unwrap(); PALETTE_SIZE];
+    let mut count = 0;
+    for color in element.children_named("color") {
+        let index = color
+            .attr("index")
+            .and_then(|i| i.trim().parse::<usize>().ok());
+        let rgb: Option<Vec<u8>> = color
+            .attr("rgb")
+            .and_then(|rgb| rgb.split_whitespace().map(|v| v.parse().ok()).collect());
+        match (index, rgb) {
+            (Some(index), Some(ref rgb)) if index < PALETTE_SIZE && rgb.len() == 3 => {
+                colors[index] = from_bytes(rgb[0], rgb[1], rgb[2]);
+                count = count.max(index + 1);
+            }
+            _ => return Err(syntax(1, &format!("palette {} has a bad color", name))),
+        }
+    }
+    if count == 0 {
+        return Err(syntax(1, &format!("palette {} has no colors", name)));
+    }
+    Ok(colors)
+}
+
+/// Writes palettes as a flam3 palettes file, numbered in order.
+pub fn write_flam3(palettes: &[Palette]) -> String {
+    let mut root = Element::new("palettes");
+    for (number, palette) in palettes.iter().enumerate() {
+        let mut element = Element::new("palette");
+        element.set_attr("number", number);
+        element.set_attr("name", &palette.name);
+        let data: String = palette
+            .colors
+            .iter()
+            .map(|&c| format!("00{:06X}", pack_rgb(c)))
+            .collect();
+        element.set_attr("data", data);
+        root.children.push(element);
+    }
+    root.write()
+}
+
+/// Reads a GIMP gradient, sampled into 256 colors. Its segments' blending and HSV coloring are
+/// followed; alpha is dropped.
+pub fn read_ggr(text: &str) -> Result<Palette, PaletteError> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty());
+    match lines.next() {
+        Some((_, header)) if header.trim() == "GIMP Gradient" => {}
+        _ => return Err(syntax(1, "expected a GIMP Gradient header")),
+    }
+    let mut name = String::new();
+    let (count_line, count) = match lines.next() {
+        Some((i, line)) if line.starts_with("Name:") => {
+            name = String::from(line[5..].trim());
+            lines.next().map_or((i + 1, ""), |(i, l)| (i, l))
+        }
+        Some((i, line)) => (i, line),
+        None => (1, ""),
+    };
+    let count: usize = count
+        .trim()
+        .parse()
+        .map_err(|_| syntax(count_line + 1, "expected the number of segments"))?;
+    let mut segments = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (i, line) = lines
+            .next()
+            .ok_or_else(|| syntax(count_line + 1, "there are fewer segments than promised"))?;
+        let values: Vec<f64> = line
+            .split_whitespace()
+            .map(|v| v.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| syntax(i + 1, "can't read the segment"))?;
+        if values.len() < 13 {
+            return Err(syntax(i + 1, "a segment needs 13 numbers"));
+        }
+        segments.push(Segment {
+            left: values[0],
+            middle: values[1],
+            right: values[2],
+            left_color: unit_color(values[3], values[4], values[5]),
+            right_color: unit_color(values[7], values[8], values[9]),
+            blending: values[11] as u32,
+            coloring: values[12] as u32,
+        });
+    }
+    if segments.is_empty() {
+        return Err(syntax(count_line + 1, "the gradient has no segments"));
+    }
+    let colors = (0..PALETTE_SIZE)
+        .map(|i| {
+            let position = i as f64 / (PALETTE_SIZE - 1) as f64;
+            let segment = segments
+                .iter()
+                .find(|s| position <= s.right)
+                .unwrap_or(&segments[segments.len() - 1]);
+            segment.color_at(position)
+        })
+        .collect();
+    Ok(Palette::new(&name, colors))
+}
+
+/// Writes a palette as a GIMP gradient, a linear RGB segment between each pair of neighboring
+/// colors.
+pub fn write_ggr(palette: &Palette) -> String {
+    let mut out = format!("GIMP Gradient\nName: {}\n", palette.name.replace('\n', " "));
+    let colors = &palette.colors;
+    if colors.len() < 2 {
+        let c = colors
+            .first()
+            .cloned()
+            .unwrap_or_else(|| ColorFRGB::new(0.0, 0.0, 0.0).unwrap());
+        out.push_str("1\n");
+        out.push_str(&segment_line(0.0, 1.0, c, c));
+        return out;
+    }
+    let steps = (colors.len() - 1) as f64;
+    out.push_str(&format!("{}\n", colors.len() - 1));
+    for (i, pair) in colors.windows(2).enumerate() {
+        out.push_str(&segment_line(
+            i as f64 / steps,
+            (i + 1) as f64 / steps,
+            pair[0],
+            pair[1],
+        ));
+    }
+    out
+}
+
+fn segment_line(left: f64, right: f64, left_color: ColorFRGB, right_color: ColorFRGB) -> String {
+    format!(
+        "{:.6} {:.6} {:.6} {:.6} {:.6} {:.6} 1.000000 {:.6} {:.6} {:.6} 1.000000 0 0\n",
+        left,
+        (left + right) / 2.0,
+        right,
+        left_color.r,
+        left_color.g,
+        left_color.b,
+        right_color.r,
+        right_color.g,
+        right_color.b
+    )
+}
+
+struct Segment {
+    left: f64,
+    middle: f64,
+    right: f64,
+    left_color: ColorFRGB,
+    right_color: ColorFRGB,
+    /// Linear, curved, sine, sphere increasing, sphere decreasing or step, 0 to 5.
+    blending: u32,
+    /// RGB, HSV counterclockwise or HSV clockwise, 0 to 2.
+    coloring: u32,
+}
+
+impl Segment {
+    /// Follows GIMP's gradient sampling.
+    fn color_at(&self, position: f64) -> ColorFRGB {
+        const EPSILON: f64 = 1e-10;
+        let length = self.right - self.left;
+        let (middle, position) = if length < EPSILON {
+            (0.5, 0.5)
+        } else {
+            (
+                (self.middle - self.left) / length,
+                (position - self.left) / length,
+            )
+        };
+        let linear = if position <= middle {
+            if middle < EPSILON {
+                0.0
+            } else {
+                0.5 * position / middle
+            }
+        } else if 1.0 - middle < EPSILON {
+            1.0
+        } else {
+            0.5 + 0.5 * (position - middle) / (1.0 - middle)
+        };
+        let factor = match self.blending {
+            1 => position
+                .max(0.0)
+                .powf(0.5f64.ln() / middle.max(EPSILON).ln()),
+            2 => ((-PI / 2.0 + PI * linear).sin() + 1.0) / 2.0,
+            3 => (1.0 - (linear - 1.0) * (linear - 1.0)).max(0.0).sqrt(),
+            4 => 1.0 - (1.0 - linear * linear).max(0.0).sqrt(),
+            5 => {
+                if position >= middle {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            _ => linear,
+        };
+        let factor = clamp_unit(factor);
+        if self.coloring == 0 {
+            return lerp(self.left_color, self.right_color, factor);
+        }
+        let left = ColorFHSV::from(self.left_color);
+        let right = ColorFHSV::from(self.right_color);
+        let (h0, h1) = (left.h as f64 / 360.0, right.h as f64 / 360.0);
+        //Counterclockwise hues increase, clockwise ones decrease, wrapping around either way.
+        let hue = if self.coloring == 1 {
+            let span = if h1 >= h0 { h1 - h0 } else { 1.0 - (h0 - h1) };
+            h0 + span * factor
+        } else {
+            let span = if h0 >= h1 { h0 - h1 } else { 1.0 - (h1 - h0) };
+            h0 - span * factor
+        };
+        let mix = |a: f32, b: f32| (a as f64 + (b as f64 - a as f64) * factor) as f32;
+        ColorFRGB::from(ColorFHSV::wrapped(
+            (hue * 360.0) as f32,
+            mix(left.s, right.s),
+            mix(left.v, right.v),
+        ))
+    }
+}
+
+fn syntax(line: usize, message: &str) -> PaletteError {
+    PaletteError::Syntax {
+        line,
+        message: String::from(message),
+    }
+}
+
+fn newlines(text: &str) -> usize {
+    text.matches('\n').count()
+}
+
+fn from_bytes(r: u8, g: u8, b: u8) -> ColorFRGB {
+    ColorFRGB {
+        r: r as f32 / 255.0,
+        g: g as f32 / 255.0,
+        b: b as f32 / 255.0,
+    }
+}
+
+fn to_bytes(c: ColorFRGB) -> (u8, u8, u8) {
+    let byte = |v: f32| (clamp_unit(v as f64) * 255.0).round() as u8;
+    (byte(c.r), byte(c.g), byte(c.b))
+}
+
+/// UltraFractal's packing: red in the low byte.
+fn pack(c: ColorFRGB) -> u32 {
+    let (r, g, b) = to_bytes(c);
+    r as u32 | (g as u32) << 8 | (b as u32) << 16
+}
+
+fn unpack(packed: u32) -> ColorFRGB {
+    from_bytes(packed as u8, (packed >> 8) as u8, (packed >> 16) as u8)
+}
+
+/// Hex order: red in the high byte.
+fn pack_rgb(c: ColorFRGB) -> u32 {
+    let (r, g, b) = to_bytes(c);
+    (r as u32) << 16 | (g as u32) << 8 | b as u32
+}
+
+fn unpack_rgb(rgb: u32) -> ColorFRGB {
+    from_bytes((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+}
@@ -1,13 +1,44 @@
 extern crate lodepng;
+extern crate serde_json;
+use self::lodepng::RGB;
+use self::lodepng::RGBA;
+use batch;
 use color::*;
+use extract;
+use extract::Image;
+use extract::SortKey;
+use extract::Strategy;
 use flame_2d;
 use flame_2d::AffineTransform as Affine2d;
+use flame_2d::Point;
 use flame_2d::Transform as Transform2d;
+use genome;
+use history::Edit;
+use history::EditError;
+use history::EditHistory;
+use json;
+use output;
+use output::write_pfm;
+use output::PngBandWriter;
+use palette;
+use palette::Palette;
+use rand::Rng;
+use rand::SeedableRng;
+use rand::XorShiftRng;
+use std::convert::TryFrom;
+use std::fs;
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
+use std::slice;
 use std::time;
 use std::time::Instant;
+use variations;
+use variations::VariationError;
+use xml;
+use Config;
+use OutputFormat;
+use Selection;
 
 #[test]
 fn fRGB_to_fHSV_and_back() {
@@ -103,3 +134,1422 @@ fn basic_flame_render_test() {
     file.write_all(&enc);
     println!("File saved. {} seconds elapsed.", start.elapsed().as_secs())
 }
+
+fn three_affine_flame() -> flame_2d::Flame {
+    let mut flame = flame_2d::Flame::new(String::from("Testflame"));
+    flame.add_transform(
+        Transform2d::AffineOnly(Affine2d {
+            xx: 0.95,
+            xy: 0.1,
+            yx: 0.1,
+            yy: 0.975,
+            cx: 0.05,
+            cy: 0.1,
+        }),
+        ColorFRGB::new(1.0, 1.0, 1.0).unwrap(),
+    );
+    flame.add_transform(
+        Transform2d::AffineOnly(Affine2d {
+            xx: 0.1,
+            xy: 0.8,
+            yx: 0.7,
+            yy: 0.5,
+            cx: 0.0,
+            cy: 0.01,
+        }),
+        ColorFRGB::new(1.0, 0.0, 0.0).unwrap(),
+    );
+    flame.add_transform(
+        Transform2d::AffineOnly(Affine2d {
+            xx: 0.9,
+            xy: 0.05,
+            yx: 0.05,
+            yy: 0.9,
+            cx: 0.0,
+            cy: 0.0,
+        }),
+        ColorFRGB::new(0.0, 1.0, 1.0).unwrap(),
+    );
+    flame
+}
+
+#[test]
+fn tiled_render_matches_whole_render() {
+    let mut renderer = flame_2d::Renderer::new(64, 48, 2.2, 0.75, three_affine_flame());
+    renderer.set_termination(flame_2d::Termination::Iterations(200_000));
+    renderer.set_seed([1, 2, 3, 4]);
+    let whole = renderer.render_unthreaded();
+
+    let mut tiled = Vec::new();
+    let mut next_row = 0;
+    renderer
+        .render_tiled(5, |first_row, band: &[RGB<u8>]| {
+            assert_eq!(first_row, next_row);
+            assert!(band.len() <= 5 * 64);
+            next_row += band.len() as u32 / 64;
+            tiled.extend_from_slice(band);
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(next_row, 48);
+    assert!(whole.buffer == tiled);
+
+    //The command line streams the bands straight into the PNG, genome and all.
+    let dir = ::std::env::temp_dir().join(format!("rustflame-tiled-{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("one.flame");
+    fs::write(&input, include_str!("../OneFlame.flame")).unwrap();
+    let line = format!(
+        "rustflame {} -o {}/{{file}}.png -W 40 -H 30 -q 1 -s 9 --tile-rows 7",
+        input.display(),
+        dir.display()
+    );
+    ::run(Config::new(&args(&line)).unwrap()).unwrap();
+    let png = fs::read(dir.join("one.png")).unwrap();
+    assert!(output::is_png(&png));
+    let settings = &genome::from_png(&png).unwrap()[0].settings;
+    assert_eq!(
+        (settings.width, settings.height, settings.seed),
+        (40, 30, Some(9))
+    );
+    //Only the genome and its image, with no temporary file left over.
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn png_band_writer_rejects_short_images() {
+    let mut writer = PngBandWriter::<_, RGB<u8>>::new(Vec::new(), 4, 2).unwrap();
+    writer.write_rows(&[RGB { r: 0, g: 0, b: 0 }; 4]).unwrap();
+    assert!(writer.write_rows(&[RGB { r: 0, g: 0, b: 0 }; 3]).is_err());
+    //Text between bands would split the image data.
+    assert!(writer.write_text("Comment", "between").is_err());
+    assert!(writer.finish().is_err());
+    assert!(PngBandWriter::<_, RGB<u8>>::new(Vec::new(), 0, 2).is_err());
+    assert!(PngBandWriter::<_, RGB<u8>>::new(Vec::new(), 4, 0).is_err());
+
+    let mut writer = PngBandWriter::<_, RGBA<u16>>::new(Vec::new(), 4, 2).unwrap();
+    let pixel = RGBA {
+        r: 0u16,
+        g: 1000,
+        b: 65535,
+        a: 40000,
+    };
+    writer.write_rows(&[pixel; 8]).unwrap();
+    writer.write_text("Comment", "after").unwrap();
+    let png = writer.finish().unwrap();
+    assert_eq!(&png[1..4], b"PNG");
+    let kinds: Vec<&[u8]> = png
+        .windows(4)
+        .filter(|w| w == b"IDAT" || w == b"tEXt")
+        .collect();
+    assert_eq!(kinds.last(), Some(&&b"tEXt"[..]));
+    //IHDR data starts after the signature, chunk length and chunk type.
+    assert_eq!(png[16 + 8], 16);
+    assert_eq!(png[16 + 9], 6);
+    assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+}
+
+#[test]
+fn outputs_replace_files_whole() {
+    let dir = ::std::env::temp_dir().join(format!("rustflame-atomic-{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("out.png");
+    output::write_atomically(&path, |w| w.write_all(b"first")).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"first");
+
+    //A write that fails part way leaves the old file as it was, and nothing else behind.
+    let failed = output::write_atomically(&path, |w| {
+        w.write_all(b"half")?;
+        Err(::std::io::Error::new(
+            ::std::io::ErrorKind::Other,
+            "on purpose",
+        ))
+    });
+    assert!(failed.is_err());
+    assert_eq!(fs::read(&path).unwrap(), b"first");
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    output::write_atomically(&path, |w| w.write_all(b"second")).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"second");
+    assert!(output::write_atomically(&dir.join(".."), |_| Ok(())).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn sixteen_bit_output_refines_eight_bit() {
+    let mut renderer = flame_2d::Renderer::new(64, 48, 2.2, 0.75, three_affine_flame());
+    renderer.set_termination(flame_2d::Termination::Iterations(50_000));
+    renderer.set_seed([13, 14, 15, 16]);
+    let eight = renderer.render_unthreaded();
+    let sixteen = renderer.render_unthreaded_as::<RGB<u16>>();
+    for (e, s) in eight.buffer.iter().zip(sixteen.buffer.iter()) {
+        assert_eq!(e.r, (s.r / 257) as u8);
+        assert_eq!(e.g, (s.g / 257) as u8);
+        assert_eq!(e.b, (s.b / 257) as u8);
+    }
+}
+
+#[test]
+fn single_precision_bins_match_double() {
+    let mut renderer = flame_2d::Renderer::new(64, 48, 2.2, 0.75, three_affine_flame());
+    renderer.set_termination(flame_2d::Termination::Iterations(200_000));
+    renderer.set_seed([5, 6, 7, 8]);
+    let double = renderer.render_unthreaded();
+    renderer.set_precision(flame_2d::BinPrecision::Single);
+    let single = renderer.render_unthreaded();
+
+    for (d, s) in double.buffer.iter().zip(single.buffer.iter()) {
+        assert!((d.r as i32 - s.r as i32).abs() <= 1);
+        assert!((d.g as i32 - s.g as i32).abs() <= 1);
+        assert!((d.b as i32 - s.b as i32).abs() <= 1);
+    }
+}
+
+#[test]
+fn background_and_transparency() {
+    let mut flame = three_affine_flame();
+    flame.background = ColorFRGB::new(0.0, 0.0, 1.0).unwrap();
+    let mut renderer = flame_2d::Renderer::new(64, 48, 2.2, 0.75, flame);
+    renderer.set_termination(flame_2d::Termination::Iterations(50_000));
+    renderer.set_seed([9, 10, 11, 12]);
+    let opaque = renderer.render_unthreaded();
+    let transparent = renderer.render_unthreaded_as::<RGBA<u8>>();
+
+    let mut saw_empty = false;
+    for (o, t) in opaque.buffer.iter().zip(transparent.buffer.iter()) {
+        if t.a == 0 {
+            saw_empty = true;
+            assert_eq!(*o, RGB { r: 0, g: 0, b: 255 });
+        }
+        if t.a == 255 {
+            assert_eq!(
+                *o,
+                RGB {
+                    r: t.r,
+                    g: t.g,
+                    b: t.b
+                }
+            );
+        }
+    }
+    assert!(saw_empty);
+}
+
+#[test]
+fn hdr_output_is_unclipped() {
+    let mut renderer = flame_2d::Renderer::new(64, 48, 2.2, 0.75, three_affine_flame());
+    renderer.set_termination(flame_2d::Termination::Iterations(50_000));
+    renderer.set_seed([17, 18, 19, 20]);
+    let hdr = renderer.render_unthreaded_as::<RGB<f32>>();
+    assert!(hdr.buffer.iter().any(|p| p.r > 1.0));
+
+    let density = renderer.render_density();
+    let max_alpha = density.buffer.iter().map(|p| p.a).fold(0.0, f32::max);
+    assert_eq!(max_alpha, 1.0);
+    assert!(density
+        .buffer
+        .iter()
+        .all(|p| p.r <= p.a && p.g <= p.a && p.b <= p.a));
+
+    let mut pfm = Vec::new();
+    write_pfm(&mut pfm, &hdr).unwrap();
+    assert!(pfm.starts_with(b"PF\n64 48\n-1.0\n"));
+    assert_eq!(pfm.len(), "PF\n64 48\n-1.0\n".len() + 64 * 48 * 12);
+}
+
+struct CountingMonitor {
+    progress: Vec<f64>,
+    frames: usize,
+    cancel_after: Option<usize>,
+}
+
+impl flame_2d::RenderMonitor<RGB<u8>> for CountingMonitor {
+    fn progress(&mut self, done: f64) -> bool {
+        self.progress.push(done);
+        self.cancel_after.map_or(true, |n| self.progress.len() < n)
+    }
+    fn preview_frames(&self) -> u32 {
+        3
+    }
+    fn preview(&mut self, frame: &lodepng::Bitmap<RGB<u8>>) {
+        assert_eq!(frame.buffer.len(), 64 * 48);
+        self.frames += 1;
+    }
+}
+
+#[test]
+fn monitored_render_reports_progress_and_cancels() {
+    let mut renderer = flame_2d::Renderer::new(64, 48, 2.2, 0.75, three_affine_flame());
+    renderer.set_termination(flame_2d::Termination::Iterations(400_000));
+    renderer.set_seed([5, 6, 7, 8]);
+    let mut monitor = CountingMonitor {
+        progress: Vec::new(),
+        frames: 0,
+        cancel_after: None,
+    };
+    let monitored = renderer.render_monitored(&mut monitor).unwrap();
+    assert!(monitored.buffer == renderer.render_unthreaded().buffer);
+    assert_eq!(monitor.frames, 3);
+    assert_eq!(*monitor.progress.last().unwrap(), 1.0);
+    assert!(monitor.progress.windows(2).all(|w| w[0] <= w[1]));
+
+    let mut cancelling = CountingMonitor {
+        progress: Vec::new(),
+        frames: 0,
+        cancel_after: Some(2),
+    };
+    assert!(renderer.render_monitored(&mut cancelling).is_none());
+    assert_eq!(cancelling.progress.len(), 2);
+}
+
+#[test]
+fn variation_registry_and_editing() {
+    let names = variations::names();
+    assert!(names.contains(&"linear") && names.contains(&"curl"));
+    for name in &names {
+        let function = variations::create(name).unwrap();
+        assert_eq!(function.name(), *name);
+        let p = function.transform(&Point { x: 0.3, y: -0.2 });
+        assert!(p.x.is_finite() && p.y.is_finite(), "{} is not finite", name);
+    }
+    assert_eq!(
+        variations::param_names("curl"),
+        Some(vec!["curl_c1", "curl_c2"])
+    );
+    assert!(variations::create("nope").is_err());
+
+    let affine = Affine2d {
+        xx: 0.5,
+        xy: 0.1,
+        yx: -0.2,
+        yy: 0.5,
+        cx: 0.1,
+        cy: 0.0,
+    };
+    let mut transform = Transform2d::AffineOnly(affine);
+    transform.set_variation_weight("curl", 0.5).unwrap();
+    transform
+        .set_variation_param("curl", "curl_c2", 0.25)
+        .unwrap();
+    assert_eq!(
+        transform.set_variation_param("curl", "curl_c3", 0.25),
+        Err(VariationError::UnknownParam {
+            variation: String::from("curl"),
+            param: String::from("curl_c3"),
+        })
+    );
+    assert_eq!(
+        transform.set_variation_param("swirl", "anything", 0.25),
+        Err(VariationError::NotPresent(String::from("swirl")))
+    );
+    {
+        let used = transform.variations();
+        assert_eq!(used.len(), 2);
+        assert_eq!((used[0].0, used[0].1.name()), (1.0, "linear"));
+        assert_eq!(used[1].1.param("curl_c2"), Some(0.25));
+    }
+    transform.set_variation_weight("linear", 0.0).unwrap();
+    assert_eq!(transform.variations().len(), 1);
+    assert_eq!(transform.affine(), Some(&affine));
+}
+
+#[test]
+fn zero_weight_transforms_are_never_picked() {
+    let mut renderer = flame_2d::Renderer::new(32, 32, 2.2, 0.75, three_affine_flame());
+    renderer.set_termination(flame_2d::Termination::Iterations(50_000));
+    renderer.set_seed([9, 9, 9, 9]);
+    let full = renderer.render_unthreaded();
+
+    let mut weighted = three_affine_flame();
+    weighted.weights[2] = 0.0;
+    let mut trimmed = three_affine_flame();
+    assert!(trimmed.remove_transform(2));
+    assert!(!trimmed.remove_transform(2));
+
+    let mut renderer = flame_2d::Renderer::new(32, 32, 2.2, 0.75, weighted);
+    renderer.set_termination(flame_2d::Termination::Iterations(50_000));
+    renderer.set_seed([9, 9, 9, 9]);
+    let weighted = renderer.render_unthreaded();
+    let mut renderer = flame_2d::Renderer::new(32, 32, 2.2, 0.75, trimmed);
+    renderer.set_termination(flame_2d::Termination::Iterations(50_000));
+    renderer.set_seed([9, 9, 9, 9]);
+    assert!(weighted.buffer == renderer.render_unthreaded().buffer);
+    assert!(weighted.buffer != full.buffer);
+}
+
+#[test]
+fn edit_history_undo_redo_and_coalescing() {
+    let original = three_affine_flame();
+    let mut history = EditHistory::new(original.clone());
+    assert!(!history.can_undo());
+
+    history.apply(Edit::RemoveTransform { index: 1 }).unwrap();
+    history
+        .apply(Edit::SetVariationWeight {
+            index: 0,
+            variation: String::from("swirl"),
+            weight: 0.5,
+        })
+        .unwrap();
+    assert_eq!(
+        history.apply(Edit::SetWeight {
+            index: 5,
+            weight: 1.0
+        }),
+        Err(EditError::NoTransform { index: 5, count: 2 })
+    );
+    //Transforms missing a color or weight can't be edited, rather than panicking.
+    let mut short = three_affine_flame();
+    short.colors.pop();
+    short.weights.truncate(1);
+    for edit in vec![
+        Edit::RemoveTransform { index: 2 },
+        Edit::SetColor {
+            index: 1,
+            color: ColorFRGB::new(0.0, 0.0, 0.0).unwrap(),
+        },
+        Edit::SetWeight {
+            index: 1,
+            weight: 1.0,
+        },
+    ] {
+        assert!(edit.apply(&mut short).is_err());
+    }
+    let insert = Edit::InsertTransform {
+        index: 2,
+        transform: short.transforms[0].clone(),
+        color: ColorFRGB::new(0.0, 0.0, 0.0).unwrap(),
+        weight: 1.0,
+    };
+    assert_eq!(
+        insert.apply(&mut short).err(),
+        Some(EditError::NoTransform { index: 2, count: 1 })
+    );
+
+    //A slider drag: one undo step, however many edits.
+    history.set_coalesce_window(time::Duration::from_secs(60));
+    for i in 1..10 {
+        history
+            .apply(Edit::SetWeight {
+                index: 1,
+                weight: i as f64,
+            })
+            .unwrap();
+    }
+    history.seal();
+    history
+        .apply(Edit::SetWeight {
+            index: 1,
+            weight: 20.0,
+        })
+        .unwrap();
+    assert_eq!(history.flame().weights, vec![1.0, 20.0]);
+
+    assert!(history.undo());
+    assert_eq!(history.flame().weights, vec![1.0, 9.0]);
+    assert!(history.undo());
+    assert_eq!(history.flame().weights, vec![1.0, 1.0]);
+    assert!(history.undo());
+    assert_eq!(history.flame().transforms[0].variations().len(), 0);
+    assert!(history.undo());
+    assert_eq!(history.flame().transforms.len(), 3);
+    assert_eq!(history.flame().colors, original.colors);
+    assert!(!history.undo());
+
+    assert!(history.redo());
+    assert!(history.redo());
+    assert_eq!(history.flame().transforms[0].variation_weight("swirl"), 0.5);
+    assert!(history.redo());
+    assert_eq!(history.flame().weights, vec![1.0, 9.0]);
+    history
+        .apply(Edit::SetBackground(ColorFRGB::new(0.5, 0.5, 0.5).unwrap()))
+        .unwrap();
+    assert!(!history.can_redo());
+}
+
+fn assert_near(a: Point, b: Point) {
+    assert!(
+        (a.x - b.x).abs() < 1e-9 && (a.y - b.y).abs() < 1e-9,
+        "{:?} != {:?}",
+        a,
+        b
+    );
+}
+
+#[test]
+fn triangle_handles() {
+    let affine = Affine2d {
+        xx: 0.5,
+        xy: 0.25,
+        yx: -0.1,
+        yy: 0.8,
+        cx: 0.3,
+        cy: -0.2,
+    };
+    let triangle = affine.triangle();
+    assert_near(triangle.o, Point { x: 0.3, y: -0.2 });
+    assert_near(triangle.x, Point { x: 0.8, y: 0.05 });
+    assert_eq!(Affine2d::from_triangle(&triangle), affine);
+
+    //A quarter turn around the centroid keeps the centroid and turns each corner with it.
+    let pivot = triangle.centroid();
+    let mut turned = affine;
+    turned.rotate(::std::f64::consts::FRAC_PI_2, pivot);
+    let after = turned.triangle();
+    assert_near(after.centroid(), pivot);
+    assert_near(
+        after.o,
+        Point {
+            x: pivot.x - (triangle.o.y - pivot.y),
+            y: pivot.y + (triangle.o.x - pivot.x),
+        },
+    );
+
+    let mut scaled = affine;
+    scaled.scale(2.0, 3.0, triangle.o);
+    assert_near(scaled.triangle().o, triangle.o);
+    assert!((scaled.xx - 1.0).abs() < 1e-9 && (scaled.yy - 2.4).abs() < 1e-9);
+
+    let mut flipped = affine;
+    flipped.flip_horizontal(pivot);
+    flipped.flip_vertical(pivot);
+    flipped.rotate(::std::f64::consts::PI, pivot);
+    assert_near(flipped.triangle().x, triangle.x);
+
+    //Shearing about a corner leaves that corner alone.
+    let mut sheared = affine;
+    sheared.shear(0.5, 0.0, triangle.y);
+    assert_near(sheared.triangle().y, triangle.y);
+    assert_near(
+        sheared.triangle().o,
+        Point {
+            x: triangle.o.x + 0.5 * (triangle.o.y - triangle.y.y),
+            y: triangle.o.y,
+        },
+    );
+
+    let mut dragged = affine;
+    dragged.move_vertex(flame_2d::Vertex::O, Point { x: 0.0, y: 0.0 });
+    let after = dragged.triangle();
+    assert_near(after.o, Point { x: 0.0, y: 0.0 });
+    assert_near(after.x, triangle.x);
+    assert_near(after.y, triangle.y);
+}
+
+#[test]
+fn thumbnails_stop_at_the_first_limit() {
+    let mut capped = flame_2d::Renderer::new(32, 32, 2.2, 0.75, three_affine_flame());
+    capped.set_termination(flame_2d::Termination::FirstOf(
+        time::Duration::from_secs(60),
+        50_000,
+    ));
+    capped.set_seed([5, 6, 7, 8]);
+    let mut counted = capped.clone();
+    counted.set_termination(flame_2d::Termination::Iterations(50_000));
+    assert!(capped.render_unthreaded().buffer == counted.render_unthreaded().buffer);
+
+    let mut thumbnail = flame_2d::Renderer::thumbnail(64, 48, three_affine_flame());
+    let start = Instant::now();
+    let image = thumbnail.render_unthreaded();
+    //The budget is tens of milliseconds; leave plenty of room for slow test machines.
+    assert!(start.elapsed() < time::Duration::from_secs(2));
+    assert_eq!((image.width, image.height), (64, 48));
+    assert!(image.buffer.iter().any(|p| p.r > 0 || p.g > 0 || p.b > 0));
+}
+
+#[test]
+fn flam3_genomes_load() {
+    let genomes = genome::parse(include_str!("../OneFlame.flame")).unwrap();
+    assert_eq!(genomes.len(), 1);
+    let ruby = &genomes[0];
+    assert_eq!(ruby.flame.name(), "Ruby Flame");
+    assert_eq!((ruby.settings.width, ruby.settings.height), (1920, 1440));
+    assert_eq!(ruby.settings.gamma, 1.1);
+    assert_eq!(ruby.settings.quality, 50.0);
+    assert_eq!(ruby.flame.transforms.len(), 3);
+    assert_eq!(ruby.flame.weights[1], 0.560450246919392);
+    let first = &ruby.flame.transforms[0];
+    assert_eq!(first.variation_weight("spherical"), 0.622734208125621);
+    assert_eq!(first.variation_weight("cylinder"), 0.377265791874379);
+    assert_eq!(first.affine().unwrap().yx, -1.153183);
+    //Color 0 is the first palette entry, D9501A.
+    assert_eq!(
+        ruby.flame.colors[0],
+        ColorFRGB::new(217.0 / 255.0, 80.0 / 255.0, 26.0 / 255.0).unwrap()
+    );
+
+    let collection = genome::parse(include_str!("../Flames.flame")).unwrap();
+    let names: Vec<&str> = collection.iter().map(|g| g.flame.name()).collect();
+    assert_eq!(names[..3], ["Ruby Flame", "Plasmadisk", "Chromapheonix"]);
+    assert_eq!(names.len(), 6);
+
+    let colors = genome::parse(
+        "<flame name=\"x\" background=\"0 0 1\"><xform coefs=\"1 0 0 1 0 0\" color=\"1\" \
+         linear=\"1\"/><color index=\"255\" rgb=\"0 255 0\"/></flame>",
+    )
+    .unwrap();
+    let flame = &colors[0].flame;
+    assert_eq!(flame.colors[0], ColorFRGB::new(0.0, 1.0, 0.0).unwrap());
+    assert_eq!(flame.background, ColorFRGB::new(0.0, 0.0, 1.0).unwrap());
+    assert!(flame.transforms[0].variations().is_empty());
+
+    //Only a camera framing -1 to 1 on both axes renders as its file asks.
+    let camera = |attributes: &str| {
+        let xml = format!(
+            "<flame {}><xform coefs=\"1 0 0 1 0 0\" linear=\"1\"/></flame>",
+            attributes
+        );
+        let import = genome::import(&xml).unwrap();
+        import
+            .warnings
+            .into_iter()
+            .map(|w| match w.kind {
+                genome::WarningKind::Unsupported { attribute, .. } => attribute,
+                kind => panic!("{:?}", kind),
+            })
+            .collect::<Vec<String>>()
+    };
+    let framed = "size=\"200 200\" center=\"0 0\" scale=\"100\" rotate=\"0\" zoom=\"0\"";
+    assert!(camera(framed).is_empty());
+    assert_eq!(
+        camera("size=\"200 200\" center=\"0.5 0\" scale=\"120\" rotate=\"30\" zoom=\"1\""),
+        ["center", "scale", "rotate", "zoom"]
+    );
+    //A wide image squashes the square, which no scale undoes.
+    assert_eq!(camera("size=\"200 100\" scale=\"100\""), ["scale"]);
+
+    match genome::parse("<flame><xform coefs=\"1 0 0\"/></flame>") {
+        Err(genome::GenomeError::BadAttribute { attribute, .. }) => assert_eq!(attribute, "coefs"),
+        _ => panic!("short coefs should be rejected"),
+    }
+    match genome::parse("<flames>\n<flame>\n</flames>") {
+        Err(genome::GenomeError::Xml(e)) => assert_eq!(e.line, 3),
+        _ => panic!("mismatched tags should be rejected"),
+    }
+    assert!(
+        xml::parse("<a b='1 &amp; 2'>x &lt; y</a>").unwrap() == {
+            let mut a = xml::Element::new("a");
+            a.attributes
+                .push((String::from("b"), String::from("1 & 2")));
+            a.text = String::from("x < y");
+            a
+        }
+    );
+}
+
+fn args(line: &str) -> Vec<String> {
+    line.split_whitespace().map(String::from).collect()
+}
+
+#[test]
+fn command_line_options() {
+    let config = Config::new(&args(
+        "rustflame a.flame b.flame -o out/{file}-{name}.pfm --width=640 -H 480 -q 200 -j 4 \
+         --seed 7 --name Spark --background 0,0.5,1 --precision single",
+    ))
+    .unwrap();
+    assert_eq!(config.inputs, args("a.flame b.flame"));
+    assert_eq!(config.selection, Selection::Name(String::from("Spark")));
+    assert_eq!(config.threads, 4);
+    assert_eq!(config.seed, Some(7));
+    assert_eq!(config.precision, Some(flame_2d::BinPrecision::Single));
+    assert_eq!(config.tile_rows, None);
+    let path = config.output_path("dir/b.flame", 2, "Spark: 2");
+    assert_eq!(path, "out/b-Spark_ 2.pfm");
+    assert_eq!(OutputFormat::from_path(&path), OutputFormat::Pfm);
+
+    let settings = config.settings(&flame_2d::RenderSettings::default());
+    assert_eq!((settings.width, settings.height), (640, 480));
+    assert_eq!(settings.quality, 200.0);
+    assert_eq!(settings.gamma, 2.2);
+
+    assert!(Config::new(&args("rustflame")).is_err());
+    assert!(Config::new(&args("rustflame --help")).unwrap().help);
+    assert!(Config::new(&args("rustflame a.flame -W 0 -h")).is_err());
+    //Only as an option: here it's the name of the flame to render.
+    let named = Config::new(&args("rustflame a.flame --name -h")).unwrap();
+    assert!(!named.help);
+    assert_eq!(named.selection, Selection::Name(String::from("-h")));
+    assert!(Config::new(&args("rustflame a.flame --width 0")).is_err());
+    assert!(Config::new(&args("rustflame a.flame --format gif")).is_err());
+    assert!(Config::new(&args("rustflame a.flame --quality")).is_err());
+    assert!(Config::new(&args("rustflame a.flame --background 2,0,0")).is_err());
+    for gamma in &["0", "-1", "inf", "NaN"] {
+        let line = format!("rustflame a.flame --gamma {}", gamma);
+        assert!(Config::new(&args(&line)).is_err(), "{}", gamma);
+    }
+    let tiled = Config::new(&args("rustflame a.flame --tile-rows 64")).unwrap();
+    assert_eq!(tiled.tile_rows, Some(64));
+    assert!(Config::new(&args("rustflame a.flame --tile-rows 0")).is_err());
+    assert!(Config::new(&args("rustflame a.flame --tile-rows 8 -o a.pfm")).is_err());
+    assert!(Config::new(&args("rustflame a.flame --tile-rows 8 -f pfm")).is_err());
+    //Two inputs can't share one output file.
+    assert!(Config::new(&args("rustflame a.flame b.flame -o out.png")).is_err());
+}
+
+#[test]
+fn threaded_renders_share_the_iterations() {
+    let mut renderer = flame_2d::Renderer::new(32, 32, 2.2, 0.75, three_affine_flame());
+    renderer.set_termination(flame_2d::Termination::Iterations(90_000));
+    renderer.set_seed([3, 1, 4, 1]);
+    let single = renderer.render_unthreaded();
+    assert!(renderer.render_threaded_as::<RGB<u8>>(1).buffer == single.buffer);
+    let threaded = renderer.render_threaded_as::<RGB<u8>>(3);
+    assert!(renderer.render_threaded_as::<RGB<u8>>(3).buffer == threaded.buffer);
+    assert!(threaded.buffer != single.buffer);
+    assert!(threaded.buffer.iter().any(|p| p.r > 0));
+}
+
+#[test]
+fn batches_render_every_flame_once() {
+    let dir = ::std::env::temp_dir().join(format!("rustflame-batch-{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("Flames.flame"), include_str!("../Flames.flame")).unwrap();
+    let line = format!(
+        "rustflame {0} {0}/missing.flame -b -o {0}/out/{{index}}-{{name}}.pfm -W 8 -H 8 -q 1 \
+         --jobs 2",
+        dir.display()
+    );
+    let config = Config::new(&args(&line)).unwrap();
+    assert_eq!(config.jobs(), 2);
+
+    let summary = batch::run(&config);
+    assert_eq!(
+        (summary.rendered(), summary.skipped(), summary.failed()),
+        (6, 0, 1)
+    );
+    assert_eq!(summary.entries[5].flame, "Spark");
+    assert!(dir.join("out/5-Spark.pfm").exists());
+    let mut report = Vec::new();
+    summary.write_report(&mut report).unwrap();
+    let report = String::from_utf8(report).unwrap();
+    assert_eq!(report.lines().count(), 8);
+    assert!(report.lines().last().unwrap().contains("\tfailed\t"));
+
+    //Everything that rendered is left alone the second time round.
+    let summary = batch::run(&config);
+    assert_eq!(
+        (summary.rendered(), summary.skipped(), summary.failed()),
+        (0, 6, 1)
+    );
+
+    //Flames named alike in different files would write over each other.
+    fs::copy(dir.join("Flames.flame"), dir.join("Again.flame")).unwrap();
+    let line = format!(
+        "rustflame {0} -b -o {0}/named/{{name}}.pfm -W 8 -H 8 -q 1",
+        dir.display()
+    );
+    let summary = batch::run(&Config::new(&args(&line)).unwrap());
+    assert_eq!(
+        (summary.rendered(), summary.skipped(), summary.failed()),
+        (6, 0, 6)
+    );
+    let collision = &summary.entries[6];
+    assert!(collision.input.ends_with("Flames.flame"));
+    assert_eq!(
+        collision.outcome,
+        batch::Outcome::Failed(format!(
+            "output collides with {} in {}",
+            collision.flame, summary.entries[0].input
+        ))
+    );
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(Config::new(&args("rustflame a.flame -b -o out.png")).is_err());
+    assert!(Config::new(&args("rustflame a.flame -b -o {name}.png -n Spark")).is_err());
+}
+
+#[test]
+fn empty_images_and_flames_fail_instead_of_panicking() {
+    let dir = ::std::env::temp_dir().join(format!("rustflame-empty-{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let xform = r#"<xform weight="1" color="0" linear="1" coefs="0.5 0 0 0.5 0 0"/>"#;
+    let genomes = [
+        (
+            "zero.flame",
+            format!(r#"<flame name="zero" size="0 0">{}</flame>"#, xform),
+        ),
+        (
+            "bare.flame",
+            String::from(r#"<flame name="bare" size="8 8"></flame>"#),
+        ),
+    ];
+    for (file, genome) in &genomes {
+        let path = dir.join(file);
+        fs::write(&path, genome).unwrap();
+        let line = format!(
+            "rustflame {} -o {}/out.pfm -q 1",
+            path.display(),
+            dir.display()
+        );
+        let e = ::run(Config::new(&args(&line)).unwrap()).unwrap_err();
+        assert!(e.to_string().contains(file), "{}", e);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(Config::new(&args("rustflame a.flame -W 0")).is_err());
+
+    let settings = flame_2d::RenderSettings::default();
+    let mut unweighted = three_affine_flame();
+    unweighted.weights = vec![0.0; 3];
+    assert_eq!(
+        flame_2d::Renderer::from_settings(&settings, unweighted).err(),
+        Some(flame_2d::RendererError::NoTransforms)
+    );
+    let mut renderer =
+        flame_2d::Renderer::new(8, 8, 2.2, 0.75, flame_2d::Flame::new(String::new()));
+    renderer.set_termination(flame_2d::Termination::Iterations(100));
+    renderer.render_unthreaded();
+}
+
+fn assert_same_genome(a: &genome::Genome, b: &genome::Genome) {
+    assert_eq!(a.flame.name(), b.flame.name());
+    assert_eq!(a.settings, b.settings);
+    assert_eq!(a.flame.background, b.flame.background);
+    assert_eq!(a.flame.weights, b.flame.weights);
+    for (x, y) in a.flame.colors.iter().zip(b.flame.colors.iter()) {
+        assert!((x.r - y.r).abs() < 1e-6 && (x.g - y.g).abs() < 1e-6 && (x.b - y.b).abs() < 1e-6);
+    }
+    assert_eq!(a.flame.transforms.len(), b.flame.transforms.len());
+    for (x, y) in a.flame.transforms.iter().zip(b.flame.transforms.iter()) {
+        assert_eq!(x.affine(), y.affine());
+        let (xs, ys) = (x.variations(), y.variations());
+        assert_eq!(xs.len(), ys.len());
+        for ((xw, xf), (yw, yf)) in xs.iter().zip(ys.iter()) {
+            assert_eq!((xw, xf.name()), (yw, yf.name()));
+            for param in xf.param_names() {
+                assert_eq!(xf.param(param), yf.param(param));
+            }
+        }
+    }
+}
+
+#[test]
+fn genomes_survive_a_trip_through_png() {
+    let collection = genome::parse(include_str!("../Flames.flame")).unwrap();
+    let written = genome::to_xml(&collection).unwrap();
+    let read = genome::parse(&written).unwrap();
+    assert_eq!(read.len(), 6);
+    for (a, b) in collection.iter().zip(read.iter()) {
+        assert_same_genome(a, b);
+    }
+
+    //A name outside ASCII needs an iTXt chunk.
+    let spark = &collection[5];
+    let mut flame = flame_2d::Flame::new(String::from("Étincelle ✦"));
+    flame.transforms = spark.flame.transforms.clone();
+    flame.colors = spark.flame.colors.clone();
+    flame.weights = spark.flame.weights.clone();
+    //So are the settings flam3 has no attribute for, to render the image again just as it was.
+    let renamed = genome::Genome {
+        flame,
+        settings: flame_2d::RenderSettings {
+            seed: Some(0x1234_5678_9abc),
+            time: Some(time::Duration::from_millis(12_500)),
+            precision: flame_2d::BinPrecision::Single,
+            ..spark.settings.clone()
+        },
+    };
+    let mut writer = PngBandWriter::<_, RGB<u8>>::new(Vec::new(), 2, 1).unwrap();
+    writer.write_rows(&[RGB { r: 0, g: 0, b: 0 }; 2]).unwrap();
+    let text = genome::to_xml(slice::from_ref(&renamed)).unwrap();
+    assert!(text.contains("rustflame_seed=\"20015998343868\""));
+    assert!(text.contains("rustflame_time=\"12.5\""));
+    assert!(text.contains("rustflame_precision=\"single\""));
+    writer.write_text(genome::PNG_KEYWORD, &text).unwrap();
+    let png = writer.finish().unwrap();
+    let read = &genome::from_png(&png).unwrap()[0];
+    assert_eq!(read.settings.seed, Some(0x1234_5678_9abc));
+    assert_eq!(
+        read.settings.time,
+        Some(time::Duration::from_millis(12_500))
+    );
+    assert_eq!(read.settings.precision, flame_2d::BinPrecision::Single);
+    assert_same_genome(read, &renamed);
+    for bad in &["rustflame_time=\"-1\"", "rustflame_precision=\"half\""] {
+        let text = text.replacen("<flame ", &format!("<flame {} ", bad), 1);
+        assert!(genome::parse(&text).is_err(), "{}", bad);
+    }
+
+    let mut unweighted = spark.clone();
+    unweighted.flame.weights.pop();
+    let text = genome::to_xml(slice::from_ref(&unweighted)).unwrap();
+    let read = &genome::parse(&text).unwrap()[0].flame;
+    assert_eq!(read.weights.last(), Some(&1.0));
+
+    let text = genome::to_xml(slice::from_ref(spark)).unwrap();
+    let mut tagged = output::add_png_text(&png, "Software", "rustflame").unwrap();
+    tagged = output::add_png_text(&tagged, genome::PNG_KEYWORD, &text).unwrap();
+    let texts = output::png_text(&tagged).unwrap();
+    assert_eq!(
+        texts[1],
+        (String::from("Software"), String::from("rustflame"))
+    );
+    //The first genome in the image wins.
+    assert_same_genome(&genome::from_png(&tagged).unwrap()[0], spark);
+
+    let mut plain = PngBandWriter::<_, RGB<u8>>::new(Vec::new(), 1, 1).unwrap();
+    plain.write_rows(&[RGB { r: 0, g: 0, b: 0 }]).unwrap();
+    let plain = plain.finish().unwrap();
+    match genome::from_png(&plain) {
+        Err(genome::GenomeError::NoFlames) => {}
+        _ => panic!("an image without a genome has no flames"),
+    }
+    let mut corrupt = tagged.clone();
+    corrupt[40] ^= 1;
+    assert!(output::png_text(&corrupt).is_err());
+    assert!(output::add_png_text(&png, "", "x").is_err());
+}
+
+#[test]
+fn genomes_survive_a_trip_through_json() {
+    let collection = genome::parse(include_str!("../Flames.flame")).unwrap();
+    let written = json::to_string(&collection).unwrap();
+    let read = json::from_str(&written).unwrap();
+    for (a, b) in collection.iter().zip(read.iter()) {
+        assert_same_genome(a, b);
+        assert_eq!(a.flame.colors, b.flame.colors);
+    }
+    assert_eq!(json::to_string(&read).unwrap(), written);
+
+    //Every kind of transform, and a variation with its parameters.
+    let mut blob = variations::create("blob").unwrap();
+    blob.set_param("blob_waves", 3.0);
+    let affine = three_affine_flame().transforms[0]
+        .affine()
+        .cloned()
+        .unwrap();
+    let mut flame = flame_2d::Flame::new(String::from("kinds"));
+    let white = ColorFRGB::new(1.0, 1.0, 1.0).unwrap();
+    flame.add_transform(Transform2d::Basic(affine, blob.clone()), white);
+    flame.add_transform(
+        Transform2d::Chaining(
+            Box::new(Transform2d::AffineOnly(affine)),
+            Box::new(Transform2d::Sum(affine, vec![(0.5, blob)])),
+        ),
+        white,
+    );
+    let kinds = vec![genome::Genome {
+        flame,
+        settings: flame_2d::RenderSettings::default(),
+    }];
+    let written = json::to_string(&kinds).unwrap();
+    assert!(written.contains("\"kind\": \"chain\""));
+    assert!(written.contains("\"blob_waves\": 3.0"));
+    let read = json::from_str(&written).unwrap();
+    assert_eq!(json::to_string(&read).unwrap(), written);
+    match read[0].flame.transforms[0] {
+        Transform2d::Basic(_, ref f) => assert_eq!(f.param("blob_waves"), Some(3.0)),
+        _ => panic!("a basic transform should stay basic"),
+    }
+
+    //Settings and weights can be left out.
+    let sparse = json::from_str(
+        "{\"schema\": 1, \"genomes\": [{\"flame\": {\"name\": \"x\", \"transforms\": [{\"color\": \
+         {\"r\": 1, \"g\": 0, \"b\": 0}, \"transform\": {\"kind\": \"sum\", \"affine\": {\"xx\": \
+         1, \"xy\": 0, \"yx\": 0, \"yy\": 1, \"cx\": 0, \"cy\": 0}, \"variations\": [{\"weight\": \
+         1, \"name\": \"curl\", \"params\": {\"curl_c1\": 3}}]}}]}, \"settings\": \
+         {\"width\": 64}}]}",
+    )
+    .unwrap();
+    assert_eq!(sparse[0].settings.width, 64);
+    assert_eq!(sparse[0].settings.height, 1080);
+    assert_eq!(sparse[0].flame.weights, [1.0]);
+    assert_eq!(sparse[0].flame.transforms[0].variation_weight("curl"), 1.0);
+
+    match json::from_str("{\"schema\": 2, \"genomes\": []}") {
+        Err(json::JsonError::UnsupportedSchema(2)) => {}
+        _ => panic!("a newer schema should be rejected"),
+    }
+    let unknown = written.replace("blob_waves", "blob_wobble");
+    assert!(json::from_str(&unknown).is_err());
+
+    //Anything the renderer couldn't use is turned away at the door.
+    let sparse = "{\"schema\": 1, \"genomes\": [{\"flame\": {\"name\": \"x\", \"transforms\": \
+                  [{\"color\": {\"r\": 1, \"g\": 0, \"b\": 0}, \"transform\": {\"kind\": \
+                  \"affine\", \"affine\": {\"xx\": 1, \"xy\": 0, \"yx\": 0, \"yy\": 1, \
+                  \"cx\": 0, \"cy\": 0}}}]}, \"settings\": {\"width\": 64}}]}";
+    assert!(json::from_str(sparse).is_ok());
+    for &(from, to, complaint) in &[
+        ("\"r\": 1", "\"r\": 1.5", "red"),
+        ("\"g\": 0", "\"g\": -0.5", "green"),
+        ("\"width\": 64", "\"width\": 0", "empty"),
+        ("\"width\": 64", "\"width\": 64, \"gamma\": 0", "gamma"),
+        ("\"width\": 64", "\"width\": 64, \"quality\": -1", "quality"),
+        ("\"cx\": 0", "\"cx\": 1e999", ""),
+    ] {
+        let bad = sparse.replace(from, to);
+        assert_ne!(bad, sparse);
+        match json::from_str(&bad) {
+            Err(e) => assert!(e.to_string().contains(complaint), "{}: {}", to, e),
+            Ok(_) => panic!("{} should be rejected", to),
+        }
+    }
+    let unknown = written.replace("\"blob\"", "\"blobby\"");
+    assert!(json::from_str(&unknown).is_err());
+    assert!(json::from_str("{\"genomes\": []}").is_err());
+}
+
+#[test]
+fn jwildfire_genomes_import_with_warnings() {
+    let import = genome::import(
+        "<jwf-flames>
+         <flame name=\"jwf\" version=\"JWildfire V7.50\" size=\"640 480\" cam_pitch=\"0\" \
+          cam_yaw=\"0\" cam_persp=\"0.2\" cam_dof=\"0\" mixer_mode=\"OFF\">
+           <layer name=\"hidden\" visible=\"0\">
+             <xform weight=\"1\" color=\"0\" linear3D=\"1\" coefs=\"1 0 0 1 0 0\"/>
+           </layer>
+           <layer name=\"main\" visible=\"1\" gradient_mix=\"1\">
+             <xform weight=\"0.25\" color=\"1\" linear3D=\"0.5\" spherical3D=\"0.5\" \
+              coefs=\"0.5 0 0 0.5 0 0\" chaos=\"1 1\" material=\"0\" post=\"1 0 0 1 0 0\"/>
+             <xform weight=\"0.75\" color=\"0\" julia3D=\"1\" julia3D_power=\"3\" \
+              post_curl=\"0.2\" curl=\"0.8\" curl_c1=\"0.3\" coefs=\"0.5 0 0 0.5 0.5 0\" \
+              chaos=\"1 0\" material=\"2\" opacity=\"0.5\"/>
+             <finalxform color=\"0\" linear3D=\"1\" coefs=\"1 0 0 1 0 0\"/>
+             <palette count=\"2\" format=\"RGB\">FF000000FF00</palette>
+           </layer>
+           <layer name=\"extra\">
+             <xform weight=\"1\" color=\"0\" linear3D=\"1\" coefs=\"1 0 0 1 0 0\"/>
+           </layer>
+         </flame>
+         </jwf-flames>",
+    )
+    .unwrap();
+    let flame = &import.genomes[0].flame;
+    assert_eq!(flame.transforms.len(), 2);
+    let hidden =
+        "<flame name=\"dark\"><layer visible=\"0\"><xform linear3D=\"1\"/></layer></flame>";
+    match genome::parse(hidden) {
+        Err(genome::GenomeError::NoVisibleLayer(name)) => assert_eq!(name, "dark"),
+        _ => panic!("a flame with every layer hidden should be rejected"),
+    }
+    assert_eq!(flame.weights, [0.25, 0.75]);
+    assert_eq!(flame.colors[1], ColorFRGB::new(1.0, 0.0, 0.0).unwrap());
+    assert_eq!(flame.transforms[0].variation_weight("spherical"), 0.5);
+    assert_eq!(flame.transforms[0].variation_weight("linear"), 0.5);
+    assert_eq!(flame.transforms[1].variation_weight("curl"), 0.8);
+
+    let unsupported =
+        |xform: Option<usize>, attribute: &str, value: &str| genome::WarningKind::Unsupported {
+            xform,
+            attribute: String::from(attribute),
+            value: String::from(value),
+        };
+    assert_eq!(
+        import.warnings[4].to_string(),
+        "flame 0: xform 1 uses julia3D, which isn't a known variation and is left out (of \
+         JWildfire's 3D variations, only linear3D and spherical3D are read)"
+    );
+    let kinds: Vec<genome::WarningKind> = import.warnings.into_iter().map(|w| w.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            genome::WarningKind::ExtraLayers(1),
+            unsupported(None, "cam_persp", "0.2"),
+            unsupported(None, "gradient_mix", "1"),
+            genome::WarningKind::FinalTransform,
+            genome::WarningKind::UnknownVariation {
+                xform: 1,
+                name: String::from("julia3D"),
+            },
+            genome::WarningKind::UnknownVariation {
+                xform: 1,
+                name: String::from("post_curl"),
+            },
+            unsupported(Some(1), "chaos", "1 0"),
+            unsupported(Some(1), "material", "2"),
+            unsupported(Some(1), "opacity", "0.5"),
+        ]
+    );
+}
+
+#[test]
+fn palettes_survive_every_gradient_format() {
+    let colors = (0..palette::PALETTE_SIZE)
+        .map(|i| ColorFRGB {
+            r: i as f32 / 255.0,
+            g: ((i * 7) % 256) as f32 / 255.0,
+            b: (255 - i) as f32 / 255.0,
+        })
+        .collect();
+    let original = Palette::new("ramp", colors);
+    let twice = [
+        original.clone(),
+        Palette::new("ramp2", original.colors.clone()),
+    ];
+
+    let ugr = palette::read_ugr(&palette::write_ugr(&twice)).unwrap();
+    let flam3 = palette::read_flam3(&palette::write_flam3(&twice)).unwrap();
+    assert_eq!(ugr, twice);
+    assert_eq!(flam3, twice);
+    assert_eq!(
+        palette::read_map(&palette::write_map(&original), "ramp").unwrap(),
+        original
+    );
+    let ggr = palette::read_ggr(&palette::write_ggr(&original)).unwrap();
+    assert_eq!(ggr.name, "ramp");
+    //GIMP keeps six decimals, which is well within a byte a channel.
+    let close = |a: f32, b: f32| (a - b).abs() < 0.5 / 255.0;
+    for (a, b) in ggr.colors.iter().zip(&original.colors) {
+        assert!(close(a.r, b.r) && close(a.g, b.g) && close(a.b, b.b));
+    }
+
+    //A sparse UltraFractal gradient blends between its stops.
+    let sparse = "two {\ngradient:\n title=\"Two Stops\" smooth=no\n index=0 color=255\n \
+                  index=399 color=16711680\n}\n";
+    let two = palette::read_ugr(sparse).unwrap();
+    assert_eq!(two[0].name, "Two Stops");
+    assert_eq!(two[0].colors.len(), palette::PALETTE_SIZE);
+    assert_eq!(
+        two[0].colors[0],
+        ColorFRGB {
+            r: 1.0,
+            g: 0.0,
+            b: 0.0
+        }
+    );
+    assert_eq!(
+        two[0].colors[255],
+        ColorFRGB {
+            r: 0.0,
+            g: 0.0,
+            b: 1.0
+        }
+    );
+    assert!((two[0].colors[128].r - 0.5).abs() < 0.01);
+
+    //GIMP's HSV segments go around the hue circle, here from red through green to blue.
+    let hsv = "GIMP Gradient\nName: Hues\n1\n0.0 0.5 1.0 1 0 0 1 0 0 1 1 0 1\n";
+    let hues = palette::read_ggr(hsv).unwrap();
+    let middle = hues.colors[128];
+    assert!(middle.g > 0.9 && middle.r < 0.1 && middle.b < 0.1);
+
+    match palette::read_map("0 0 0\n1 2\n", "bad") {
+        Err(palette::PaletteError::Syntax { line: 2, .. }) => {}
+        other => panic!("expected a syntax error on line 2, got {:?}", other),
+    }
+}
+
+#[test]
+fn palettes_from_images() {
+    let rgb = |r, g, b| ColorFRGB { r, g, b };
+    let ramp: Vec<ColorFRGB> = (0..16)
+        .map(|x| rgb(1.0 - x as f32 / 15.0, 0.0, x as f32 / 15.0))
+        .collect();
+    let image = Image::new(16, 1, ramp);
+    let line = extract::palette_from_image(
+        &image,
+        &Strategy::Path(vec![(0.0, 0.0), (15.0, 0.0)]),
+        "line",
+    )
+    .unwrap();
+    assert_eq!(line.colors.len(), 256);
+    assert_eq!(line.colors[0], rgb(1.0, 0.0, 0.0));
+    assert_eq!(line.colors[255], rgb(0.0, 0.0, 1.0));
+    //Doubling back halfway along the path ends the palette where it began.
+    let there_and_back = Strategy::Path(vec![(0.0, 0.0), (15.0, 0.0), (0.0, 0.0)]);
+    let path = extract::palette_from_image(&image, &there_and_back, "path").unwrap();
+    assert_eq!(path.colors[255], rgb(1.0, 0.0, 0.0));
+
+    //Half black, a quarter white and a quarter violet, which sorts last by hue.
+    let mut pixels = vec![rgb(0.0, 0.0, 0.0); 8];
+    pixels.extend(vec![rgb(1.0, 1.0, 1.0); 4]);
+    pixels.extend(vec![rgb(0.5, 0.0, 1.0); 4]);
+    let image = Image::new(4, 4, pixels);
+    let by_luminance =
+        extract::palette_from_image(&image, &Strategy::Sorted(SortKey::Luminance), "").unwrap();
+    assert_eq!(by_luminance.colors[0], rgb(0.0, 0.0, 0.0));
+    assert_eq!(by_luminance.colors[150], rgb(0.5, 0.0, 1.0));
+    assert_eq!(by_luminance.colors[255], rgb(1.0, 1.0, 1.0));
+    let by_hue = extract::palette_from_image(&image, &Strategy::Sorted(SortKey::Hue), "").unwrap();
+    assert_eq!(by_hue.colors[255], rgb(0.5, 0.0, 1.0));
+    assert!((by_hue.hsv()[255].h - 270.0).abs() < 0.01);
+
+    let clusters = extract::palette_from_image(&image, &Strategy::KMeans(3), "").unwrap();
+    assert_eq!(clusters.colors[0], rgb(0.0, 0.0, 0.0));
+    assert_eq!(clusters.colors[255], rgb(1.0, 1.0, 1.0));
+    //Black covers half the image, so holds until a quarter of the way, then blends into violet.
+    assert_eq!(clusters.colors[63], rgb(0.0, 0.0, 0.0));
+    assert!(clusters.colors[100].r > 0.0 && clusters.colors[100].g == 0.0);
+    assert_eq!(
+        extract::palette_from_image(&image, &Strategy::KMeans(3), "").unwrap(),
+        clusters
+    );
+
+    let mut transparent = image.clone();
+    transparent.alpha = vec![0.0; 16];
+    assert!(extract::palette_from_image(&transparent, &Strategy::KMeans(3), "").is_err());
+}
+
+#[test]
+fn palette_edits() {
+    let rgb = |r, g, b| ColorFRGB { r, g, b };
+    let near = |a: ColorFRGB, b: ColorFRGB| {
+        assert!(
+            (a.r - b.r).abs() < 1e-5 && (a.g - b.g).abs() < 1e-5 && (a.b - b.b).abs() < 1e-5,
+            "{:?} isn't {:?}",
+            a,
+            b
+        )
+    };
+    let red = rgb(1.0, 0.0, 0.0);
+    let blue = rgb(0.0, 0.0, 1.0);
+    let steps = Palette::new("steps", vec![red, red, blue, blue]);
+
+    let rotated = steps.rotate_hue(120.0);
+    near(rotated.colors[0], rgb(0.0, 1.0, 0.0));
+    near(rotated.colors[3], red);
+    near(steps.rotate_hue(-120.0).colors[0], blue);
+    assert_eq!(rotated.name, "steps");
+    near(steps.scale_saturation(0.0).colors[0], rgb(1.0, 1.0, 1.0));
+    near(steps.scale_saturation(0.5).colors[0], rgb(1.0, 0.5, 0.5));
+    near(steps.scale_value(0.5).colors[3], rgb(0.0, 0.0, 0.5));
+    near(steps.scale_value(4.0).colors[3], blue);
+    assert_eq!(steps.invert().colors[0], rgb(0.0, 1.0, 1.0));
+    assert_eq!(steps.invert().invert(), steps);
+
+    assert_eq!(steps.reverse().colors, vec![blue, blue, red, red]);
+    assert_eq!(steps.rotate(1).colors, vec![blue, red, red, blue]);
+    assert_eq!(steps.rotate(-1).colors, vec![red, blue, blue, red]);
+    assert_eq!(steps.rotate(5), steps.rotate(1));
+
+    let blurred = steps.blur(1);
+    near(blurred.colors[0], red);
+    near(blurred.colors[1], rgb(2.0 / 3.0, 0.0, 1.0 / 3.0));
+    near(blurred.colors[3], blue);
+    assert_eq!(steps.blur(0), steps);
+    let flat = steps.blur(usize::MAX);
+    assert!(flat.colors.iter().all(|&c| c == flat.colors[0]));
+    near(flat.colors[0], rgb(0.5, 0.0, 0.5));
+
+    let halfway = steps.interpolate(&Palette::new("blue", vec![blue, blue]), 0.5);
+    near(halfway.colors[0], rgb(0.5, 0.0, 0.5));
+    near(halfway.colors[3], blue);
+    assert_eq!(steps.interpolate(&steps.reverse(), 0.0), steps);
+    let empty = Palette::new("empty", Vec::new());
+    assert_eq!(empty.sample(0.5), rgb(0.0, 0.0, 0.0));
+    assert!(empty
+        .resample(3)
+        .colors
+        .iter()
+        .all(|&c| c == rgb(0.0, 0.0, 0.0)));
+    assert_eq!(steps.interpolate(&empty, 0.5), steps);
+    assert_eq!(empty.interpolate(&steps, 0.5), empty);
+
+    let posterized = Palette::new("", vec![rgb(0.2, 0.4, 0.9)]).posterize(3);
+    assert_eq!(posterized.colors[0], rgb(0.0, 0.5, 1.0));
+}
+
+#[test]
+fn perceptual_and_linear_color_spaces() {
+    let close =
+        |a: f32, b: f32, tolerance: f32| assert!((a - b).abs() < tolerance, "{} isn't {}", a, b);
+    close(srgb_to_linear(0.5), 0.214_041, 1e-5);
+    close(linear_to_srgb(0.214_041), 0.5, 1e-5);
+    close(srgb_to_linear(0.02), 0.02 / 12.92, 1e-7);
+
+    let white = ColorFRGB::new(1.0, 1.0, 1.0).unwrap();
+    let xyz = ColorXYZ::from(white);
+    close(xyz.x, 0.950_47, 1e-4);
+    close(xyz.y, 1.0, 1e-4);
+    close(xyz.z, 1.088_83, 1e-4);
+    let lab = ColorLab::from(white);
+    close(lab.l, 100.0, 1e-2);
+    close(lab.a, 0.0, 1e-2);
+    close(lab.b, 0.0, 1e-2);
+
+    let red = ColorFRGB::new(1.0, 0.0, 0.0).unwrap();
+    let lab = ColorLab::from(red);
+    close(lab.l, 53.24, 0.01);
+    close(lab.a, 80.09, 0.01);
+    close(lab.b, 67.20, 0.01);
+    let lch = ColorLCh::from(red);
+    close(lch.c, 104.55, 0.01);
+    close(lch.h, 40.0, 0.01);
+    let oklab = ColorOKLab::from(red);
+    close(oklab.l, 0.627_96, 1e-4);
+    close(oklab.a, 0.224_86, 1e-4);
+    close(oklab.b, 0.125_85, 1e-4);
+    let oklch = ColorOKLCh::from(ColorFRGB::new(0.0, 0.0, 1.0).unwrap());
+    close(oklch.h, 264.05, 0.01);
+
+    for &(r, g, b) in &[
+        (0.2, 0.4, 0.9),
+        (1.0, 0.0, 0.5),
+        (0.01, 0.02, 0.0),
+        (0.5, 0.5, 0.5),
+    ] {
+        let c = ColorFRGB::new(r, g, b).unwrap();
+        let through = [
+            ColorFRGB::from_linear(c.to_linear()),
+            ColorFRGB::from(ColorXYZ::from(c)),
+            ColorFRGB::from(ColorLab::from(c)),
+            ColorFRGB::from(ColorLCh::from(c)),
+            ColorFRGB::from(ColorOKLab::from(c)),
+            ColorFRGB::from(ColorOKLCh::from(c)),
+        ];
+        for back in &through {
+            close(back.r, r, 1e-4);
+            close(back.g, g, 1e-4);
+            close(back.b, b, 1e-4);
+        }
+    }
+
+    //Too saturated for sRGB, so clipped into it.
+    let beyond = ColorFRGB::from(ColorOKLCh {
+        l: 0.7,
+        c: 0.4,
+        h: 150.0,
+    });
+    assert!(ColorFRGB::new(beyond.r, beyond.g, beyond.b).is_some());
+}
+
+/// A channel from anywhere in f32: the edges of the ranges and a hair either side of them, values
+/// in and around the ranges, and any bit pattern at all, NaNs and infinities included.
+fn any_channel(rng: &mut XorShiftRng) -> f32 {
+    const EDGES: [f32; 14] = [
+        0.0,
+        -0.0,
+        1.0,
+        360.0,
+        -1e-7,
+        1.000_000_1,
+        359.999_97,
+        360.000_03,
+        1e-45,
+        f32::MAX,
+        f32::MIN,
+        f32::INFINITY,
+        f32::NEG_INFINITY,
+        f32::NAN,
+    ];
+    match rng.gen_range(0, 4) {
+        0 => EDGES[rng.gen_range(0, EDGES.len())],
+        1 => rng.gen_range(0.0, 1.0),
+        2 => rng.gen_range(-720.0, 720.0),
+        _ => f32::from_bits(rng.gen()),
+    }
+}
+
+fn is_valid_rgb(c: ColorFRGB) -> bool {
+    ColorFRGB::try_from((c.r, c.g, c.b)).is_ok()
+}
+
+fn is_valid_hsv(c: ColorFHSV) -> bool {
+    ColorFHSV::try_from((c.h, c.s, c.v)).is_ok() && c.h < 360.0
+}
+
+#[test]
+fn color_constructors_hold_over_every_input() {
+    let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+    let unit = |v: f32| v >= 0.0 && v <= 1.0;
+    for _ in 0..100_000 {
+        let (a, b, c) = (
+            any_channel(&mut rng),
+            any_channel(&mut rng),
+            any_channel(&mut rng),
+        );
+
+        let rgb = ColorFRGB::try_from((a, b, c));
+        assert_eq!(rgb.is_ok(), unit(a) && unit(b) && unit(c));
+        assert_eq!(ColorFRGB::new(a, b, c), rgb.ok());
+        if let Err(e) = rgb {
+            //The first channel out of range is the one reported.
+            let (channel, value) = if !unit(a) {
+                ("red", a)
+            } else if !unit(b) {
+                ("green", b)
+            } else {
+                ("blue", c)
+            };
+            assert_eq!((e.channel, e.value.to_bits()), (channel, value.to_bits()));
+            assert!(e.to_string().starts_with(channel));
+        }
+        let clamped = ColorFRGB::clamped(a, b, c);
+        assert!(is_valid_rgb(clamped));
+        if let Ok(rgb) = rgb {
+            assert_eq!(clamped, rgb);
+        }
+
+        let hsv = ColorFHSV::try_from((a, b, c));
+        assert_eq!(hsv.is_ok(), a >= 0.0 && a <= 360.0 && unit(b) && unit(c));
+        assert_eq!(ColorFHSV::new(a, b, c), hsv.ok());
+        let wrapped = ColorFHSV::wrapped(a, b, c);
+        assert!(is_valid_hsv(wrapped));
+        if unit(b) && unit(c) {
+            assert_eq!((wrapped.s, wrapped.v), (b, c));
+        }
+        if a.abs() < 1e4 {
+            //The same hue, whole turns apart.
+            let turns = (a - wrapped.h) / 360.0;
+            assert!(
+                (turns - turns.round()).abs() < 1e-4,
+                "{} wrapped to {}",
+                a,
+                wrapped.h
+            );
+        }
+    }
+
+    //JSON gets the same checks as try_from.
+    let hsv: ColorFHSV = serde_json::from_str("{\"h\": 270, \"s\": 1, \"v\": 0.5}").unwrap();
+    assert_eq!(hsv, ColorFHSV::new(270.0, 1.0, 0.5).unwrap());
+    for bad in &[
+        "{\"h\": 400, \"s\": 1, \"v\": 0.5}",
+        "{\"h\": 270, \"s\": -1, \"v\": 0.5}",
+        "{\"h\": 270, \"s\": 1, \"v\": 2}",
+    ] {
+        assert!(serde_json::from_str::<ColorFHSV>(bad).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn color_conversions_never_panic() {
+    let mut rng = XorShiftRng::from_seed([5, 6, 7, 8]);
+    for _ in 0..100_000 {
+        let (a, b, c) = (
+            any_channel(&mut rng),
+            any_channel(&mut rng),
+            any_channel(&mut rng),
+        );
+        //The fields are public, so conversions can be handed anything at all.
+        let rgb = ColorFRGB { r: a, g: b, b: c };
+        let hsv = ColorFHSV { h: a, s: b, v: c };
+        assert!(is_valid_hsv(ColorFHSV::from(rgb)), "{:?}", rgb);
+        assert!(is_valid_rgb(ColorFRGB::from(hsv)), "{:?}", hsv);
+        assert!(is_valid_rgb(ColorFRGB::from(ColorXYZ { x: a, y: b, z: c })));
+        assert!(is_valid_rgb(ColorFRGB::from(ColorLab { l: a, a: b, b: c })));
+        assert!(is_valid_rgb(ColorFRGB::from(ColorLCh { l: a, c: b, h: c })));
+        assert!(is_valid_rgb(ColorFRGB::from(ColorOKLab {
+            l: a,
+            a: b,
+            b: c
+        })));
+        assert!(is_valid_rgb(ColorFRGB::from(ColorOKLCh {
+            l: a,
+            c: b,
+            h: c
+        })));
+
+        if is_valid_rgb(rgb) {
+            let back = ColorFRGB::from(ColorFHSV::from(rgb));
+            for &(x, y) in &[(back.r, rgb.r), (back.g, rgb.g), (back.b, rgb.b)] {
+                assert!((x - y).abs() < 1e-5, "{:?} came back as {:?}", rgb, back);
+            }
+        }
+    }
+
+    //The cases that used to panic.
+    let red = ColorFRGB::new(1.0, 0.0, 0.0).unwrap();
+    assert_eq!(
+        ColorFRGB::from(ColorFHSV::new(360.0, 1.0, 1.0).unwrap()),
+        red
+    );
+    assert_eq!(ColorFRGB::from(ColorFHSV::wrapped(-1e-7, 1.0, 1.0)), red);
+    let rose = ColorFHSV::from(ColorFRGB::new(1.0, 0.0, 0.5).unwrap());
+    assert!((rose.h - 330.0).abs() < 1e-3);
+}
@@ -0,0 +1,463 @@
+//! The registry of named variations a transform can be built from, with the formulas and
+//! parameter names flam3 uses.
+
+use flame_2d::Point;
+use flame_2d::TransformFunction;
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt;
+
+//Keeps the singular points of the formulas finite.
+const EPS: f64 = 1e-10;
+
+struct Spec {
+    name: &'static str,
+    /// Parameter names and their defaults.
+    params: &'static [(&'static str, f64)],
+    function: fn(&Point, &[f64]) -> Point,
+}
+
+const SPECS: &[Spec] = &[
+    Spec {
+        name: "linear",
+        params: &[],
+        function: linear,
+    },
+    Spec {
+        name: "sinusoidal",
+        params: &[],
+        function: sinusoidal,
+    },
+    Spec {
+        name: "spherical",
+        params: &[],
+        function: spherical,
+    },
+    Spec {
+        name: "swirl",
+        params: &[],
+        function: swirl,
+    },
+    Spec {
+        name: "horseshoe",
+        params: &[],
+        function: horseshoe,
+    },
+    Spec {
+        name: "polar",
+        params: &[],
+        function: polar,
+    },
+    Spec {
+        name: "handkerchief",
+        params: &[],
+        function: handkerchief,
+    },
+    Spec {
+        name: "heart",
+        params: &[],
+        function: heart,
+    },
+    Spec {
+        name: "disc",
+        params: &[],
+        function: disc,
+    },
+    Spec {
+        name: "spiral",
+        params: &[],
+        function: spiral,
+    },
+    Spec {
+        name: "hyperbolic",
+        params: &[],
+        function: hyperbolic,
+    },
+    Spec {
+        name: "diamond",
+        params: &[],
+        function: diamond,
+    },
+    Spec {
+        name: "ex",
+        params: &[],
+        function: ex,
+    },
+    Spec {
+        name: "bent",
+        params: &[],
+        function: bent,
+    },
+    Spec {
+        name: "fisheye",
+        params: &[],
+        function: fisheye,
+    },
+    Spec {
+        name: "exponential",
+        params: &[],
+        function: exponential,
+    },
+    Spec {
+        name: "power",
+        params: &[],
+        function: power,
+    },
+    Spec {
+        name: "cosine",
+        params: &[],
+        function: cosine,
+    },
+    Spec {
+        name: "bubble",
+        params: &[],
+        function: bubble,
+    },
+    Spec {
+        name: "cylinder",
+        params: &[],
+        function: cylinder,
+    },
+    Spec {
+        name: "tangent",
+        params: &[],
+        function: tangent,
+    },
+    Spec {
+        name: "cross",
+        params: &[],
+        function: cross,
+    },
+    Spec {
+        name: "blob",
+        params: &[("blob_high", 1.0), ("blob_low", 0.5), ("blob_waves", 1.0)],
+        function: blob,
+    },
+    Spec {
+        name: "pdj",
+        params: &[
+            ("pdj_a", 1.0),
+            ("pdj_b", 1.0),
+            ("pdj_c", 1.0),
+            ("pdj_d", 1.0),
+        ],
+        function: pdj,
+    },
+    Spec {
+        name: "fan2",
+        params: &[("fan2_x", 0.5), ("fan2_y", 0.0)],
+        function: fan2,
+    },
+    Spec {
+        name: "rings2",
+        params: &[("rings2_val", 0.5)],
+        function: rings2,
+    },
+    Spec {
+        name: "perspective",
+        params: &[("perspective_angle", 0.0), ("perspective_dist", 1.0)],
+        function: perspective,
+    },
+    Spec {
+        name: "curl",
+        params: &[("curl_c1", 1.0), ("curl_c2", 0.0)],
+        function: curl,
+    },
+    Spec {
+        name: "ngon",
+        params: &[
+            ("ngon_power", 3.0),
+            ("ngon_sides", 5.0),
+            ("ngon_corners", 2.0),
+            ("ngon_circle", 1.0),
+        ],
+        function: ngon,
+    },
+];
+
+/// The names of every registered variation.
+pub fn names() -> Vec<&'static str> {
+    SPECS.iter().map(|spec| spec.name).collect()
+}
+
+/// The parameter names of a registered variation, or `None` if there is no such variation.
+pub fn param_names(variation: &str) -> Option<Vec<&'static str>> {
+    spec(variation).map(|spec| spec.params.iter().map(|&(name, _)| name).collect())
+}
+
+/// Creates a registered variation with its parameters at their defaults.
+pub fn create(name: &str) -> Result<Box<TransformFunction>, VariationError> {
+    let spec = spec(name).ok_or_else(|| VariationError::UnknownVariation(String::from(name)))?;
+    Ok(Box::new(Variation {
+        spec,
+        params: spec.params.iter().map(|&(_, default)| default).collect(),
+    }))
+}
+
+fn spec(name: &str) -> Option<&'static Spec> {
+    SPECS.iter().find(|spec| spec.name == name)
+}
+
+/// Why a variation edit couldn't be made.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VariationError {
+    UnknownVariation(String),
+    UnknownParam {
+        variation: String,
+        param: String,
+    },
+    /// The transform doesn't use the variation.
+    NotPresent(String),
+    /// The transform is a chain of transforms, which has no variations of its own.
+    Chained,
+}
+
+impl fmt::Display for VariationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VariationError::UnknownVariation(name) => write!(f, "no variation is named {}", name),
+            VariationError::UnknownParam { variation, param } => {
+                write!(f, "variation {} has no parameter {}", variation, param)
+            }
+            VariationError::NotPresent(name) => {
+                write!(f, "the transform doesn't use variation {}", name)
+            }
+            VariationError::Chained => write!(f, "a chained transform has no variations"),
+        }
+    }
+}
+
+impl Error for VariationError {}
+
+/// A registered variation and its current parameters.
+#[derive(Clone)]
+struct Variation {
+    spec: &'static Spec,
+    params: Vec<f64>,
+}
+
+impl TransformFunction for Variation {
+    fn transform(&self, input: &Point) -> Point {
+        (self.spec.function)(input, &self.params)
+    }
+    fn box_clone(&self) -> Box<TransformFunction> {
+        Box::new(self.clone())
+    }
+    fn name(&self) -> &'static str {
+        self.spec.name
+    }
+    fn param_names(&self) -> Vec<&'static str> {
+        self.spec.params.iter().map(|&(name, _)| name).collect()
+    }
+    fn param(&self, name: &str) -> Option<f64> {
+        self.param_index(name).map(|i| self.params[i])
+    }
+    fn set_param(&mut self, name: &str, value: f64) -> bool {
+        match self.param_index(name) {
+            Some(i) => {
+                self.params[i] = value;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Variation {
+    fn param_index(&self, name: &str) -> Option<usize> {
+        self.spec.params.iter().position(|&(n, _)| n == name)
+    }
+}
+
+fn point(x: f64, y: f64) -> Point {
+    Point { x, y }
+}
+
+fn sumsq(p: &Point) -> f64 {
+    p.x * p.x + p.y * p.y
+}
+
+/// flam3 measures this angle from the y axis.
+fn atan_xy(p: &Point) -> f64 {
+    p.x.atan2(p.y)
+}
+
+fn linear(p: &Point, _: &[f64]) -> Point {
+    *p
+}
+
+fn sinusoidal(p: &Point, _: &[f64]) -> Point {
+    point(p.x.sin(), p.y.sin())
+}
+
+fn spherical(p: &Point, _: &[f64]) -> Point {
+    let r2 = 1.0 / (sumsq(p) + EPS);
+    point(p.x * r2, p.y * r2)
+}
+
+fn swirl(p: &Point, _: &[f64]) -> Point {
+    let (s, c) = sumsq(p).sin_cos();
+    point(p.x * s - p.y * c, p.x * c + p.y * s)
+}
+
+fn horseshoe(p: &Point, _: &[f64]) -> Point {
+    let r = 1.0 / (sumsq(p).sqrt() + EPS);
+    point((p.x - p.y) * (p.x + p.y) * r, 2.0 * p.x * p.y * r)
+}
+
+fn polar(p: &Point, _: &[f64]) -> Point {
+    point(atan_xy(p) / PI, sumsq(p).sqrt() - 1.0)
+}
+
+fn handkerchief(p: &Point, _: &[f64]) -> Point {
+    let a = atan_xy(p);
+    let r = sumsq(p).sqrt();
+    point(r * (a + r).sin(), r * (a - r).cos())
+}
+
+fn heart(p: &Point, _: &[f64]) -> Point {
+    let r = sumsq(p).sqrt();
+    let (s, c) = (atan_xy(p) * r).sin_cos();
+    point(r * s, -r * c)
+}
+
+fn disc(p: &Point, _: &[f64]) -> Point {
+    let a = atan_xy(p) / PI;
+    let (s, c) = (PI * sumsq(p).sqrt()).sin_cos();
+    point(s * a, c * a)
+}
+
+fn spiral(p: &Point, _: &[f64]) -> Point {
+    let r = sumsq(p).sqrt() + EPS;
+    let (sr, cr) = r.sin_cos();
+    point((p.y / r + sr) / r, (p.x / r - cr) / r)
+}
+
+fn hyperbolic(p: &Point, _: &[f64]) -> Point {
+    let r = sumsq(p).sqrt() + EPS;
+    point(p.x / r / r, p.y)
+}
+
+fn diamond(p: &Point, _: &[f64]) -> Point {
+    let r = sumsq(p).sqrt() + EPS;
+    let (sr, cr) = r.sin_cos();
+    point(p.x / r * cr, p.y / r * sr)
+}
+
+fn ex(p: &Point, _: &[f64]) -> Point {
+    let a = atan_xy(p);
+    let r = sumsq(p).sqrt();
+    let m0 = (a + r).sin().powi(3);
+    let m1 = (a - r).cos().powi(3);
+    point(r * (m0 + m1), r * (m0 - m1))
+}
+
+fn bent(p: &Point, _: &[f64]) -> Point {
+    let x = if p.x < 0.0 { p.x * 2.0 } else { p.x };
+    let y = if p.y < 0.0 { p.y / 2.0 } else { p.y };
+    point(x, y)
+}
+
+fn fisheye(p: &Point, _: &[f64]) -> Point {
+    let r = 2.0 / (sumsq(p).sqrt() + 1.0);
+    point(r * p.y, r * p.x)
+}
+
+fn exponential(p: &Point, _: &[f64]) -> Point {
+    let e = (p.x - 1.0).exp();
+    let (s, c) = (PI * p.y).sin_cos();
+    point(e * c, e * s)
+}
+
+fn power(p: &Point, _: &[f64]) -> Point {
+    let r = sumsq(p).sqrt() + EPS;
+    let (sina, cosa) = (p.x / r, p.y / r);
+    let scale = r.powf(sina);
+    point(scale * cosa, scale * sina)
+}
+
+fn cosine(p: &Point, _: &[f64]) -> Point {
+    let (s, c) = (p.x * PI).sin_cos();
+    point(c * p.y.cosh(), -s * p.y.sinh())
+}
+
+fn bubble(p: &Point, _: &[f64]) -> Point {
+    let r = 4.0 / (sumsq(p) + 4.0);
+    point(r * p.x, r * p.y)
+}
+
+fn cylinder(p: &Point, _: &[f64]) -> Point {
+    point(p.x.sin(), p.y)
+}
+
+fn tangent(p: &Point, _: &[f64]) -> Point {
+    point(p.x.sin() / p.y.cos(), p.y.tan())
+}
+
+fn cross(p: &Point, _: &[f64]) -> Point {
+    let s = p.x * p.x - p.y * p.y;
+    let r = (1.0 / (s * s + EPS)).sqrt();
+    point(p.x * r, p.y * r)
+}
+
+fn blob(p: &Point, params: &[f64]) -> Point {
+    let (high, low, waves) = (params[0], params[1], params[2]);
+    let a = atan_xy(p);
+    let scale = low + (high - low) * (0.5 + 0.5 * (waves * a).sin());
+    point(scale * p.x, scale * p.y)
+}
+
+fn pdj(p: &Point, params: &[f64]) -> Point {
+    let (a, b, c, d) = (params[0], params[1], params[2], params[3]);
+    point(
+        (a * p.y).sin() - (b * p.x).cos(),
+        (c * p.x).sin() - (d * p.y).cos(),
+    )
+}
+
+fn fan2(p: &Point, params: &[f64]) -> Point {
+    let (fx, fy) = (params[0], params[1]);
+    let dx = PI * (fx * fx + EPS);
+    let half = 0.5 * dx;
+    let mut a = atan_xy(p);
+    let t = a + fy - dx * ((a + fy) / dx).trunc();
+    a = if t > half { a - half } else { a + half };
+    let r = sumsq(p).sqrt();
+    point(r * a.sin(), r * a.cos())
+}
+
+fn rings2(p: &Point, params: &[f64]) -> Point {
+    let dx = params[0] * params[0] + EPS;
+    let mut r = sumsq(p).sqrt();
+    let a = atan_xy(p);
+    r += -2.0 * dx * ((r + dx) / (2.0 * dx)).trunc() + r * (1.0 - dx);
+    point(r * a.sin(), r * a.cos())
+}
+
+fn perspective(p: &Point, params: &[f64]) -> Point {
+    let (angle, dist) = (params[0], params[1]);
+    let (vsin, vcos) = (angle * PI / 2.0).sin_cos();
+    let t = 1.0 / (dist - p.y * vsin + EPS);
+    point(dist * p.x * t, dist * vcos * p.y * t)
+}
+
+fn curl(p: &Point, params: &[f64]) -> Point {
+    let (c1, c2) = (params[0], params[1]);
+    let re = 1.0 + c1 * p.x + c2 * (p.x * p.x - p.y * p.y);
+    let im = c1 * p.y + 2.0 * c2 * p.x * p.y;
+    let r = 1.0 / (re * re + im * im + EPS);
+    point((p.x * re + p.y * im) * r, (p.y * re - p.x * im) * r)
+}
+
+fn ngon(p: &Point, params: &[f64]) -> Point {
+    let (power, sides, corners, circle) = (params[0], params[1], params[2], params[3]);
+    let r_factor = sumsq(p).powf(power / 2.0);
+    let b = 2.0 * PI / sides;
+    let theta = p.y.atan2(p.x);
+    let mut phi = theta - b * (theta / b).floor();
+    if phi > b / 2.0 {
+        phi -= b;
+    }
+    let amp = (corners * (1.0 / (phi.cos() + EPS) - 1.0) + circle) / (r_factor + EPS);
+    point(p.x * amp, p.y * amp)
+}
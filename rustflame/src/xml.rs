@@ -0,0 +1,286 @@
+//! Just enough XML to read and write flame genomes: elements, attributes and text. Doctypes and
+//! CDATA sections aren't supported.
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Element {
+    pub name: String,
+    /// In document order.
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    /// The element's own text, with entities decoded, all runs of it joined.
+    pub text: String,
+}
+
+impl Element {
+    pub fn new(name: &str) -> Element {
+        Element {
+            name: String::from(name),
+            ..Element::default()
+        }
+    }
+
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The children with the given name.
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    pub fn set_attr<V: ToString>(&mut self, name: &str, value: V) {
+        self.attributes
+            .push((String::from(name), value.to_string()));
+    }
+
+    /// Writes the element as a document, children indented under their parents.
+    pub fn write(&self) -> String {
+        let mut out = String::new();
+        self.write_into(&mut out, 0);
+        out
+    }
+
+    fn write_into(&self, out: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth);
+        out.push_str(&indent);
+        out.push('<');
+        out.push_str(&self.name);
+        for (name, value) in &self.attributes {
+            out.push_str(&format!(" {}=\"{}\"", name, escape(value, true)));
+        }
+        if self.children.is_empty() && self.text.is_empty() {
+            out.push_str("/>\n");
+            return;
+        }
+        out.push('>');
+        out.push_str(&escape(&self.text, false));
+        if !self.children.is_empty() {
+            out.push('\n');
+            for child in &self.children {
+                child.write_into(out, depth + 1);
+            }
+            out.push_str(&indent);
+        }
+        out.push_str(&format!("</{}>\n", self.name));
+    }
+}
+
+/// Escapes markup characters, and in attribute values the whitespace a reader would otherwise
+/// normalize.
+fn escape(text: &str, attribute: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' if attribute => out.push_str("&quot;"),
+            '\n' | '\r' | '\t' if attribute => out.push_str(&format!("&#{};", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Where and why a document couldn't be read.
+#[derive(Clone, Debug, PartialEq)]
+pub struct XmlError {
+    /// 1-based.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for XmlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for XmlError {}
+
+/// Reads a document's root element. A leading byte order mark, the XML declaration, comments
+/// and processing instructions are skipped.
+pub fn parse(text: &str) -> Result<Element, XmlError> {
+    let mut parser = Parser {
+        text: text.trim_start_matches('\u{feff}'),
+        pos: 0,
+    };
+    parser.skip_misc()?;
+    if !parser.rest().starts_with('<') {
+        return Err(parser.error("expected a root element"));
+    }
+    let root = parser.element()?;
+    parser.skip_misc()?;
+    if !parser.rest().is_empty() {
+        return Err(parser.error("unexpected content after the root element"));
+    }
+    Ok(root)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn error(&self, message: &str) -> XmlError {
+        XmlError {
+            line: self.text[..self.pos].matches('\n').count() + 1,
+            message: String::from(message),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Skips past `end`, which must come before the end of the document.
+    fn skip_past(&mut self, end: &str, what: &str) -> Result<&'a str, XmlError> {
+        match self.rest().find(end) {
+            Some(i) => {
+                let skipped = &self.rest()[..i];
+                self.pos += i + end.len();
+                Ok(skipped)
+            }
+            None => Err(self.error(&format!("unterminated {}", what))),
+        }
+    }
+
+    /// Skips whitespace, comments and processing instructions.
+    fn skip_misc(&mut self) -> Result<(), XmlError> {
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("<!--") {
+                self.skip_past("-->", "comment")?;
+            } else if self.rest().starts_with("<?") {
+                self.skip_past("?>", "processing instruction")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<&'a str, XmlError> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || "_-.:".contains(c)))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("expected a name"));
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), XmlError> {
+        if self.rest().starts_with(s) {
+            self.pos += s.len();
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", s)))
+        }
+    }
+
+    /// Reads an element, starting at its '<'.
+    fn element(&mut self) -> Result<Element, XmlError> {
+        self.expect("<")?;
+        let mut element = Element::new(self.name()?);
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.pos += 2;
+                return Ok(element);
+            }
+            if self.rest().starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+            let name = self.name()?;
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+            let quote = match self.rest().chars().next() {
+                Some(q) if q == '"' || q == '\'' => q,
+                _ => return Err(self.error("expected a quoted attribute value")),
+            };
+            self.pos += 1;
+            let raw = self.skip_past(&quote.to_string(), "attribute value")?;
+            let value = self.unescape(raw)?;
+            element.attributes.push((String::from(name), value));
+        }
+        loop {
+            let rest = self.rest();
+            let text_len = rest.find('<').unwrap_or(rest.len());
+            if text_len > 0 {
+                let text = self.unescape(&rest[..text_len])?;
+                element.text.push_str(&text);
+                self.pos += text_len;
+            }
+            let rest = self.rest();
+            if rest.is_empty() {
+                return Err(self.error(&format!("<{}> is never closed", element.name)));
+            } else if rest.starts_with("</") {
+                self.pos += 2;
+                let start = self.pos;
+                let name = self.name()?;
+                if name != element.name {
+                    self.pos = start;
+                    return Err(self.error(&format!("</{}> closes <{}>", name, element.name)));
+                }
+                self.skip_whitespace();
+                self.expect(">")?;
+                return Ok(element);
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->", "comment")?;
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>", "processing instruction")?;
+            } else {
+                element.children.push(self.element()?);
+            }
+        }
+    }
+
+    fn unescape(&self, raw: &str) -> Result<String, XmlError> {
+        let mut out = String::with_capacity(raw.len());
+        let mut rest = raw;
+        while let Some(i) = rest.find('&') {
+            out.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+            let end = rest
+                .find(';')
+                .ok_or_else(|| self.error("unterminated entity"))?;
+            let decoded = match &rest[..end] {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                entity if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16)
+                    .ok()
+                    .and_then(::std::char::from_u32),
+                entity if entity.starts_with('#') => {
+                    entity[1..].parse().ok().and_then(::std::char::from_u32)
+                }
+                _ => None,
+            };
+            match decoded {
+                Some(c) => out.push(c),
+                None => return Err(self.error(&format!("unknown entity &{};", &rest[..end]))),
+            }
+            rest = &rest[end + 1..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+}